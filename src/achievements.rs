@@ -0,0 +1,333 @@
+// milestones that are earned rather than paid for, kept apart from quests
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    events::ExecutionEvent,
+    CoinManager,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl Rarity {
+    pub fn description(&self) -> &str {
+        match self {
+            Rarity::Common => "⚪ Common",
+            Rarity::Uncommon => "🟢 Uncommon",
+            Rarity::Rare => "🔵 Rare",
+            Rarity::Epic => "🟣 Epic",
+            Rarity::Legendary => "🟡 Legendary",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Milestone {
+    ProgramsRun(u64),
+    FunctionsDefined(u64),
+    VariablesCreated(u64),
+    RecoveredFromErrors(u64), // a successful run right after a failed one
+    OutOfCoins,               // every balance at zero or below after a run
+}
+
+impl Milestone {
+    // (current, target), capped at the target
+    fn progress(&self, stats: &AchievementStats) -> (u64, u64) {
+        let (current, target) = match self {
+            Milestone::ProgramsRun(target) => (stats.programs_run, *target),
+            Milestone::FunctionsDefined(target) => (stats.functions_defined, *target),
+            Milestone::VariablesCreated(target) => (stats.variables_created, *target),
+            Milestone::RecoveredFromErrors(target) => (stats.recoveries, *target),
+            Milestone::OutOfCoins => (stats.went_broke as u64, 1),
+        };
+        (current.min(target), target)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Achievement {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub rarity: Rarity,
+    pub milestone: Milestone,
+}
+
+impl Achievement {
+    pub fn new(id: &str, title: &str, description: &str, rarity: Rarity, milestone: Milestone) -> Self {
+        Self {
+            id: id.to_string(),
+            title: title.to_string(),
+            description: description.to_string(),
+            rarity,
+            milestone,
+        }
+    }
+}
+
+// running totals, kept here because the execution history is trimmed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AchievementStats {
+    pub programs_run: u64,
+    pub functions_defined: u64,
+    pub variables_created: u64,
+    pub runtime_errors: u64,
+    pub recoveries: u64,
+    pub went_broke: bool,
+    last_run_failed: bool,
+}
+
+pub fn default_achievements() -> Vec<Achievement> {
+    vec![
+        Achievement::new("first_run", "First Steps", "Run your first program", Rarity::Common, Milestone::ProgramsRun(1)),
+        Achievement::new("centurion", "Centurion", "Run 100 programs", Rarity::Rare, Milestone::ProgramsRun(100)),
+        Achievement::new("marathon", "Marathon", "Run 1000 programs", Rarity::Legendary, Milestone::ProgramsRun(1000)),
+        Achievement::new("hoarder", "Hoarder", "Create 25 variables", Rarity::Uncommon, Milestone::VariablesCreated(25)),
+        Achievement::new("toolsmith", "Toolsmith", "Define 10 functions", Rarity::Uncommon, Milestone::FunctionsDefined(10)),
+        Achievement::new(
+            "back_on_your_feet",
+            "Back on Your Feet",
+            "Run a program successfully right after one failed",
+            Rarity::Common,
+            Milestone::RecoveredFromErrors(1),
+        ),
+        Achievement::new(
+            "resilient",
+            "Resilient",
+            "Recover from 20 runtime errors",
+            Rarity::Epic,
+            Milestone::RecoveredFromErrors(20),
+        ),
+        Achievement::new("flat_broke", "Flat Broke", "End a run with no coins left", Rarity::Rare, Milestone::OutOfCoins),
+    ]
+}
+
+pub struct AchievementTracker {
+    achievements: Vec<Achievement>,
+    stats: AchievementStats,
+    unlocked: HashMap<String, u64>, // id -> unlock timestamp
+    clock: Box<dyn Clock>,
+}
+
+impl AchievementTracker {
+    pub fn new(achievements: Vec<Achievement>) -> Self {
+        Self::with_clock(achievements, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(achievements: Vec<Achievement>, clock: Box<dyn Clock>) -> Self {
+        Self {
+            achievements,
+            stats: AchievementStats::default(),
+            unlocked: HashMap::new(),
+            clock,
+        }
+    }
+
+    pub fn achievements(&self) -> &[Achievement] {
+        &self.achievements
+    }
+
+    pub fn stats(&self) -> &AchievementStats {
+        &self.stats
+    }
+
+    pub fn unlocked_at(&self, id: &str) -> Option<u64> {
+        self.unlocked.get(id).copied()
+    }
+
+    pub fn progress(&self, achievement: &Achievement) -> (u64, u64) {
+        achievement.milestone.progress(&self.stats)
+    }
+
+    // takes the events of a single run, returns what it unlocked
+    pub fn record_run(&mut self, events: &[ExecutionEvent], coin_manager: &CoinManager) -> Vec<&Achievement> {
+        let mut failed = false;
+        for event in events {
+            match event {
+                ExecutionEvent::ProgramStarted { .. } => self.stats.programs_run += 1,
+                ExecutionEvent::FunctionDefined { .. } => self.stats.functions_defined += 1,
+                ExecutionEvent::VariableDefined { .. } => self.stats.variables_created += 1,
+                ExecutionEvent::RuntimeError { .. } => failed = true,
+                _ => {}
+            }
+        }
+
+        if failed {
+            self.stats.runtime_errors += 1;
+        } else if self.stats.last_run_failed {
+            self.stats.recoveries += 1;
+        }
+        self.stats.last_run_failed = failed;
+
+        if coin_manager.get_all_balances().values().all(|&balance| balance <= 0) {
+            self.stats.went_broke = true;
+        }
+
+        let now = self.clock.now();
+        let mut newly = Vec::new();
+        for achievement in &self.achievements {
+            let (current, target) = achievement.milestone.progress(&self.stats);
+            if current >= target && !self.unlocked.contains_key(&achievement.id) {
+                self.unlocked.insert(achievement.id.clone(), now);
+                newly.push(achievement);
+            }
+        }
+        newly
+    }
+
+    // same key=value format as the income file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let stats = &self.stats;
+        let mut contents = format!(
+            "programs_run={}\nfunctions_defined={}\nvariables_created={}\nruntime_errors={}\nrecoveries={}\nwent_broke={}\nlast_run_failed={}\n",
+            stats.programs_run,
+            stats.functions_defined,
+            stats.variables_created,
+            stats.runtime_errors,
+            stats.recoveries,
+            stats.went_broke,
+            stats.last_run_failed
+        );
+        let mut unlocked: Vec<_> = self.unlocked.iter().collect();
+        unlocked.sort();
+        for (id, timestamp) in unlocked {
+            contents.push_str(&format!("unlocked.{}={}\n", id, timestamp));
+        }
+        fs::write(path, contents)
+    }
+
+    // a missing file is a fresh start; unlocks of achievements that no
+    // longer exist are kept so they come back if the achievement does
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("bad value in '{}'", line));
+            let stats = &mut self.stats;
+            match key.trim() {
+                "programs_run" => stats.programs_run = value.parse().map_err(|_| invalid())?,
+                "functions_defined" => stats.functions_defined = value.parse().map_err(|_| invalid())?,
+                "variables_created" => stats.variables_created = value.parse().map_err(|_| invalid())?,
+                "runtime_errors" => stats.runtime_errors = value.parse().map_err(|_| invalid())?,
+                "recoveries" => stats.recoveries = value.parse().map_err(|_| invalid())?,
+                "went_broke" => stats.went_broke = value.parse().map_err(|_| invalid())?,
+                "last_run_failed" => stats.last_run_failed = value.parse().map_err(|_| invalid())?,
+                key => {
+                    if let Some(id) = key.strip_prefix("unlocked.") {
+                        self.unlocked.insert(id.to_string(), value.parse().map_err(|_| invalid())?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for AchievementTracker {
+    fn default() -> Self {
+        Self::new(default_achievements())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn started() -> ExecutionEvent {
+        ExecutionEvent::ProgramStarted {
+            source: "1 + 1".to_string(),
+        }
+    }
+
+    fn failed() -> ExecutionEvent {
+        ExecutionEvent::RuntimeError {
+            message: "Undefined variable: x".to_string(),
+        }
+    }
+
+    fn tracker() -> (AchievementTracker, ManualClock) {
+        let clock = ManualClock::new(1_000);
+        (AchievementTracker::with_clock(default_achievements(), Box::new(clock.clone())), clock)
+    }
+
+    #[test]
+    fn test_milestones_unlock_once_with_timestamp() {
+        let (mut tracker, clock) = tracker();
+        let coins = CoinManager::new();
+
+        let unlocked: Vec<_> = tracker.record_run(&[started()], &coins).iter().map(|a| a.id.clone()).collect();
+        assert_eq!(unlocked, vec!["first_run"]);
+        assert_eq!(tracker.unlocked_at("first_run"), Some(1_000));
+
+        clock.advance(60);
+        for _ in 0..98 {
+            assert!(tracker.record_run(&[started()], &coins).is_empty());
+        }
+        let centurion = tracker.achievements()[1].clone();
+        assert_eq!(tracker.progress(&centurion), (99, 100));
+
+        let unlocked = tracker.record_run(&[started()], &coins);
+        assert_eq!(unlocked[0].rarity, Rarity::Rare);
+        assert_eq!(tracker.unlocked_at("centurion"), Some(1_060));
+        assert_eq!(tracker.unlocked_at("first_run"), Some(1_000));
+    }
+
+    #[test]
+    fn test_recovery_and_out_of_coins() {
+        let (mut tracker, _) = tracker();
+        let coins = CoinManager::new();
+
+        tracker.record_run(&[started(), failed()], &coins);
+        tracker.record_run(&[started(), failed()], &coins);
+        assert_eq!(tracker.stats().runtime_errors, 2);
+        assert_eq!(tracker.unlocked_at("back_on_your_feet"), None);
+
+        let unlocked = tracker.record_run(&[started()], &coins);
+        assert_eq!(unlocked[0].id, "back_on_your_feet");
+        assert_eq!(tracker.stats().recoveries, 1);
+
+        // further successes aren't recoveries
+        tracker.record_run(&[started()], &coins);
+        assert_eq!(tracker.stats().recoveries, 1);
+
+        let broke = CoinManager::with_balances(0, 0);
+        let unlocked = tracker.record_run(&[started()], &broke);
+        assert_eq!(unlocked[0].id, "flat_broke");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let (mut tracker, _) = tracker();
+        tracker.record_run(&[started(), failed()], &CoinManager::new());
+
+        let path = std::env::temp_dir().join(format!("cang_achievements_{}", std::process::id()));
+        tracker.save(&path).unwrap();
+
+        let (mut restored, _) = self::tracker();
+        restored.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.stats(), tracker.stats());
+        assert_eq!(restored.unlocked_at("first_run"), Some(1_000));
+
+        // the failure carries over into the next session
+        let unlocked = restored.record_run(&[started()], &CoinManager::new());
+        assert_eq!(unlocked[0].id, "back_on_your_feet");
+    }
+}
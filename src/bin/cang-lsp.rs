@@ -0,0 +1,13 @@
+// the language server editors start, it talks to them over stdin and stdout
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    match cang::lsp::serve(stdin.lock(), &mut io::stdout().lock()) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("cang-lsp: {}", e);
+            process::exit(1);
+        }
+    }
+}
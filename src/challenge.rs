@@ -0,0 +1,125 @@
+// hidden test cases run against a user's function in a throwaway environment
+use std::collections::HashMap;
+
+use crate::{
+    natives::NativeRegistry,
+    parser::Evaluator,
+    Expr,
+};
+
+// generous for anything written without conditionals, small enough to keep
+// runaway recursion off the real stack limit
+const CALL_LIMIT: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub args: Vec<i64>,
+    pub expected: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    pub actual: Result<i64, String>,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChallengeReport {
+    pub function: String,
+    pub results: Vec<CaseResult>,
+}
+
+impl ChallengeReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.passed() == self.results.len()
+    }
+}
+
+// works on a copy of env and without a meter, so nothing leaks back and
+// no coins are spent
+pub fn run_challenge(
+    function: &str,
+    cases: &[TestCase],
+    env: &HashMap<String, Expr>,
+    natives: &NativeRegistry,
+) -> ChallengeReport {
+    let results = cases
+        .iter()
+        .map(|case| {
+            let mut sandbox = env.clone();
+            let mut output = Vec::new();
+            let call = Expr::FnCall(
+                function.to_string(),
+                case.args.iter().map(|&arg| Expr::Number(arg)).collect(),
+            );
+            let actual = Evaluator::new(&mut output)
+                .with_call_limit(CALL_LIMIT)
+                .with_natives(natives)
+                .eval(&call, &mut sandbox)
+                .map_err(|e| e.to_string());
+            let passed = actual == Ok(case.expected);
+            CaseResult { actual, passed }
+        })
+        .collect();
+
+    ChallengeReport {
+        function: function.to_string(),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::{eval, Parser},
+        tokenize,
+    };
+
+    fn define(source: &str) -> HashMap<String, Expr> {
+        let mut env = HashMap::new();
+        let program = Parser::new(tokenize(source)).parse_program().unwrap();
+        eval(&program, &mut env).unwrap();
+        env
+    }
+
+    fn case(args: &[i64], expected: i64) -> TestCase {
+        TestCase {
+            args: args.to_vec(),
+            expected,
+        }
+    }
+
+    #[test]
+    fn test_reports_each_case() {
+        let env = define("fn square(n) { n * n }");
+        let report = run_challenge("square", &[case(&[3], 9), case(&[-2], 4), case(&[2], 5)], &env, &NativeRegistry::new());
+
+        assert_eq!(report.passed(), 2);
+        assert!(!report.all_passed());
+        assert_eq!(report.results[2].actual, Ok(4));
+    }
+
+    #[test]
+    fn test_sandbox_leaves_env_alone() {
+        let env = define("fn f(n) { let leaked = n; print(leaked); n }");
+        let report = run_challenge("f", &[case(&[1], 1)], &env, &NativeRegistry::new());
+
+        assert!(report.all_passed());
+        assert!(!env.contains_key("leaked"));
+    }
+
+    #[test]
+    fn test_errors_fail_the_case() {
+        let env = define("fn loop(n) { loop(n) }");
+        let report = run_challenge("loop", &[case(&[1], 1)], &env, &NativeRegistry::new());
+        assert!(report.results[0].actual.as_ref().unwrap_err().contains("Call depth"));
+
+        let report = run_challenge("missing", &[case(&[], 0)], &env, &NativeRegistry::new());
+        assert!(!report.all_passed());
+    }
+}
@@ -0,0 +1,83 @@
+// time source for anything that depends on wall clock, swap in ManualClock for tests
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub trait Clock {
+    // seconds since unix epoch
+    fn now(&self) -> u64;
+
+    fn today(&self) -> u64 {
+        self.now() / SECONDS_PER_DAY
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+// clones share the same time so a test can keep a handle and advance it
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<u64>>,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: Rc::new(Cell::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.set(self.now.get() + secs);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
+
+// YYYY-MM-DD in UTC, from Howard Hinnant's days-to-civil algorithm
+pub fn format_date(secs: u64) -> String {
+    let days = (secs / SECONDS_PER_DAY) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_767_225_599), "2025-12-31");
+    }
+}
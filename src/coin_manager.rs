@@ -6,9 +6,69 @@ pub enum CoinType {
     Function,
 }
 
+impl CoinType {
+    pub const ALL: [CoinType; 2] = [CoinType::Variable, CoinType::Function];
+
+    pub fn from_name(name: &str) -> Option<CoinType> {
+        match name.to_lowercase().as_str() {
+            "variable" | "var" => Some(CoinType::Variable),
+            "function" | "func" | "fn" => Some(CoinType::Function),
+            _ => None,
+        }
+    }
+}
+
+// fn params you get before buying any ExtraParam upgrades, once the limit is
+// turned on. It's off by default so programs written before the shop keep running
+pub const BASE_PARAM_LIMIT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Upgrade {
+    ExtraParam,  // +1 param limit per level
+    RewardBonus, // +1 coin on every reward payout per level
+}
+
+impl Upgrade {
+    pub fn from_name(name: &str) -> Option<Upgrade> {
+        match name.to_lowercase().as_str() {
+            "extra_param" | "param" => Some(Upgrade::ExtraParam),
+            "reward_bonus" | "bonus" => Some(Upgrade::RewardBonus),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Upgrade::ExtraParam => "extra_param",
+            Upgrade::RewardBonus => "reward_bonus",
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        match self {
+            Upgrade::ExtraParam => "+1 function parameter limit",
+            Upgrade::RewardBonus => "+1 coin on every quest or income reward",
+        }
+    }
+}
+
+// give `give` coins of one type, get `receive` of the other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExchangeRate {
+    pub give: u32,
+    pub receive: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShopItem {
+    pub upgrade: Upgrade,
+    pub coin_type: CoinType,
+    pub price: u32,
+}
+
 // for now i think it cost to make stuff not to use, due to change prob
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CoinError {
     InsufficientFunds {
         required: u32,
@@ -16,6 +76,33 @@ pub enum CoinError {
         coin_type: CoinType,
     },
     InvalidCoinType,
+    InvalidExchangeRate {
+        from: CoinType,
+        to: CoinType,
+    },
+    NoExchangeRate {
+        from: CoinType,
+        to: CoinType,
+    },
+    ExchangeTooSmall {
+        minimum: u32,
+        offered: u32,
+    },
+    NotForSale(Upgrade),
+    CreditLimitExceeded {
+        required: u32,
+        available: u32,
+        limit: u32,
+        coin_type: CoinType,
+    },
+    DebtOutstanding {
+        debt: u32,
+        coin_type: CoinType,
+    },
+    ParamLimitExceeded {
+        limit: usize,
+        requested: usize,
+    },
 }
 
 impl std::fmt::Display for CoinError {
@@ -33,6 +120,38 @@ impl std::fmt::Display for CoinError {
                 )
             }
             CoinError::InvalidCoinType => write!(f, "Invalid coin type"),
+            CoinError::InvalidExchangeRate { from, to } => {
+                write!(f, "Invalid exchange rate from {:?} to {:?} coins", from, to)
+            }
+            CoinError::NoExchangeRate { from, to } => {
+                write!(f, "Cannot exchange {:?} coins for {:?} coins", from, to)
+            }
+            CoinError::ExchangeTooSmall { minimum, offered } => {
+                write!(f, "Exchange needs at least {} coins (offered {})", minimum, offered)
+            }
+            CoinError::CreditLimitExceeded {
+                required,
+                available,
+                limit,
+                coin_type,
+            } => {
+                write!(
+                    f,
+                    "Credit limit of {} {:?} coins reached (need {}, can still spend {})",
+                    limit, coin_type, required, available
+                )
+            }
+            CoinError::DebtOutstanding { debt, coin_type } => {
+                write!(f, "Pay off your {} {:?} coin debt first", debt, coin_type)
+            }
+            CoinError::NotForSale(upgrade) => write!(f, "'{}' is not for sale", upgrade.name()),
+            CoinError::ParamLimitExceeded { limit, requested } => {
+                write!(
+                    f,
+                    "Function has {} params but the limit is {} (buy 'extra_param' in the shop)",
+                    requested, limit
+                )
+            }
         }
     }
 }
@@ -45,28 +164,69 @@ pub struct CoinReward {
     pub amount: u32,
 }
 
+// percent of outstanding debt added per execution, rounded up
+pub const DEFAULT_INTEREST_PERCENT: u32 = 10;
+
+// biggest credit line that can be opened
+pub const MAX_CREDIT_LIMIT: u32 = 1_000;
+
 #[derive(Debug, Clone)]
 pub struct CoinManager {
-    balances: HashMap<CoinType, u32>,
+    // negative means debt, only possible with a credit limit
+    balances: HashMap<CoinType, i64>,
+    credit_limits: HashMap<CoinType, u32>,
+    interest_percent: u32,
+    exchange_rates: HashMap<(CoinType, CoinType), ExchangeRate>,
+    shop_items: Vec<ShopItem>,
+    upgrades: HashMap<Upgrade, u32>,
+    param_limit: bool, // whether functions are held to param_limit()
 }
 
 impl CoinManager {
     // def 10 var 3 func
     pub fn new() -> Self {
-        let mut balances = HashMap::new();
-        balances.insert(CoinType::Variable, 10);
-        balances.insert(CoinType::Function, 3);
-
-        Self { balances }
+        Self::with_balances(10, 3)
     }
 
     // create with amt
     pub fn with_balances(variable_coins: u32, function_coins: u32) -> Self {
         let mut balances = HashMap::new();
-        balances.insert(CoinType::Variable, variable_coins);
-        balances.insert(CoinType::Function, function_coins);
+        balances.insert(CoinType::Variable, variable_coins as i64);
+        balances.insert(CoinType::Function, function_coins as i64);
 
-        Self { balances }
+        // both directions lose coins so a round trip never pays
+        let mut exchange_rates = HashMap::new();
+        exchange_rates.insert(
+            (CoinType::Variable, CoinType::Function),
+            ExchangeRate { give: 3, receive: 1 },
+        );
+        exchange_rates.insert(
+            (CoinType::Function, CoinType::Variable),
+            ExchangeRate { give: 1, receive: 2 },
+        );
+
+        let shop_items = vec![
+            ShopItem {
+                upgrade: Upgrade::ExtraParam,
+                coin_type: CoinType::Function,
+                price: 2,
+            },
+            ShopItem {
+                upgrade: Upgrade::RewardBonus,
+                coin_type: CoinType::Variable,
+                price: 8,
+            },
+        ];
+
+        Self {
+            balances,
+            credit_limits: HashMap::new(),
+            interest_percent: DEFAULT_INTEREST_PERCENT,
+            exchange_rates,
+            shop_items,
+            upgrades: HashMap::new(),
+            param_limit: false,
+        }
     }
 
     pub fn spend_var_coin(&mut self) -> Result<(), CoinError> {
@@ -77,38 +237,251 @@ impl CoinManager {
         self.spend_coins(CoinType::Function, 1)
     }
 
-    fn spend_coins(&mut self, coin_type: CoinType, amt: u32) -> Result<(), CoinError> {
-        let current_balance = self.get_balance(coin_type);
+    // may dip into credit if a credit line is open for this coin type
+    pub fn spend_coins(&mut self, coin_type: CoinType, amt: u32) -> Result<(), CoinError> {
+        self.check_affordable(coin_type, amt)?;
+        *self.balances.entry(coin_type).or_insert(0) -= amt as i64;
+        Ok(())
+    }
 
-        if current_balance < amt {
+    // shop and exchange are cash only, otherwise credit could be laundered
+    fn spend_cash(&mut self, coin_type: CoinType, amt: u32) -> Result<(), CoinError> {
+        let debt = self.debt(coin_type);
+        if debt > 0 {
+            return Err(CoinError::DebtOutstanding { debt, coin_type });
+        }
+
+        let current_balance = self.get_balance(coin_type);
+        if current_balance < amt as i64 {
             return Err(CoinError::InsufficientFunds {
                 required: amt,
-                available: current_balance,
+                available: current_balance.max(0) as u32,
                 coin_type,
             });
         }
 
-        self.balances.insert(coin_type, current_balance - amt);
+        self.balances.insert(coin_type, current_balance - amt as i64);
         Ok(())
     }
 
-    pub fn get_balance(&self, coin_type: CoinType) -> u32 {
+    pub fn check_affordable(&self, coin_type: CoinType, amt: u32) -> Result<(), CoinError> {
+        let available = self.available(coin_type);
+        if available >= amt {
+            return Ok(());
+        }
+
+        let limit = self.credit_limit(coin_type);
+        if limit == 0 {
+            Err(CoinError::InsufficientFunds {
+                required: amt,
+                available,
+                coin_type,
+            })
+        } else {
+            Err(CoinError::CreditLimitExceeded {
+                required: amt,
+                available,
+                limit,
+                coin_type,
+            })
+        }
+    }
+
+    pub fn get_balance(&self, coin_type: CoinType) -> i64 {
         *self.balances.get(&coin_type).unwrap_or(&0)
     }
 
+    // what can still be spent, credit included
+    pub fn available(&self, coin_type: CoinType) -> u32 {
+        let available = self.get_balance(coin_type).saturating_add(self.credit_limit(coin_type) as i64);
+        available.clamp(0, u32::MAX as i64) as u32
+    }
+
+    pub fn debt(&self, coin_type: CoinType) -> u32 {
+        self.get_balance(coin_type).saturating_neg().clamp(0, u32::MAX as i64) as u32
+    }
+
     pub fn add_coins(&mut self, amt: u32, coin_type: CoinType) {
-        let current_balance = self.get_balance(coin_type);
-        self.balances.insert(coin_type, current_balance + amt);
+        // goes to paying off debt first since debt is just a negative balance
+        *self.balances.entry(coin_type).or_insert(0) += amt as i64;
     }
 
-    pub fn get_all_balances(&self) -> &HashMap<CoinType, u32> {
+    pub fn get_all_balances(&self) -> &HashMap<CoinType, i64> {
         &self.balances
     }
 
-    pub fn apply_rewards(&mut self, rewards: &[CoinReward]) {
-        // for multiple at once
-        for reward in rewards {
-            self.add_coins(reward.amount, reward.coin_type);
+    pub fn credit_limit(&self, coin_type: CoinType) -> u32 {
+        *self.credit_limits.get(&coin_type).unwrap_or(&0)
+    }
+
+    // 0 closes the credit line, existing debt stays until paid.
+    // anything above MAX_CREDIT_LIMIT is capped to it
+    pub fn set_credit_limit(&mut self, coin_type: CoinType, limit: u32) {
+        if limit == 0 {
+            self.credit_limits.remove(&coin_type);
+        } else {
+            self.credit_limits.insert(coin_type, limit.min(MAX_CREDIT_LIMIT));
+        }
+    }
+
+    pub fn interest_percent(&self) -> u32 {
+        self.interest_percent
+    }
+
+    pub fn set_interest_percent(&mut self, percent: u32) {
+        self.interest_percent = percent;
+    }
+
+    // call once per execution, returns the interest added to each debt.
+    // interest never takes debt past the credit limit
+    pub fn accrue_interest(&mut self) -> Vec<(CoinType, u32)> {
+        let mut charged = Vec::new();
+        for coin_type in CoinType::ALL {
+            let debt = self.debt(coin_type) as u64;
+            if debt == 0 || self.interest_percent == 0 {
+                continue;
+            }
+            let headroom = (self.credit_limit(coin_type) as u64).saturating_sub(debt);
+            let interest = debt
+                .saturating_mul(self.interest_percent as u64)
+                .div_ceil(100)
+                .min(headroom);
+            if interest == 0 {
+                continue;
+            }
+            let balance = self.balances.entry(coin_type).or_insert(0);
+            *balance = balance.saturating_sub(interest as i64);
+            charged.push((coin_type, interest as u32));
+        }
+        charged
+    }
+
+    // for multiple at once, returns what was actually credited (reward bonus included)
+    pub fn apply_rewards(&mut self, rewards: &[CoinReward]) -> Vec<CoinReward> {
+        let bonus = self.upgrade_level(Upgrade::RewardBonus);
+        rewards
+            .iter()
+            .map(|reward| {
+                let credited = CoinReward {
+                    coin_type: reward.coin_type,
+                    amount: reward.amount.saturating_add(bonus),
+                };
+                self.add_coins(credited.amount, credited.coin_type);
+                credited
+            })
+            .collect()
+    }
+
+    pub fn exchange_rate(&self, from: CoinType, to: CoinType) -> Option<ExchangeRate> {
+        self.exchange_rates.get(&(from, to)).copied()
+    }
+
+    pub fn exchange_rates(&self) -> &HashMap<(CoinType, CoinType), ExchangeRate> {
+        &self.exchange_rates
+    }
+
+    // rejects rates that would let a round trip make coins out of nothing
+    pub fn set_exchange_rate(
+        &mut self,
+        from: CoinType,
+        to: CoinType,
+        give: u32,
+        receive: u32,
+    ) -> Result<(), CoinError> {
+        if from == to || give == 0 || receive == 0 {
+            return Err(CoinError::InvalidExchangeRate { from, to });
+        }
+
+        if let Some(back) = self.exchange_rate(to, from) {
+            // a -> b -> a multiplies by (receive / give) * (back.receive / back.give)
+            let round_trip_out = receive as u64 * back.receive as u64;
+            let round_trip_in = give as u64 * back.give as u64;
+            if round_trip_out > round_trip_in {
+                return Err(CoinError::InvalidExchangeRate { from, to });
+            }
+        }
+
+        self.exchange_rates
+            .insert((from, to), ExchangeRate { give, receive });
+        Ok(())
+    }
+
+    // only whole batches are exchanged, leftover coins stay put
+    pub fn exchange(&mut self, from: CoinType, to: CoinType, amt: u32) -> Result<u32, CoinError> {
+        let rate = self
+            .exchange_rate(from, to)
+            .ok_or(CoinError::NoExchangeRate { from, to })?;
+
+        let batches = amt / rate.give;
+        if batches == 0 {
+            return Err(CoinError::ExchangeTooSmall {
+                minimum: rate.give,
+                offered: amt,
+            });
+        }
+
+        self.spend_cash(from, batches * rate.give)?;
+        let received = batches * rate.receive;
+        self.add_coins(received, to);
+        Ok(received)
+    }
+
+    // extra_param is only on sale while there's a limit for it to raise
+    pub fn shop_items(&self) -> Vec<&ShopItem> {
+        self.shop_items
+            .iter()
+            .filter(|i| self.param_limit || i.upgrade != Upgrade::ExtraParam)
+            .collect()
+    }
+
+    pub fn set_price(&mut self, upgrade: Upgrade, coin_type: CoinType, price: u32) {
+        if let Some(item) = self.shop_items.iter_mut().find(|i| i.upgrade == upgrade) {
+            item.coin_type = coin_type;
+            item.price = price;
+        } else {
+            self.shop_items.push(ShopItem {
+                upgrade,
+                coin_type,
+                price,
+            });
+        }
+    }
+
+    pub fn remove_from_shop(&mut self, upgrade: Upgrade) {
+        self.shop_items.retain(|i| i.upgrade != upgrade);
+    }
+
+    pub fn buy_upgrade(&mut self, upgrade: Upgrade) -> Result<(), CoinError> {
+        let item = self
+            .shop_items()
+            .into_iter()
+            .find(|i| i.upgrade == upgrade)
+            .cloned()
+            .ok_or(CoinError::NotForSale(upgrade))?;
+
+        self.spend_cash(item.coin_type, item.price)?;
+        *self.upgrades.entry(upgrade).or_insert(0) += 1;
+        Ok(())
+    }
+
+    pub fn upgrade_level(&self, upgrade: Upgrade) -> u32 {
+        *self.upgrades.get(&upgrade).unwrap_or(&0)
+    }
+
+    pub fn set_param_limit(&mut self, enabled: bool) {
+        self.param_limit = enabled;
+    }
+
+    // None while functions can take any number of params
+    pub fn param_limit(&self) -> Option<usize> {
+        self.param_limit
+            .then(|| BASE_PARAM_LIMIT + self.upgrade_level(Upgrade::ExtraParam) as usize)
+    }
+
+    pub fn check_params(&self, requested: usize) -> Result<(), CoinError> {
+        match self.param_limit() {
+            Some(limit) if requested > limit => Err(CoinError::ParamLimitExceeded { limit, requested }),
+            _ => Ok(()),
         }
     }
 }
@@ -118,3 +491,257 @@ impl Default for CoinManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total_coins(manager: &CoinManager) -> i64 {
+        manager.get_all_balances().values().sum()
+    }
+
+    #[test]
+    fn test_exchange_whole_batches() {
+        let mut manager = CoinManager::with_balances(10, 0);
+
+        let received = manager
+            .exchange(CoinType::Variable, CoinType::Function, 7)
+            .unwrap();
+
+        assert_eq!(received, 2);
+        assert_eq!(manager.get_balance(CoinType::Variable), 4);
+        assert_eq!(manager.get_balance(CoinType::Function), 2);
+    }
+
+    #[test]
+    fn test_exchange_insufficient_funds_leaves_balances() {
+        let mut manager = CoinManager::with_balances(2, 0);
+
+        let err = manager
+            .exchange(CoinType::Variable, CoinType::Function, 3)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CoinError::InsufficientFunds {
+                required: 3,
+                available: 2,
+                coin_type: CoinType::Variable,
+            }
+        );
+        assert_eq!(manager.get_balance(CoinType::Variable), 2);
+        assert_eq!(manager.get_balance(CoinType::Function), 0);
+
+        let err = manager
+            .exchange(CoinType::Variable, CoinType::Function, 1)
+            .unwrap_err();
+        assert_eq!(err, CoinError::ExchangeTooSmall { minimum: 3, offered: 1 });
+    }
+
+    #[test]
+    fn test_round_trip_never_creates_coins() {
+        let mut manager = CoinManager::with_balances(30, 0);
+        let start = total_coins(&manager);
+
+        for _ in 0..5 {
+            let vars = manager.available(CoinType::Variable);
+            if manager.exchange(CoinType::Variable, CoinType::Function, vars).is_err() {
+                break;
+            }
+            let funcs = manager.available(CoinType::Function);
+            manager
+                .exchange(CoinType::Function, CoinType::Variable, funcs)
+                .unwrap();
+            assert!(total_coins(&manager) <= start);
+        }
+
+        assert!(manager.get_balance(CoinType::Variable) < 30);
+    }
+
+    #[test]
+    fn test_set_exchange_rate_rejects_arbitrage() {
+        let mut manager = CoinManager::new();
+
+        // 1 func -> 2 var is the default, so 2 var -> 2 func would loop for profit
+        let err = manager
+            .set_exchange_rate(CoinType::Variable, CoinType::Function, 2, 2)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            CoinError::InvalidExchangeRate {
+                from: CoinType::Variable,
+                to: CoinType::Function,
+            }
+        );
+
+        // break-even is allowed
+        manager
+            .set_exchange_rate(CoinType::Variable, CoinType::Function, 2, 1)
+            .unwrap();
+        assert_eq!(
+            manager.exchange_rate(CoinType::Variable, CoinType::Function),
+            Some(ExchangeRate { give: 2, receive: 1 })
+        );
+
+        assert!(manager
+            .set_exchange_rate(CoinType::Variable, CoinType::Variable, 1, 1)
+            .is_err());
+        assert!(manager
+            .set_exchange_rate(CoinType::Function, CoinType::Variable, 0, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_buy_upgrade() {
+        let mut manager = CoinManager::with_balances(0, 5);
+        assert_eq!(manager.param_limit(), None);
+        assert_eq!(
+            manager.buy_upgrade(Upgrade::ExtraParam),
+            Err(CoinError::NotForSale(Upgrade::ExtraParam))
+        );
+
+        manager.set_param_limit(true);
+        assert_eq!(manager.param_limit(), Some(BASE_PARAM_LIMIT));
+
+        manager.buy_upgrade(Upgrade::ExtraParam).unwrap();
+        manager.buy_upgrade(Upgrade::ExtraParam).unwrap();
+
+        assert_eq!(manager.upgrade_level(Upgrade::ExtraParam), 2);
+        assert_eq!(manager.param_limit(), Some(BASE_PARAM_LIMIT + 2));
+        assert_eq!(manager.get_balance(CoinType::Function), 1);
+
+        // can't afford a third, nothing changes
+        assert!(manager.buy_upgrade(Upgrade::ExtraParam).is_err());
+        assert_eq!(manager.upgrade_level(Upgrade::ExtraParam), 2);
+        assert_eq!(manager.get_balance(CoinType::Function), 1);
+    }
+
+    #[test]
+    fn test_configurable_prices() {
+        let mut manager = CoinManager::with_balances(1, 0);
+        manager.set_price(Upgrade::RewardBonus, CoinType::Variable, 1);
+        manager.buy_upgrade(Upgrade::RewardBonus).unwrap();
+        assert_eq!(manager.get_balance(CoinType::Variable), 0);
+
+        let credited = manager.apply_rewards(&[CoinReward {
+            coin_type: CoinType::Function,
+            amount: 2,
+        }]);
+        assert_eq!(credited[0].amount, 3);
+        assert_eq!(manager.get_balance(CoinType::Function), 3);
+
+        let credited = manager.apply_rewards(&[CoinReward {
+            coin_type: CoinType::Variable,
+            amount: u32::MAX,
+        }]);
+        assert_eq!(credited[0].amount, u32::MAX);
+
+        manager.remove_from_shop(Upgrade::ExtraParam);
+        assert_eq!(
+            manager.buy_upgrade(Upgrade::ExtraParam),
+            Err(CoinError::NotForSale(Upgrade::ExtraParam))
+        );
+    }
+
+    #[test]
+    fn test_spending_without_credit() {
+        let mut manager = CoinManager::with_balances(1, 0);
+        manager.spend_var_coin().unwrap();
+
+        assert_eq!(
+            manager.spend_var_coin(),
+            Err(CoinError::InsufficientFunds {
+                required: 1,
+                available: 0,
+                coin_type: CoinType::Variable,
+            })
+        );
+        assert_eq!(manager.get_balance(CoinType::Variable), 0);
+    }
+
+    #[test]
+    fn test_credit_line_allows_overspending_to_limit() {
+        let mut manager = CoinManager::with_balances(1, 0);
+        manager.set_credit_limit(CoinType::Variable, 2);
+
+        manager.spend_coins(CoinType::Variable, 3).unwrap();
+        assert_eq!(manager.get_balance(CoinType::Variable), -2);
+        assert_eq!(manager.debt(CoinType::Variable), 2);
+        assert_eq!(manager.available(CoinType::Variable), 0);
+
+        assert_eq!(
+            manager.spend_var_coin(),
+            Err(CoinError::CreditLimitExceeded {
+                required: 1,
+                available: 0,
+                limit: 2,
+                coin_type: CoinType::Variable,
+            })
+        );
+        assert_eq!(manager.get_balance(CoinType::Variable), -2);
+    }
+
+    #[test]
+    fn test_interest_and_rewards_pay_debt_first() {
+        let mut manager = CoinManager::with_balances(0, 0);
+        manager.set_credit_limit(CoinType::Function, 6);
+        manager.set_interest_percent(10);
+        manager.spend_coins(CoinType::Function, 5).unwrap();
+
+        // 10% of 5 rounds up to 1
+        assert_eq!(manager.accrue_interest(), vec![(CoinType::Function, 1)]);
+        assert_eq!(manager.get_balance(CoinType::Function), -6);
+
+        manager.apply_rewards(&[CoinReward {
+            coin_type: CoinType::Function,
+            amount: 4,
+        }]);
+        assert_eq!(manager.debt(CoinType::Function), 2);
+
+        manager.add_coins(3, CoinType::Function);
+        assert_eq!(manager.get_balance(CoinType::Function), 1);
+        assert!(manager.accrue_interest().is_empty());
+    }
+
+    #[test]
+    fn test_interest_stops_at_credit_limit() {
+        let mut manager = CoinManager::with_balances(0, 0);
+        manager.set_credit_limit(CoinType::Variable, 1);
+        manager.spend_var_coin().unwrap();
+
+        for _ in 0..500 {
+            assert!(manager.accrue_interest().is_empty());
+        }
+        assert_eq!(manager.debt(CoinType::Variable), 1);
+
+        manager.set_credit_limit(CoinType::Function, u32::MAX);
+        assert_eq!(manager.credit_limit(CoinType::Function), MAX_CREDIT_LIMIT);
+        manager.set_interest_percent(u32::MAX);
+        manager.spend_coins(CoinType::Function, MAX_CREDIT_LIMIT / 2).unwrap();
+        assert_eq!(
+            manager.accrue_interest(),
+            vec![(CoinType::Function, MAX_CREDIT_LIMIT / 2)]
+        );
+        assert_eq!(manager.debt(CoinType::Function), MAX_CREDIT_LIMIT);
+    }
+
+    #[test]
+    fn test_credit_cannot_be_used_in_shop() {
+        let mut manager = CoinManager::with_balances(0, 0);
+        manager.set_credit_limit(CoinType::Variable, 10);
+        manager.set_credit_limit(CoinType::Function, 10);
+
+        assert!(manager.exchange(CoinType::Variable, CoinType::Function, 3).is_err());
+        assert!(manager.buy_upgrade(Upgrade::ExtraParam).is_err());
+
+        manager.spend_var_coin().unwrap();
+        manager.add_coins(5, CoinType::Variable);
+        manager.spend_coins(CoinType::Variable, 5).unwrap();
+        assert_eq!(
+            manager.exchange(CoinType::Variable, CoinType::Function, 3),
+            Err(CoinError::DebtOutstanding {
+                debt: 1,
+                coin_type: CoinType::Variable,
+            })
+        );
+    }
+}
@@ -0,0 +1,419 @@
+// a step debugger the evaluator reports to before every statement and on
+// every call. When it decides to stop it takes commands, from its own script
+// if it has one or else from wherever the program reads its input
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    formatter::format_expr,
+    input::InputSource,
+    output::Output,
+    parser::{Statement, StatementSpan},
+    CoinManager, CoinType, Expr, ValidationError,
+};
+
+// a function call in progress, the program itself at the bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: Option<String>, // None for the top level
+    pub args: Vec<(String, i64)>,
+    pub statement: usize, // index into its body
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Step,
+    Over(usize), // stop once the stack is no deeper than this
+    Out(usize),  // stop once the stack is shallower than this
+    Continue,
+    Detached, // out of commands, run to the end
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    // source line of each statement, by the function it's in and its index there
+    lines: HashMap<(Option<String>, usize), usize>,
+    stack: Vec<Frame>,
+    mode: Mode,
+    commands: Option<Box<dyn InputSource>>,
+    // where it last stopped, so continuing doesn't stop on the same line again
+    last_stop: Option<(usize, Option<usize>)>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            lines: HashMap::new(),
+            stack: Vec::new(),
+            mode: Mode::Step,
+            commands: None,
+            last_stop: None,
+        }
+    }
+
+    // a script of commands to use instead of asking, one per line
+    pub fn with_commands(mut self, commands: Box<dyn InputSource>) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.insert(line)
+    }
+
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn stack(&self) -> &[Frame] {
+        &self.stack
+    }
+
+    // gets ready to run a program, with the parser's spans for its line numbers.
+    // It stops at the first statement, or runs to a breakpoint if there are any
+    pub fn load(&mut self, statements: &[Statement], spans: &[StatementSpan]) {
+        self.lines.clear();
+        let statements: Vec<&Expr> = statements.iter().map(|(stmt, _)| stmt).collect();
+        let mut next = 0;
+        self.map_lines(None, &statements, spans, &mut next);
+        self.stack.clear();
+        self.last_stop = None;
+        self.mode = if self.breakpoints.is_empty() { Mode::Step } else { Mode::Continue };
+    }
+
+    // spans are in pre-order, so a function's body follows the function
+    fn map_lines(&mut self, function: Option<&str>, statements: &[&Expr], spans: &[StatementSpan], next: &mut usize) {
+        for (i, stmt) in statements.iter().enumerate() {
+            let Some(span) = spans.get(*next) else { return };
+            *next += 1;
+            self.lines.insert((function.map(str::to_string), i), span.start.0);
+            if let Expr::FnDef(name, _, body) = stmt {
+                let body: Vec<&Expr> = match body.as_ref() {
+                    Expr::Block(statements) => statements.iter().collect(),
+                    other => vec![other],
+                };
+                self.map_lines(Some(name), &body, spans, next);
+            }
+        }
+    }
+
+    pub fn line_of(&self, frame: &Frame) -> Option<usize> {
+        self.lines.get(&(frame.function.clone(), frame.statement)).copied()
+    }
+
+    pub(crate) fn enter(&mut self, function: &str, params: &[String], args: &[i64]) {
+        self.top();
+        self.stack.push(Frame {
+            function: Some(function.to_string()),
+            args: params.iter().cloned().zip(args.iter().copied()).collect(),
+            statement: 0,
+        });
+    }
+
+    pub(crate) fn leave(&mut self) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    fn top(&mut self) -> &mut Frame {
+        if self.stack.is_empty() {
+            self.stack.push(Frame {
+                function: None,
+                args: Vec::new(),
+                statement: 0,
+            });
+        }
+        self.stack.last_mut().unwrap()
+    }
+
+    // called before each statement runs, returns an error to abort the program
+    pub(crate) fn statement(
+        &mut self,
+        index: usize,
+        stmt: &Expr,
+        env: &HashMap<String, Expr>,
+        coins: Option<&CoinManager>,
+        out: &mut dyn Output,
+        input: &mut dyn InputSource,
+    ) -> Result<(), ValidationError> {
+        self.top().statement = index;
+        let depth = self.stack.len();
+        let line = self.line_of(&self.stack[depth - 1]);
+        let here = (depth, line);
+        if self.last_stop.is_some_and(|last| last != here) {
+            self.last_stop = None;
+        }
+
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Over(max) => depth <= max,
+            Mode::Out(max) => depth < max,
+            Mode::Continue => self.last_stop.is_none() && line.is_some_and(|line| self.breakpoints.contains(&line)),
+            Mode::Detached => false,
+        };
+        if !stop {
+            return Ok(());
+        }
+        self.last_stop = Some(here);
+
+        let place = match line {
+            Some(line) => format!("line {}", line),
+            None => "a statement with no line".to_string(),
+        };
+        let function = match &self.stack[depth - 1].function {
+            Some(name) => format!(" in {}", name),
+            None => String::new(),
+        };
+        say(out, &format!("⏸️  Paused at {}{}: {}", place, function, format_expr(stmt)));
+
+        loop {
+            out.write_str("(debug) ");
+            out.flush();
+            let command = match self.commands.as_deref_mut() {
+                Some(commands) => {
+                    let command = commands.read_line();
+                    // a script's commands are echoed so the transcript reads like a session
+                    if let Some(command) = &command {
+                        out.write_str(&format!("{}\n", command));
+                    }
+                    command
+                }
+                None => input.read_line(),
+            };
+            let Some(command) = command else {
+                say(out, "\nNo more debugger commands, running to the end");
+                self.mode = Mode::Detached;
+                return Ok(());
+            };
+
+            match command.trim().split_once(' ').unwrap_or((command.trim(), "")) {
+                ("step" | "s", "") => self.mode = Mode::Step,
+                ("next" | "n" | "over", "") => self.mode = Mode::Over(depth),
+                ("out" | "finish", "") => self.mode = Mode::Out(depth),
+                ("continue" | "c", "") => self.mode = Mode::Continue,
+                ("quit" | "q" | "abort", "") => {
+                    self.mode = Mode::Detached;
+                    return Err(ValidationError::RuntimeError("Stopped by the debugger".to_string()));
+                }
+                ("break" | "b", line) => {
+                    match line.trim().parse() {
+                        Ok(line) if self.add_breakpoint(line) => say(out, &format!("Breakpoint set at line {}", line)),
+                        Ok(line) => say(out, &format!("There's already a breakpoint at line {}", line)),
+                        Err(_) => say(out, "Usage: break <line>"),
+                    }
+                    continue;
+                }
+                ("delete" | "d", line) => {
+                    match line.trim().parse() {
+                        Ok(line) if self.remove_breakpoint(line) => say(out, &format!("Breakpoint at line {} removed", line)),
+                        Ok(line) => say(out, &format!("There's no breakpoint at line {}", line)),
+                        Err(_) => say(out, "Usage: delete <line>"),
+                    }
+                    continue;
+                }
+                ("breakpoints", "") => {
+                    say(out, &self.describe_breakpoints());
+                    continue;
+                }
+                ("stack" | "bt" | "where", "") => {
+                    self.show_stack(out);
+                    continue;
+                }
+                ("locals", "") => {
+                    show_locals(env, out);
+                    continue;
+                }
+                ("coins", "") => {
+                    show_coins(coins, out);
+                    continue;
+                }
+                ("help", "") => {
+                    show_help(out);
+                    continue;
+                }
+                ("", "") => continue,
+                _ => {
+                    say(out, &format!("Unknown debugger command '{}', try help", command.trim()));
+                    continue;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    pub fn describe_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "No breakpoints".to_string();
+        }
+        let lines: Vec<String> = self.breakpoints.iter().map(usize::to_string).collect();
+        format!("Breakpoints at line{} {}", if lines.len() == 1 { "" } else { "s" }, lines.join(", "))
+    }
+
+    fn show_stack(&self, out: &mut dyn Output) {
+        // innermost first, like a backtrace
+        for (i, frame) in self.stack.iter().rev().enumerate() {
+            let call = match &frame.function {
+                Some(name) => {
+                    let args: Vec<String> = frame.args.iter().map(|(param, value)| format!("{} = {}", param, value)).collect();
+                    format!("{}({})", name, args.join(", "))
+                }
+                None => "<program>".to_string(),
+            };
+            let line = match self.line_of(frame) {
+                Some(line) => format!(" at line {}", line),
+                None => String::new(),
+            };
+            say(out, &format!("  #{} {}{}", i, call, line));
+        }
+    }
+}
+
+fn say(out: &mut dyn Output, text: &str) {
+    out.write_str(text);
+    out.write_str("\n");
+}
+
+fn show_locals(env: &HashMap<String, Expr>, out: &mut dyn Output) {
+    let mut variables: Vec<(&String, i64)> = env
+        .iter()
+        .filter_map(|(name, value)| match value {
+            Expr::Number(n) => Some((name, *n)),
+            _ => None,
+        })
+        .collect();
+    variables.sort();
+    let mut functions: Vec<String> = env
+        .values()
+        .filter_map(|value| match value {
+            Expr::FnDef(name, params, _) => Some(format!("{}({})", name, params.join(", "))),
+            _ => None,
+        })
+        .collect();
+    functions.sort();
+
+    if variables.is_empty() && functions.is_empty() {
+        say(out, "  Nothing defined yet");
+    }
+    for (name, value) in variables {
+        say(out, &format!("  {} = {}", name, value));
+    }
+    if !functions.is_empty() {
+        say(out, &format!("  functions: {}", functions.join(", ")));
+    }
+}
+
+fn show_coins(coins: Option<&CoinManager>, out: &mut dyn Output) {
+    let Some(coins) = coins else {
+        say(out, "  Coin balances aren't available here");
+        return;
+    };
+    for coin_type in CoinType::ALL {
+        say(out, &format!("  {:?}: {}", coin_type, coins.get_balance(coin_type)));
+    }
+}
+
+fn show_help(out: &mut dyn Output) {
+    say(out, "Debugger commands:");
+    say(out, "  step (s)        - Run to the next statement, into function calls");
+    say(out, "  next (n)        - Run to the next statement, over function calls");
+    say(out, "  out             - Run until the current function returns");
+    say(out, "  continue (c)    - Run to the next breakpoint");
+    say(out, "  break <line>    - Stop when a line is reached");
+    say(out, "  delete <line>   - Remove a breakpoint");
+    say(out, "  breakpoints     - List breakpoints");
+    say(out, "  stack (bt)      - Show the calls in progress");
+    say(out, "  locals          - Show the variables and functions in scope");
+    say(out, "  coins           - Show coin balances");
+    say(out, "  quit (q)        - Stop the program");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::BufferOutput,
+        parser::{program, run_program, Parser},
+        input::{NoInput, ScriptedInput},
+        tokenize, MeteringMode, NativeRegistry, ResourceValidator,
+    };
+
+    const SOURCE: &str = "let x = 2;\nfn square(n) {\n    let sq = n * n;\n    sq\n};\nfn twice(n) { square(n) + square(n) };\nprint(twice(x))";
+
+    // runs SOURCE under a debugger following `commands`, returning the transcript
+    fn debug(commands: &[&str], breakpoints: &[usize], mode: MeteringMode) -> (String, Result<i64, ValidationError>) {
+        let mut parser = Parser::new(tokenize(SOURCE));
+        let statements = parser.parse_statements().unwrap();
+        let mut debugger = Debugger::new().with_commands(Box::new(ScriptedInput::new(commands.iter().copied())));
+        for line in breakpoints {
+            debugger.add_breakpoint(*line);
+        }
+        debugger.load(&statements, parser.statement_spans());
+
+        let buffer = BufferOutput::new();
+        let mut sink = buffer.clone();
+        let mut validator = ResourceValidator::new(CoinManager::with_balances(5, 5)).with_natives(NativeRegistry::with_stdlib());
+        let run = run_program(
+            &program(statements),
+            &mut validator,
+            &mut HashMap::new(),
+            mode,
+            &mut sink,
+            &mut NoInput,
+            Some(&mut debugger),
+        );
+        (buffer.contents(), run.result)
+    }
+
+    #[test]
+    fn test_stepping_into_over_and_out() {
+        let (transcript, result) = debug(&["next", "next", "next", "step", "step", "stack", "out"], &[], MeteringMode::Static);
+        assert!(result.is_ok());
+        let paused: Vec<&str> = transcript.lines().filter(|line| line.starts_with("⏸️")).collect();
+        assert_eq!(
+            paused,
+            [
+                "⏸️  Paused at line 1: let x = 2",
+                "⏸️  Paused at line 2: fn square(n) { let sq = n * n; sq }",
+                "⏸️  Paused at line 6: fn twice(n) { square(n) + square(n) }",
+                "⏸️  Paused at line 7: print(twice(x))",
+                "⏸️  Paused at line 6 in twice: square(n) + square(n)",
+                "⏸️  Paused at line 3 in square: let sq = n * n",
+            ]
+        );
+        assert!(transcript.contains("(debug) stack\n  #0 square(n = 2) at line 3\n  #1 twice(n = 2) at line 6\n  #2 <program> at line 7\n"));
+        // twice has nothing after its one statement, and neither does the program
+        assert!(transcript.ends_with("(debug) out\n8\n"));
+    }
+
+    #[test]
+    fn test_breakpoints_and_inspection() {
+        let (transcript, result) = debug(&["locals", "coins", "continue", "delete 4", "continue"], &[4], MeteringMode::Runtime);
+        assert_eq!(result.unwrap(), 0);
+        // with breakpoints set it runs straight to the first one
+        assert!(transcript.starts_with("⏸️  Paused at line 4 in square: sq\n"));
+        assert!(transcript.contains("(debug) locals\n  n = 2\n  sq = 4\n  x = 2\n  functions: square(n), twice(n)\n"));
+        assert!(transcript.contains("(debug) coins\n  Variable: 3\n  Function: 3\n"));
+        assert_eq!(transcript.matches("Paused at line 4").count(), 2);
+    }
+
+    #[test]
+    fn test_quitting_stops_the_program() {
+        let (transcript, result) = debug(&["break x", "break 7", "bogus", "quit"], &[], MeteringMode::Static);
+        assert_eq!(result.unwrap_err().to_string(), "Runtime err: Stopped by the debugger");
+        assert!(transcript.contains("Usage: break <line>"));
+        assert!(transcript.contains("Breakpoint set at line 7"));
+        assert!(transcript.contains("Unknown debugger command 'bogus', try help"));
+        assert!(!transcript.contains("\n8\n"));
+    }
+}
@@ -0,0 +1,15 @@
+// what a program actually did, emitted by the evaluator as it runs
+use crate::{challenge::TestCase, TokenTypes};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionEvent {
+    ProgramStarted { source: String }, // only recorded for runs that finish
+    VariableDefined { name: String, value: i64 },
+    VariableRead { name: String, value: i64 },
+    FunctionDefined { name: String, params: Vec<String>, body: String },
+    FunctionCalled { name: String, args: Vec<i64> },
+    Arithmetic { op: TokenTypes, lhs: i64, rhs: i64, result: i64 },
+    Printed { text: String },
+    ChallengeRun { function: String, cases: Vec<TestCase>, passed: usize },
+    RuntimeError { message: String },
+}
@@ -0,0 +1,341 @@
+// turns source back into canonical CAng: one statement per line, four space
+// indents in function bodies, single spaces around operators and only the
+// parentheses precedence needs. Comments are kept verbatim next to the
+// statement they were written beside
+use std::{collections::VecDeque, fmt, path::Path};
+
+use crate::{
+    parser::{is_identifier, Parser, StatementSpan},
+    tokenize_with_comments, Comment, Expr, TokenTypes,
+};
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatError {
+    pub message: String,
+    pub pos: Option<(usize, usize)>,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pos {
+            Some((line, col)) => write!(f, "{} (line {}, col {})", self.message, line, col),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// formatting its own output again changes nothing
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let (tokens, comments) = tokenize_with_comments(source);
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse_statements().map_err(|e| FormatError {
+        message: e.to_string(),
+        pos: parser.error_pos(),
+    })?;
+    // the parser stops at a missing `;`, which would silently drop the rest
+    if !parser.at_end() {
+        return Err(FormatError {
+            message: "Expected ';' between statements".to_string(),
+            pos: parser.peek().map(|tok| tok.pos),
+        });
+    }
+
+    let (own_line, trailing) = comments.into_iter().partition(|comment| comment.own_line);
+    let mut formatter = Formatter {
+        lines: source.lines().collect(),
+        spans: parser.statement_spans().to_vec(),
+        next_span: 0,
+        own_line,
+        trailing,
+        out: String::new(),
+        last_line: None,
+    };
+    let statements: Vec<Expr> = statements.into_iter().map(|(stmt, _)| stmt).collect();
+    formatter.statements(&statements, 0, None);
+    formatter.comments_before(usize::MAX, 0);
+    // trailing comments nothing claimed, which shouldn't happen, still aren't lost
+    while let Some(comment) = formatter.trailing.pop_front() {
+        formatter.out.push_str(&format!("//{}\n", comment.text));
+    }
+    Ok(formatter.out)
+}
+
+// a single line rendering, also how function bodies are described in events
+pub fn format_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => n.to_string(),
+        Expr::String(s) => quote(s),
+        Expr::Var(name) => name.clone(),
+        Expr::Binary(lhs, op, rhs) => {
+            let precedence = precedence(*op);
+            format!(
+                "{} {} {}",
+                operand(lhs, precedence, false),
+                operator(*op),
+                operand(rhs, precedence, true)
+            )
+        }
+        Expr::Let(name, value) => format!("let {} = {}", name, format_expr(value)),
+        Expr::FnDef(name, params, body) => {
+            let body = match body.as_ref() {
+                Expr::Block(statements) => statements.iter().map(format_expr).collect::<Vec<_>>().join("; "),
+                other => format_expr(other),
+            };
+            format!("fn {}({}) {{ {} }}", name, params.join(", "), body)
+        }
+        Expr::FnCall(name, args) => {
+            format!("{}({})", name, args.iter().map(format_expr).collect::<Vec<_>>().join(", "))
+        }
+        Expr::Block(statements) => statements.iter().map(format_expr).collect::<Vec<_>>().join("; "),
+        Expr::Print(value) => format!("print({})", format_expr(value)),
+        Expr::Import(spec, alias) => {
+            let stem = Path::new(spec).file_stem().and_then(|stem| stem.to_str());
+            let mut text = if is_identifier(spec) {
+                format!("import {}", spec)
+            } else {
+                format!("import {}", quote(spec))
+            };
+            if stem != Some(alias.as_str()) {
+                text.push_str(&format!(" as {}", alias));
+            }
+            text
+        }
+    }
+}
+
+fn precedence(op: TokenTypes) -> u8 {
+    match op {
+        TokenTypes::Plus | TokenTypes::Minus => 1,
+        TokenTypes::Star | TokenTypes::Slash => 2,
+        _ => 3,
+    }
+}
+
+fn operator(op: TokenTypes) -> &'static str {
+    match op {
+        TokenTypes::Plus => "+",
+        TokenTypes::Minus => "-",
+        TokenTypes::Star => "*",
+        TokenTypes::Slash => "/",
+        _ => "?",
+    }
+}
+
+// operators group to the left, so `a - (b - c)` keeps its parentheses
+fn operand(expr: &Expr, parent: u8, right: bool) -> String {
+    match expr {
+        Expr::Binary(_, op, _) if precedence(*op) < parent || (right && precedence(*op) == parent) => {
+            format!("({})", format_expr(expr))
+        }
+        _ => format_expr(expr),
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// how many statements the parser found nested inside this one
+pub(crate) fn nested_statements(stmt: &Expr) -> usize {
+    match stmt {
+        Expr::FnDef(_, _, body) => match body.as_ref() {
+            Expr::Block(statements) => statements.iter().map(|s| 1 + nested_statements(s)).sum(),
+            other => 1 + nested_statements(other),
+        },
+        _ => 0,
+    }
+}
+
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    spans: Vec<StatementSpan>, // in the order statements are written out
+    next_span: usize,
+    own_line: VecDeque<Comment>,
+    trailing: VecDeque<Comment>,
+    out: String,
+    last_line: Option<usize>, // source line of the last thing written
+}
+
+impl Formatter<'_> {
+    // `limit` is the line a body's closing brace is on, comments there are its own
+    fn statements(&mut self, statements: &[Expr], depth: usize, limit: Option<usize>) {
+        for (i, stmt) in statements.iter().enumerate() {
+            let terminator = if i + 1 == statements.len() { "" } else { ";" };
+            self.statement(stmt, depth, terminator, limit);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Expr, depth: usize, terminator: &str, limit: Option<usize>) {
+        let span = self.spans[self.next_span];
+        self.next_span += 1;
+        self.comments_before(span.start.0, depth);
+        self.blank_line_before(span.start.0);
+
+        let indent = INDENT.repeat(depth);
+        match stmt {
+            Expr::FnDef(name, params, body) if self.spans_lines(body, span) => {
+                let first_body_line = self.spans[self.next_span].start.0;
+                self.line(format!("{}fn {}({}) {{", indent, name, params.join(", ")), Some(first_body_line));
+                self.last_line = Some(span.start.0);
+
+                let body = match body.as_ref() {
+                    Expr::Block(statements) => statements.as_slice(),
+                    other => std::slice::from_ref(other),
+                };
+                self.statements(body, depth + 1, Some(span.end_line));
+                self.comments_before(span.end_line, depth + 1);
+                let until = self.next_start().map_or(limit, |next| Some(limit.map_or(next, |l| l.min(next))));
+                self.line(format!("{}}}{}", indent, terminator), until);
+            }
+            _ => {
+                self.next_span += nested_statements(stmt);
+                let until = self.next_start().map_or(limit, |next| Some(limit.map_or(next, |l| l.min(next))));
+                self.line(format!("{}{}{}", indent, format_expr(stmt), terminator), until);
+            }
+        }
+        self.last_line = Some(span.end_line);
+    }
+
+    fn next_start(&self) -> Option<usize> {
+        self.spans.get(self.next_span).map(|span| span.start.0)
+    }
+
+    // a function goes over several lines when its body has more than one
+    // statement, defines a function, or has comments inside it
+    fn spans_lines(&self, body: &Expr, span: StatementSpan) -> bool {
+        let inside = |comment: &Comment| comment.pos.0 > span.start.0 && comment.pos.0 <= span.end_line;
+        matches!(body, Expr::Block(_) | Expr::FnDef(..))
+            || self.own_line.iter().any(inside)
+            || self.trailing.iter().any(inside)
+    }
+
+    // writes a line with any end of line comments from before `until`
+    fn line(&mut self, text: String, until: Option<usize>) {
+        self.out.push_str(&text);
+        while let Some(comment) = self.trailing.front() {
+            if until.is_some_and(|until| comment.pos.0 >= until) {
+                break;
+            }
+            let comment = self.trailing.pop_front().unwrap();
+            self.out.push_str(&format!(" //{}", comment.text));
+        }
+        self.out.push('\n');
+    }
+
+    fn comments_before(&mut self, line: usize, depth: usize) {
+        while self.own_line.front().is_some_and(|comment| comment.pos.0 < line) {
+            let comment = self.own_line.pop_front().unwrap();
+            self.blank_line_before(comment.pos.0);
+            self.out.push_str(&format!("{}//{}\n", INDENT.repeat(depth), comment.text));
+            self.last_line = Some(comment.pos.0);
+        }
+    }
+
+    // keeps one blank line where the source had any, except right after a `{`
+    fn blank_line_before(&mut self, line: usize) {
+        let Some(last) = self.last_line else { return };
+        if self.out.is_empty() || self.out.ends_with("{\n") {
+            return;
+        }
+        let blank = (last + 1..line).any(|l| self.lines.get(l - 1).is_some_and(|text| text.trim().is_empty()));
+        if blank {
+            self.out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Vec<Expr> {
+        let statements = Parser::new(crate::tokenize(source)).parse_statements().unwrap();
+        statements.into_iter().map(|(stmt, _)| stmt).collect()
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        let source = "let   x=1+2*3;fn add(a,b){a+b};\n\n\nfn twice(n) { let m = n*2; m };print( \"hi\\n\" );(1+2)*3-(4-1);import \"lib/math.cang\" as m";
+        let expected = "\
+let x = 1 + 2 * 3;
+fn add(a, b) { a + b };
+
+fn twice(n) {
+    let m = n * 2;
+    m
+};
+print(\"hi\\n\");
+(1 + 2) * 3 - (4 - 1);
+import \"lib/math.cang\" as m
+";
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_expr(&parse("a - (b - c) / d")[0]), "a - (b - c) / d");
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let source = "\
+// setup
+let x = 1; // the answer, almost
+
+fn f(n) {
+  // double it
+  n * 2   // twice
+};
+fn g(n) { n } // identity
+// the end
+";
+        let expected = "\
+// setup
+let x = 1; // the answer, almost
+
+fn f(n) {
+    // double it
+    n * 2 // twice
+};
+fn g(n) { n } // identity
+// the end
+";
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_round_trips_are_stable() {
+        let sources = [
+            "let x = 1; x",
+            "fn f(a, b, c) { let t = a * (b + c); fn g(n) { n - 1 }; g(t) / 2 };\nf(1, 2, 3)",
+            "// only a comment",
+            "import math;\nimport \"lib/geo.cang\" as g; print(g.area(math.two))",
+            "print(\"tab\\tquote\\\"\");\n\n// gap above\n\n\n1 - (2 - (3 + 4)) * 5",
+            "fn f(n) { // header\n  n // body\n} // closing\n",
+        ];
+        for source in sources {
+            let formatted = format_source(source).unwrap();
+            assert_eq!(format_source(&formatted).unwrap(), formatted, "not idempotent: {}", source);
+            assert_eq!(parse(&formatted), parse(source), "changed meaning: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = format_source("let x = 1\nlet y = 2").unwrap_err();
+        assert_eq!(err.pos, Some((2, 3)));
+        assert!(format_source("let = 1").is_err());
+    }
+}
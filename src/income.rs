@@ -0,0 +1,300 @@
+// recurring coins that don't come from quests: run stipends and daily bonuses
+use std::{fs, io, path::Path};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    CoinManager, CoinReward, CoinType,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomeConfig {
+    pub stipend_every: u32, // successful runs per stipend, 0 turns it off
+    pub stipend: Vec<CoinReward>,
+    pub daily_bonus: Vec<CoinReward>,
+    pub max_streak_multiplier: u32, // daily bonus is multiplied by the streak up to this
+}
+
+impl Default for IncomeConfig {
+    fn default() -> Self {
+        Self {
+            stipend_every: 10,
+            stipend: vec![CoinReward {
+                coin_type: CoinType::Variable,
+                amount: 1,
+            }],
+            daily_bonus: vec![
+                CoinReward {
+                    coin_type: CoinType::Variable,
+                    amount: 2,
+                },
+                CoinReward {
+                    coin_type: CoinType::Function,
+                    amount: 1,
+                },
+            ],
+            max_streak_multiplier: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncomeSource {
+    Stipend { runs: u32 },
+    DailyBonus { streak: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomeRecord {
+    pub source: IncomeSource,
+    pub rewards: Vec<CoinReward>,
+    pub timestamp: u64,
+}
+
+pub struct IncomeTracker {
+    config: IncomeConfig,
+    clock: Box<dyn Clock>,
+    successful_runs: u32,
+    last_login_day: Option<u64>,
+    streak: u32,
+    history: Vec<IncomeRecord>,
+}
+
+impl IncomeTracker {
+    pub fn new(config: IncomeConfig) -> Self {
+        Self::with_clock(config, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(config: IncomeConfig, clock: Box<dyn Clock>) -> Self {
+        Self {
+            config,
+            clock,
+            successful_runs: 0,
+            last_login_day: None,
+            streak: 0,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &IncomeConfig {
+        &self.config
+    }
+
+    pub fn successful_runs(&self) -> u32 {
+        self.successful_runs
+    }
+
+    pub fn streak(&self) -> u32 {
+        self.streak
+    }
+
+    pub fn history(&self) -> &[IncomeRecord] {
+        &self.history
+    }
+
+    pub fn runs_until_stipend(&self) -> Option<u32> {
+        if self.config.stipend_every == 0 {
+            return None;
+        }
+        Some(self.config.stipend_every - self.successful_runs % self.config.stipend_every)
+    }
+
+    // call after every successful execution, pays out on every Nth run
+    pub fn record_run(&mut self, coin_manager: &mut CoinManager) -> Option<IncomeRecord> {
+        self.successful_runs += 1;
+
+        let every = self.config.stipend_every;
+        if every == 0 || !self.successful_runs.is_multiple_of(every) {
+            return None;
+        }
+
+        let rewards = self.config.stipend.clone();
+        Some(self.pay(
+            coin_manager,
+            IncomeSource::Stipend {
+                runs: self.successful_runs,
+            },
+            rewards,
+        ))
+    }
+
+    // once per calendar day (utc), consecutive days grow the streak
+    pub fn claim_daily_bonus(&mut self, coin_manager: &mut CoinManager) -> Option<IncomeRecord> {
+        let today = self.clock.today();
+
+        match self.last_login_day {
+            Some(day) if day >= today => return None,
+            Some(day) if day + 1 == today => self.streak += 1,
+            _ => self.streak = 1,
+        }
+        self.last_login_day = Some(today);
+
+        let multiplier = self.streak.min(self.config.max_streak_multiplier.max(1));
+        let rewards = self
+            .config
+            .daily_bonus
+            .iter()
+            .map(|r| CoinReward {
+                coin_type: r.coin_type,
+                amount: r.amount * multiplier,
+            })
+            .collect();
+
+        Some(self.pay(
+            coin_manager,
+            IncomeSource::DailyBonus {
+                streak: self.streak,
+            },
+            rewards,
+        ))
+    }
+
+    fn pay(
+        &mut self,
+        coin_manager: &mut CoinManager,
+        source: IncomeSource,
+        rewards: Vec<CoinReward>,
+    ) -> IncomeRecord {
+        // keep what was actually credited, reward_bonus included
+        let rewards = coin_manager.apply_rewards(&rewards);
+
+        let record = IncomeRecord {
+            source,
+            rewards,
+            timestamp: self.clock.now(),
+        };
+        self.history.push(record.clone());
+        record
+    }
+
+    // plain key=value lines so the file is easy to poke at by hand
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut contents = format!("runs={}\nstreak={}\n", self.successful_runs, self.streak);
+        if let Some(day) = self.last_login_day {
+            contents.push_str(&format!("last_login_day={}\n", day));
+        }
+        fs::write(path, contents)
+    }
+
+    // a missing file is a fresh start, not an error
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("bad value in '{}'", line));
+            match key.trim() {
+                "runs" => self.successful_runs = value.parse().map_err(|_| invalid())?,
+                "streak" => self.streak = value.parse().map_err(|_| invalid())?,
+                "last_login_day" => self.last_login_day = Some(value.parse().map_err(|_| invalid())?),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for IncomeTracker {
+    fn default() -> Self {
+        Self::new(IncomeConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::{ManualClock, SECONDS_PER_DAY},
+        Upgrade,
+    };
+
+    fn tracker(config: IncomeConfig) -> (IncomeTracker, ManualClock) {
+        let clock = ManualClock::new(100 * SECONDS_PER_DAY);
+        (IncomeTracker::with_clock(config, Box::new(clock.clone())), clock)
+    }
+
+    #[test]
+    fn test_stipend_every_n_runs() {
+        let config = IncomeConfig {
+            stipend_every: 3,
+            ..IncomeConfig::default()
+        };
+        let (mut income, _) = tracker(config);
+        let mut coins = CoinManager::with_balances(0, 0);
+
+        assert!(income.record_run(&mut coins).is_none());
+        assert!(income.record_run(&mut coins).is_none());
+        assert_eq!(income.runs_until_stipend(), Some(1));
+
+        let record = income.record_run(&mut coins).unwrap();
+        assert_eq!(record.source, IncomeSource::Stipend { runs: 3 });
+        assert_eq!(coins.get_balance(CoinType::Variable), 1);
+        assert_eq!(income.history().len(), 1);
+    }
+
+    #[test]
+    fn test_record_shows_reward_bonus() {
+        let config = IncomeConfig {
+            stipend_every: 1,
+            ..IncomeConfig::default()
+        };
+        let (mut income, _) = tracker(config);
+        let mut coins = CoinManager::with_balances(8, 0);
+        coins.buy_upgrade(Upgrade::RewardBonus).unwrap();
+
+        let record = income.record_run(&mut coins).unwrap();
+        let credited: u32 = record.rewards.iter().map(|r| r.amount).sum();
+        assert_eq!(credited as i64, coins.get_balance(CoinType::Variable) + coins.get_balance(CoinType::Function));
+    }
+
+    #[test]
+    fn test_daily_bonus_once_per_day_with_streak() {
+        let (mut income, clock) = tracker(IncomeConfig::default());
+        let mut coins = CoinManager::with_balances(0, 0);
+
+        let first = income.claim_daily_bonus(&mut coins).unwrap();
+        assert_eq!(first.source, IncomeSource::DailyBonus { streak: 1 });
+        assert_eq!(first.timestamp, 100 * SECONDS_PER_DAY);
+        assert!(income.claim_daily_bonus(&mut coins).is_none());
+
+        clock.advance(SECONDS_PER_DAY);
+        income.claim_daily_bonus(&mut coins).unwrap();
+        clock.advance(SECONDS_PER_DAY);
+        income.claim_daily_bonus(&mut coins).unwrap();
+        clock.advance(SECONDS_PER_DAY);
+        let capped = income.claim_daily_bonus(&mut coins).unwrap();
+
+        assert_eq!(income.streak(), 4);
+        assert_eq!(capped.rewards[0].amount, 2 * 3);
+        assert_eq!(coins.get_balance(CoinType::Variable), 2 + 4 + 6 + 6);
+
+        // skipping a day resets the streak
+        clock.advance(2 * SECONDS_PER_DAY);
+        let reset = income.claim_daily_bonus(&mut coins).unwrap();
+        assert_eq!(reset.source, IncomeSource::DailyBonus { streak: 1 });
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("cang_income_{}", std::process::id()));
+        let (mut income, clock) = tracker(IncomeConfig::default());
+        let mut coins = CoinManager::with_balances(0, 0);
+        income.claim_daily_bonus(&mut coins);
+        income.record_run(&mut coins);
+        income.save(&path).unwrap();
+
+        let mut restored = IncomeTracker::with_clock(IncomeConfig::default(), Box::new(clock.clone()));
+        restored.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.successful_runs(), 1);
+        assert_eq!(restored.streak(), 1);
+        assert!(restored.claim_daily_bonus(&mut coins).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+// where input() and input_int() read from, so tests and embedders can feed
+// programs lines instead of blocking on stdin
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, BufRead},
+    rc::Rc,
+};
+
+pub trait InputSource {
+    // the next line without its line ending, None once input runs out
+    fn read_line(&mut self) -> Option<String>;
+}
+
+fn trim_line_ending(mut line: String) -> String {
+    while line.ends_with(['\n', '\r']) {
+        line.pop();
+    }
+    line
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdinInput;
+
+impl InputSource for StdinInput {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(trim_line_ending(line)),
+        }
+    }
+}
+
+// any reader, e.g. the one the repl takes its commands from
+pub struct ReaderInput<R> {
+    reader: R,
+}
+
+impl<R: BufRead> ReaderInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: BufRead> InputSource for ReaderInput<R> {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(trim_line_ending(line)),
+        }
+    }
+}
+
+// clones share the same queue so a test can keep feeding lines in
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedInput {
+    lines: Rc<RefCell<VecDeque<String>>>,
+}
+
+impl ScriptedInput {
+    pub fn new<S: Into<String>>(lines: impl IntoIterator<Item = S>) -> Self {
+        let input = Self::default();
+        for line in lines {
+            input.push_line(line);
+        }
+        input
+    }
+
+    pub fn push_line(&self, line: impl Into<String>) {
+        self.lines.borrow_mut().push_back(line.into());
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.lines.borrow().len()
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn read_line(&mut self) -> Option<String> {
+        self.lines.borrow_mut().pop_front()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoInput;
+
+impl InputSource for NoInput {
+    fn read_line(&mut self) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_strip_line_endings() {
+        let mut reader = ReaderInput::new("12\r\nabc\n\nlast".as_bytes());
+        assert_eq!(reader.read_line().as_deref(), Some("12"));
+        assert_eq!(reader.read_line().as_deref(), Some("abc"));
+        assert_eq!(reader.read_line().as_deref(), Some(""));
+        assert_eq!(reader.read_line().as_deref(), Some("last"));
+        assert_eq!(reader.read_line(), None);
+
+        let scripted = ScriptedInput::new(["1"]);
+        let mut handle = scripted.clone();
+        scripted.push_line("2");
+        assert_eq!(handle.read_line().as_deref(), Some("1"));
+        assert_eq!(scripted.remaining(), 1);
+        assert_eq!(NoInput.read_line(), None);
+    }
+}
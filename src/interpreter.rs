@@ -0,0 +1,395 @@
+// the embedding api: build a session, eval strings, read back what they did
+use std::path::PathBuf;
+
+use crate::{
+    input::{InputSource, StdinInput},
+    modules::ModuleLoader,
+    natives::{Native, NativeRegistry},
+    output::{Output, StdoutOutput},
+    repl::{RunError, RunOutcome},
+    CoinManager, Expr, IncomeConfig, IncomeTracker, MeteringMode, Quest, QuestManager, QuestPackError,
+    Repl, ResourceValidator,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Number(i64),
+    Unit, // what print statements evaluate to
+}
+
+type QuestCallback = Box<dyn FnMut(&Quest)>;
+
+pub struct InterpreterBuilder {
+    coin_manager: CoinManager,
+    metering: MeteringMode,
+    quests: Vec<Quest>,
+    starter_quests: bool,
+    auto_accept: bool,
+    natives: NativeRegistry,
+    module_path: Vec<PathBuf>,
+    output: Box<dyn Output>,
+    input: Box<dyn InputSource>,
+    on_quest_completed: Option<QuestCallback>,
+}
+
+impl InterpreterBuilder {
+    pub fn coins(mut self, variable_coins: u32, function_coins: u32) -> Self {
+        self.coin_manager = CoinManager::with_balances(variable_coins, function_coins);
+        self
+    }
+
+    // for credit lines, upgrades or exchange rates set up by the host
+    pub fn coin_manager(mut self, coin_manager: CoinManager) -> Self {
+        self.coin_manager = coin_manager;
+        self
+    }
+
+    pub fn metering(mut self, metering: MeteringMode) -> Self {
+        self.metering = metering;
+        self
+    }
+
+    pub fn starter_quests(mut self) -> Self {
+        self.starter_quests = true;
+        self
+    }
+
+    pub fn quest(mut self, quest: Quest) -> Self {
+        self.quests.push(quest);
+        self
+    }
+
+    pub fn quests(mut self, quests: impl IntoIterator<Item = Quest>) -> Self {
+        self.quests.extend(quests);
+        self
+    }
+
+    // on by default; when off the host accepts quests itself through quests_mut
+    pub fn auto_accept(mut self, auto_accept: bool) -> Self {
+        self.auto_accept = auto_accept;
+        self
+    }
+
+    // added to the standard library, replacing a built-in of the same name
+    pub fn native(mut self, name: &str, native: Native) -> Self {
+        self.natives.register(name, native);
+        self
+    }
+
+    // for hosts that want to leave the standard library out
+    pub fn natives(mut self, natives: NativeRegistry) -> Self {
+        self.natives = natives;
+        self
+    }
+
+    // where imports are looked for after the working directory
+    pub fn module_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.module_path.push(dir.into());
+        self
+    }
+
+    // where print statements write, stdout unless set
+    pub fn output(mut self, output: Box<dyn Output>) -> Self {
+        self.output = output;
+        self
+    }
+
+    // what input() and input_int() read, stdin unless set
+    pub fn input(mut self, input: Box<dyn InputSource>) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn on_quest_completed(mut self, callback: impl FnMut(&Quest) + 'static) -> Self {
+        self.on_quest_completed = Some(Box::new(callback));
+        self
+    }
+
+    // fails if the quests don't fit together, e.g. a missing prerequisite
+    pub fn build(self) -> Result<Interpreter, QuestPackError> {
+        let mut quest_manager = QuestManager::new();
+        if self.starter_quests {
+            quest_manager.initialize_starter_quests();
+        }
+        quest_manager.load_quests(self.quests)?;
+
+        // running source never renders anything of the session's own, so
+        // its output only ever carries what programs print
+        let mut session = Repl::new().with_output(self.output).with_input(self.input);
+        session.validator = ResourceValidator::new(self.coin_manager).with_natives(self.natives);
+        for dir in self.module_path {
+            session.validator.modules_mut().add_search_dir(dir);
+        }
+        session.quest_manager = quest_manager;
+        session.metering = self.metering;
+        // stipends are a game mechanic, the host decides what coins are worth
+        session.income = IncomeTracker::new(IncomeConfig {
+            stipend_every: 0,
+            ..IncomeConfig::default()
+        });
+
+        let mut interpreter = Interpreter {
+            session,
+            auto_accept: self.auto_accept,
+            on_quest_completed: self.on_quest_completed,
+        };
+        interpreter.accept_available();
+        Ok(interpreter)
+    }
+}
+
+pub struct Interpreter {
+    session: Repl,
+    auto_accept: bool,
+    on_quest_completed: Option<QuestCallback>,
+}
+
+impl Interpreter {
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder {
+            coin_manager: CoinManager::new(),
+            metering: MeteringMode::Static,
+            quests: Vec::new(),
+            starter_quests: false,
+            auto_accept: true,
+            natives: NativeRegistry::with_stdlib(),
+            module_path: Vec::new(),
+            output: Box::new(StdoutOutput),
+            input: Box::new(StdinInput),
+            on_quest_completed: None,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::builder().build().expect("no quests to conflict")
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, RunError> {
+        let outcome = self.run(source);
+        match outcome.error {
+            Some(e) => Err(e),
+            None => Ok(outcome.value.map_or(Value::Unit, Value::Number)),
+        }
+    }
+
+    // eval with the full report: output, coins charged, quests completed
+    pub fn run(&mut self, source: &str) -> RunOutcome {
+        let outcome = self.session.run_source(source);
+
+        if let Some(callback) = self.on_quest_completed.as_mut() {
+            let quest_manager = &self.session.quest_manager;
+            for id in &outcome.completed_quests {
+                // repeatable quests stay active, so look there first
+                let quest = quest_manager
+                    .get_active_quests()
+                    .iter()
+                    .chain(quest_manager.get_completed_quests())
+                    .find(|quest| quest.id == *id);
+                if let Some(quest) = quest {
+                    callback(quest);
+                }
+            }
+        }
+
+        self.accept_available();
+        outcome
+    }
+
+    pub fn register_native(&mut self, name: &str, native: Native) {
+        self.session.validator.natives_mut().register(name, native);
+    }
+
+    pub fn global(&self, name: &str) -> Option<i64> {
+        match self.session.env.get(name) {
+            Some(Expr::Number(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // variables by name, in name order
+    pub fn globals(&self) -> Vec<(&str, i64)> {
+        let mut globals: Vec<(&str, i64)> = self
+            .session
+            .env
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Expr::Number(value) => Some((name.as_str(), *value)),
+                _ => None,
+            })
+            .collect();
+        globals.sort();
+        globals
+    }
+
+    // user defined functions by name, natives aren't included
+    pub fn functions(&self) -> Vec<&str> {
+        let mut functions: Vec<&str> = self
+            .session
+            .env
+            .iter()
+            .filter(|(_, value)| matches!(value, Expr::FnDef(..)))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        functions.sort();
+        functions
+    }
+
+    pub fn coins(&self) -> &CoinManager {
+        self.session.validator.coin_manager()
+    }
+
+    pub fn coins_mut(&mut self) -> &mut CoinManager {
+        self.session.validator.coin_manager_mut()
+    }
+
+    pub fn modules(&self) -> &ModuleLoader {
+        self.session.validator.modules()
+    }
+
+    pub fn quests(&self) -> &QuestManager {
+        &self.session.quest_manager
+    }
+
+    pub fn quests_mut(&mut self) -> &mut QuestManager {
+        &mut self.session.quest_manager
+    }
+
+    fn accept_available(&mut self) {
+        if !self.auto_accept {
+            return;
+        }
+        let quest_manager = &mut self.session.quest_manager;
+        let waiting: Vec<String> = quest_manager
+            .get_available_quests()
+            .iter()
+            .filter(|quest| !quest.is_accepted())
+            .map(|quest| quest.id.clone())
+            .collect();
+        for id in waiting {
+            let _ = quest_manager.accept_quest(&id);
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{Arity, BufferOutput, CoinReward, CoinType, QuestObjective, RunErrorKind, ScriptedInput};
+
+    #[test]
+    fn test_eval_values_and_globals() {
+        let output = BufferOutput::new();
+        let mut interpreter = Interpreter::builder()
+            .coins(2, 1)
+            .output(Box::new(output.clone()))
+            .build()
+            .unwrap();
+
+        assert_eq!(interpreter.eval("let x = 4; fn sq(n) { n * n }; sq(x)"), Ok(Value::Number(16)));
+        assert_eq!(interpreter.eval("print(x)"), Ok(Value::Unit));
+        assert_eq!(output.contents(), "4\n");
+
+        assert_eq!(interpreter.global("x"), Some(4));
+        assert_eq!(interpreter.globals(), vec![("x", 4)]);
+        assert_eq!(interpreter.functions(), vec!["sq"]);
+        assert_eq!(interpreter.coins().get_balance(CoinType::Variable), 1);
+
+        let err = interpreter.eval("let a = 1; let b = 2").unwrap_err();
+        assert_eq!(err.kind, RunErrorKind::Coins);
+
+        // overflow comes back as an error instead of taking the host down
+        let err = interpreter.eval("9999999999 * 9999999999").unwrap_err();
+        assert_eq!(err.kind, RunErrorKind::Runtime);
+        assert_eq!(interpreter.eval("9223372036854775807 + 1").unwrap_err().kind, RunErrorKind::Runtime);
+        assert_eq!(interpreter.eval("99999999999999999999").unwrap_err().kind, RunErrorKind::Parse);
+    }
+
+    #[test]
+    fn test_natives() {
+        let mut interpreter = Interpreter::builder()
+            .coins(5, 5)
+            .native("triple", Native::new(Arity::Exact(1), |args| Ok(args[0] * 3)))
+            .build()
+            .unwrap();
+        interpreter.register_native(
+            "halve",
+            Native::new(Arity::Exact(1), |args| match args[0] {
+                n if n % 2 != 0 => Err(format!("{} is odd", n)),
+                n => Ok(n / 2),
+            })
+            .with_cost(CoinType::Variable, 2),
+        );
+
+        assert_eq!(interpreter.eval("max(3, triple(3), 4) + halve(10)"), Ok(Value::Number(14)));
+        assert_eq!(interpreter.coins().get_balance(CoinType::Variable), 3);
+        let err = interpreter.eval("halve(3)").unwrap_err();
+        assert_eq!(err.message, "Runtime err: halve: 3 is odd");
+        let err = interpreter.eval("triple(1, 2)").unwrap_err();
+        assert_eq!(err.message, "Runtime err: Function 'triple' expects 1 argument, got 2");
+
+        // built-ins can't be shadowed by user functions, and trying costs nothing
+        let balances = interpreter.coins().get_all_balances().clone();
+        let err = interpreter.eval("let y = 1;\nfn max(a, b) { 0 }").unwrap_err();
+        assert_eq!(err.kind, RunErrorKind::Runtime);
+        assert_eq!(err.pos.map(|(line, _)| line), Some(2));
+        assert_eq!(interpreter.coins().get_all_balances(), &balances);
+        assert_eq!(interpreter.eval("max(1, 2)"), Ok(Value::Number(2)));
+    }
+
+    #[test]
+    fn test_input_from_host() {
+        let input = ScriptedInput::new(["3", ""]);
+        let output = BufferOutput::new();
+        let mut interpreter = Interpreter::builder()
+            .input(Box::new(input.clone()))
+            .output(Box::new(output.clone()))
+            .build()
+            .unwrap();
+
+        assert_eq!(interpreter.eval("input_int(\"how many? \") * 2"), Ok(Value::Number(6)));
+        assert_eq!(output.contents(), "how many? ");
+        // input() only says whether there was a line, blank or not
+        assert_eq!(interpreter.eval("input() + input()"), Ok(Value::Number(1)));
+        assert_eq!(input.remaining(), 0);
+
+        let err = interpreter.eval("input_int()").unwrap_err();
+        assert_eq!(err.message, "Runtime err: input_int: no more input");
+        assert_eq!(interpreter.eval("fn input() { 1 }").unwrap_err().kind, RunErrorKind::Runtime);
+    }
+
+    #[test]
+    fn test_quest_completed_callback() {
+        let quest = Quest::new(
+            "call_twice".to_string(),
+            "Call Twice".to_string(),
+            "Call a function".to_string(),
+            vec![QuestObjective::CallFunction { name: Some("double".to_string()) }],
+            vec![CoinReward {
+                coin_type: CoinType::Function,
+                amount: 2,
+            }],
+        );
+        let completed = Rc::new(RefCell::new(Vec::new()));
+        let seen = completed.clone();
+        let mut interpreter = Interpreter::builder()
+            .quest(quest)
+            .native("double", Native::new(Arity::Exact(1), |args| Ok(args[0] * 2)))
+            .on_quest_completed(move |quest| seen.borrow_mut().push(quest.id.clone()))
+            .build()
+            .unwrap();
+
+        interpreter.eval("1 + 1").unwrap();
+        assert!(completed.borrow().is_empty());
+        interpreter.eval("double(4)").unwrap();
+        assert_eq!(*completed.borrow(), vec!["call_twice"]);
+        assert_eq!(interpreter.coins().get_balance(CoinType::Function), 3 + 2);
+    }
+}
@@ -1,14 +1,52 @@
+pub mod achievements;
+pub mod challenge;
+pub mod clock;
 pub mod coin_manager;
+pub mod debugger;
+pub mod events;
+pub mod formatter;
+pub mod income;
+pub mod input;
+pub mod interpreter;
+pub mod linter;
+pub mod lsp;
+pub mod modules;
+pub mod natives;
+pub mod output;
 pub mod parser;
+pub mod protocol;
+pub mod quest_graph;
+pub mod quest_pack;
 pub mod quest_system;
 pub mod repl;
 pub mod resource_validator;
+pub mod stdlib;
 
-pub use coin_manager::{CoinError, CoinManager, CoinReward, CoinType};
+pub use achievements::{Achievement, AchievementStats, AchievementTracker, Milestone, Rarity};
+pub use challenge::{CaseResult, ChallengeReport, TestCase};
+pub use coin_manager::{CoinError, CoinManager, CoinReward, CoinType, ExchangeRate, ShopItem, Upgrade};
+pub use debugger::{Debugger, Frame};
+pub use events::ExecutionEvent;
+pub use formatter::{format_expr, format_source, FormatError};
+pub use interpreter::{Interpreter, InterpreterBuilder, Value};
+pub use income::{IncomeConfig, IncomeRecord, IncomeSource, IncomeTracker};
+pub use input::{InputSource, NoInput, ReaderInput, ScriptedInput, StdinInput};
+pub use linter::{Diagnostic, LintKind, Linter};
+pub use modules::{Module, ModuleError, ModuleLoader};
+pub use output::{BufferOutput, NullOutput, Output, StdoutOutput};
+pub use natives::{Arity, Native, NativeFn, NativeRegistry};
 pub use parser::Expr;
-pub use quest_system::{ExecutionContext, FunctionDef, Quest, QuestManager, QuestObjective, QuestProgress};
-pub use repl::Repl;
-pub use resource_validator::{CoinCost, ResourceValidator, ValidationError};
+pub use quest_graph::{QuestGraph, QuestNode, QuestStatus};
+pub use quest_pack::QuestPackError;
+pub use quest_system::{
+    ExecutionContext, FunctionDef, Hint, HistoryMark, ObjectiveProgress, ObjectiveScope, Quest, QuestError, QuestKind, QuestManager,
+    QuestObjective,
+    QuestProgress,
+};
+pub use repl::{Repl, RunError, RunErrorKind, RunOutcome};
+pub use resource_validator::{
+    CoinCost, CostEstimate, MeteringMode, ResourceValidator, StatementCost, ValidationError,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenTypes {
@@ -29,6 +67,8 @@ pub enum TokenTypes {
     Comma,
     Print,
     String,
+    Import,
+    Dot,
 }
 
 #[derive(Debug, Clone)]
@@ -38,8 +78,21 @@ pub struct Token {
     pub pos: (usize, usize),
 }
 
+// a `//` comment, which the parser never sees but the formatter keeps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String, // everything after the slashes
+    pub pos: (usize, usize),
+    pub own_line: bool, // false when it follows code on the same line
+}
+
 pub fn tokenize(input: &str) -> Vec<Token> {
+    tokenize_with_comments(input).0
+}
+
+pub fn tokenize_with_comments(input: &str) -> (Vec<Token>, Vec<Comment>) {
     let mut tokens: Vec<Token> = Vec::new();
+    let mut comments: Vec<Comment> = Vec::new();
     let mut chars = input.chars().peekable();
 
     let mut line = 1;
@@ -65,6 +118,7 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                     "let" => TokenTypes::Let,
                     "fn" => TokenTypes::Fn,
                     "print" => TokenTypes::Print,
+                    "import" => TokenTypes::Import,
                     _ => TokenTypes::Identifier,
                 };
 
@@ -122,6 +176,26 @@ pub fn tokenize(input: &str) -> Vec<Token> {
             '/' => {
                 chars.next();
                 col += 1;
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    col += 1;
+                    let start = (line, col);
+                    let mut text = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        text.push(c);
+                        chars.next();
+                        col += 1;
+                    }
+                    comments.push(Comment {
+                        text: text.trim_end().to_string(),
+                        pos: start,
+                        own_line: tokens.last().is_none_or(|tok| tok.pos.0 != line),
+                    });
+                    continue;
+                }
                 tokens.push(Token {
                     token_type: TokenTypes::Slash,
                     value: None,
@@ -191,6 +265,15 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                     pos: (line, col),
                 });
             }
+            '.' => {
+                chars.next();
+                col += 1;
+                tokens.push(Token {
+                    token_type: TokenTypes::Dot,
+                    value: None,
+                    pos: (line, col),
+                });
+            }
 
             ' ' | '\t' => {
                 chars.next();
@@ -249,5 +332,5 @@ pub fn tokenize(input: &str) -> Vec<Token> {
         }
     }
 
-    tokens
+    (tokens, comments)
 }
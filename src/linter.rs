@@ -0,0 +1,622 @@
+// warnings about code that runs but wastes coins: definitions nobody uses,
+// redefinitions that pay twice, and statements that can never run
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    formatter::nested_statements,
+    natives::NativeRegistry,
+    parser::{Parser, Statement, StatementSpan, INPUT_FUNCTIONS},
+    repl::{RunError, RunErrorKind},
+    resource_validator::{describe_costs, program_costs},
+    tokenize, CoinCost, CoinType, Expr, TokenTypes,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LintKind {
+    UnusedVariable,
+    UnusedFunction,
+    UnusedParameter,
+    Redefinition,
+    Shadowing,
+    ArityMismatch,
+    Unreachable,
+}
+
+impl LintKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LintKind::UnusedVariable => "unused_variable",
+            LintKind::UnusedFunction => "unused_function",
+            LintKind::UnusedParameter => "unused_parameter",
+            LintKind::Redefinition => "redefinition",
+            LintKind::Shadowing => "shadowing",
+            LintKind::ArityMismatch => "arity_mismatch",
+            LintKind::Unreachable => "unreachable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: LintKind,
+    pub message: String,
+    pub pos: (usize, usize),
+    pub saving: Vec<CoinCost>, // what fixing it would save, empty if nothing
+}
+
+impl Diagnostic {
+    // e.g. "saves 1 Variable coin and 2 Function coins"
+    pub fn saving_description(&self) -> Option<String> {
+        if self.saving.is_empty() {
+            return None;
+        }
+        Some(format!("saves {}", describe_costs(&self.saving)))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "warning[{}]: {} (line {}, col {})",
+            self.kind.code(),
+            self.message,
+            self.pos.0,
+            self.pos.1
+        )?;
+        if let Some(saving) = self.saving_description() {
+            write!(f, ", {}", saving)?;
+        }
+        Ok(())
+    }
+}
+
+fn merged(costs: Vec<CoinCost>) -> Vec<CoinCost> {
+    CoinType::ALL
+        .into_iter()
+        .filter_map(|coin_type| {
+            let amt: u32 = costs.iter().filter(|c| c.coin_type == coin_type).map(|c| c.amt).sum();
+            (amt > 0).then_some(CoinCost { coin_type, amt })
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Definition {
+    params: Option<usize>, // Some for functions
+    pos: (usize, usize),
+    used: bool,
+    cost: Vec<CoinCost>,
+}
+
+// every name an expression reads or calls, not looking inside function bodies
+fn references<'e>(expr: &'e Expr, names: &mut Vec<&'e str>) {
+    match expr {
+        Expr::Var(name) => names.push(name),
+        Expr::FnCall(name, args) => {
+            names.push(name);
+            args.iter().for_each(|arg| references(arg, names));
+        }
+        Expr::Binary(lhs, _, rhs) => {
+            references(lhs, names);
+            references(rhs, names);
+        }
+        Expr::Let(_, value) | Expr::Print(value) => references(value, names),
+        Expr::Block(statements) => statements.iter().for_each(|stmt| references(stmt, names)),
+        Expr::FnDef(..) | Expr::Number(_) | Expr::String(_) | Expr::Import(..) => {}
+    }
+}
+
+fn calls<'e>(expr: &'e Expr, found: &mut Vec<(&'e str, usize)>) {
+    match expr {
+        Expr::FnCall(name, args) => {
+            found.push((name, args.len()));
+            args.iter().for_each(|arg| calls(arg, found));
+        }
+        Expr::Binary(lhs, _, rhs) => {
+            calls(lhs, found);
+            calls(rhs, found);
+        }
+        Expr::Let(_, value) | Expr::Print(value) => calls(value, found),
+        Expr::Block(statements) => statements.iter().for_each(|stmt| calls(stmt, found)),
+        Expr::FnDef(..) | Expr::Var(_) | Expr::Number(_) | Expr::String(_) | Expr::Import(..) => {}
+    }
+}
+
+fn divides_by_zero(expr: &Expr) -> bool {
+    match expr {
+        Expr::Binary(_, TokenTypes::Slash, rhs) if **rhs == Expr::Number(0) => true,
+        Expr::Binary(lhs, _, rhs) => divides_by_zero(lhs) || divides_by_zero(rhs),
+        Expr::FnCall(_, args) => args.iter().any(divides_by_zero),
+        Expr::Let(_, value) | Expr::Print(value) => divides_by_zero(value),
+        _ => false,
+    }
+}
+
+pub struct Linter<'a> {
+    natives: &'a NativeRegistry,
+    globals: Option<&'a HashMap<String, Expr>>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(natives: &'a NativeRegistry) -> Self {
+        Self { natives, globals: None }
+    }
+
+    // what a repl session already defined. Unused definitions aren't reported
+    // then, since the next input may well use them
+    pub fn with_globals(mut self, globals: &'a HashMap<String, Expr>) -> Self {
+        self.globals = Some(globals);
+        self
+    }
+
+    pub fn lint_source(&self, source: &str) -> Result<Vec<Diagnostic>, RunError> {
+        let mut parser = Parser::new(tokenize(source));
+        let parsed = parser.parse_statements();
+        let statements = match parsed {
+            Ok(statements) if parser.at_end() => statements,
+            Ok(_) => {
+                return Err(RunError {
+                    kind: RunErrorKind::Parse,
+                    message: "Expected ';' between statements".to_string(),
+                    pos: parser.peek().map(|tok| tok.pos),
+                });
+            }
+            Err(e) => {
+                return Err(RunError {
+                    kind: RunErrorKind::Parse,
+                    message: e.to_string(),
+                    pos: parser.error_pos(),
+                });
+            }
+        };
+        Ok(self.lint(&statements, parser.statement_spans()))
+    }
+
+    // `spans` are the parser's, which also place statements inside functions
+    pub fn lint(&self, statements: &[Statement], spans: &[StatementSpan]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut defs: HashMap<&str, Definition> = HashMap::new();
+        let mut aliases: HashSet<&str> = HashSet::new();
+        // names functions read from outside themselves, which count as uses
+        let mut body_reads: Vec<(String, &str)> = Vec::new();
+        // the statement that always fails, and what it costs to have code after it
+        let mut failure: Option<((usize, usize), String)> = None;
+        let mut unreachable: Vec<(usize, usize)> = Vec::new();
+        let mut unreachable_cost = Vec::new();
+
+        // every function the program defines anywhere, for checking calls in bodies
+        let mut functions: HashMap<&str, usize> = HashMap::new();
+        let mut global_names: HashSet<&str> = HashSet::new();
+        for (stmt, _) in statements {
+            match stmt {
+                Expr::FnDef(name, params, _) => {
+                    functions.insert(name, params.len());
+                    global_names.insert(name);
+                }
+                Expr::Let(name, _) => {
+                    global_names.insert(name);
+                }
+                _ => {}
+            }
+        }
+
+        let mut span = 0;
+        let last = statements.len().saturating_sub(1);
+        for (i, (stmt, pos)) in statements.iter().enumerate() {
+            let stmt_spans = &spans[span.min(spans.len())..];
+            span += 1 + nested_statements(stmt);
+
+            if failure.is_some() {
+                unreachable.push(*pos);
+                unreachable_cost.extend(program_costs(stmt, self.natives));
+                continue;
+            }
+
+            let mut names = Vec::new();
+            references(stmt, &mut names);
+            for name in &names {
+                if let Some(def) = defs.get_mut(name) {
+                    def.used = true;
+                }
+            }
+            if let Some(reason) = self.check_statement(stmt, *pos, &defs, &aliases, &mut diagnostics) {
+                // it defines nothing, and what it would have is covered below
+                failure = Some((*pos, reason));
+                continue;
+            }
+
+            match stmt {
+                Expr::Let(name, _) | Expr::FnDef(name, ..) => {
+                    if let Some(earlier) = defs.get(name.as_str()).filter(|def| !def.used) {
+                        diagnostics.push(Diagnostic {
+                            kind: LintKind::Redefinition,
+                            message: format!(
+                                "'{}' is defined again before its definition on line {} is ever used",
+                                name, earlier.pos.0
+                            ),
+                            pos: *pos,
+                            saving: merged(earlier.cost.clone()),
+                        });
+                    }
+                    let params = match stmt {
+                        Expr::FnDef(_, params, _) => Some(params.len()),
+                        _ => None,
+                    };
+                    let definition = Definition {
+                        params,
+                        pos: *pos,
+                        // the last statement is the program's result, which is a use
+                        used: i == last,
+                        cost: program_costs(stmt, self.natives),
+                    };
+                    defs.insert(name, definition);
+                }
+                Expr::Import(_, alias) => {
+                    aliases.insert(alias);
+                }
+                _ => {}
+            }
+
+            if let Expr::FnDef(name, params, body) = stmt {
+                let context = FunctionContext {
+                    functions: &functions,
+                    globals: &global_names,
+                };
+                let free = self.lint_function(name, params, body, stmt_spans, &context, &mut diagnostics);
+                body_reads.extend(free.into_iter().map(|read| (read, name.as_str())));
+            }
+        }
+
+        if let (Some((pos, reason)), Some(first)) = (&failure, unreachable.first()) {
+            let count = unreachable.len();
+            diagnostics.push(Diagnostic {
+                kind: LintKind::Unreachable,
+                message: format!(
+                    "{} statement{} after line {} never run{}, because it always fails ({}), but static metering still charges for them",
+                    count,
+                    if count == 1 { "" } else { "s" },
+                    pos.0,
+                    if count == 1 { "s" } else { "" },
+                    reason
+                ),
+                pos: *first,
+                saving: merged(unreachable_cost),
+            });
+        }
+
+        for (read, reader) in &body_reads {
+            // a function calling itself doesn't make it used
+            if read != reader
+                && let Some(def) = defs.get_mut(read.as_str())
+            {
+                def.used = true;
+            }
+        }
+        if self.globals.is_none() {
+            for (name, def) in &defs {
+                if def.used {
+                    continue;
+                }
+                let (kind, what) = match def.params {
+                    Some(_) => (LintKind::UnusedFunction, "Function"),
+                    None => (LintKind::UnusedVariable, "Variable"),
+                };
+                diagnostics.push(Diagnostic {
+                    kind,
+                    message: format!("{} '{}' is never used", what, name),
+                    pos: def.pos,
+                    saving: merged(def.cost.clone()),
+                });
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.pos, d.kind.code()));
+        diagnostics
+    }
+
+    fn is_defined(&self, name: &str, defs: &HashMap<&str, Definition>, aliases: &HashSet<&str>) -> bool {
+        defs.contains_key(name)
+            || self.globals.is_some_and(|globals| globals.contains_key(name))
+            || name.split_once('.').is_some_and(|(alias, _)| aliases.contains(alias))
+    }
+
+    // the arity a call must have, None when it isn't known
+    fn expected_arity(&self, name: &str, params: Option<usize>) -> Option<(usize, usize)> {
+        if let Some(params) = params {
+            return Some((params, params));
+        }
+        if let Some(Expr::FnDef(_, params, _)) = self.globals.and_then(|globals| globals.get(name)) {
+            return Some((params.len(), params.len()));
+        }
+        if INPUT_FUNCTIONS.contains(&name) {
+            return Some((0, 1));
+        }
+        None
+    }
+
+    fn arity_problem(&self, name: &str, count: usize, params: Option<usize>) -> Option<String> {
+        if let Some((min, max)) = self.expected_arity(name, params) {
+            if count < min || count > max {
+                let expected = if min == max { min.to_string() } else { format!("{} to {}", min, max) };
+                return Some(format!("'{}' takes {} argument{}, not {}", name, expected, if max == 1 { "" } else { "s" }, count));
+            }
+        } else if let Some(native) = self.natives.get(name)
+            && !native.arity.accepts(count)
+        {
+            return Some(format!("'{}' takes {}, not {}", name, native.arity.description(), count));
+        }
+        None
+    }
+
+    // reports bad calls, and returns why the statement can't succeed if it can't
+    fn check_statement(
+        &self,
+        stmt: &Expr,
+        pos: (usize, usize),
+        defs: &HashMap<&str, Definition>,
+        aliases: &HashSet<&str>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<String> {
+        let mut failure = None;
+        if let Expr::FnDef(name, ..) = stmt
+            && (self.natives.contains(name) || INPUT_FUNCTIONS.contains(&name.as_str()))
+        {
+            failure = Some(format!("'{}' is a built-in and can't be redefined", name));
+        }
+
+        let mut found = Vec::new();
+        calls(stmt, &mut found);
+        for (name, count) in found {
+            let params = defs.get(name).and_then(|def| def.params);
+            if let Some(problem) = self.arity_problem(name, count, params) {
+                diagnostics.push(Diagnostic {
+                    kind: LintKind::ArityMismatch,
+                    message: problem.clone(),
+                    pos,
+                    saving: Vec::new(),
+                });
+                failure.get_or_insert(problem);
+            } else if !self.is_defined(name, defs, aliases) && !self.natives.contains(name) && !INPUT_FUNCTIONS.contains(&name) {
+                failure.get_or_insert(format!("there's no function '{}' yet", name));
+            }
+        }
+
+        let mut read_vars = Vec::new();
+        collect_vars(stmt, &mut read_vars);
+        for name in read_vars {
+            if !self.is_defined(name, defs, aliases) {
+                failure.get_or_insert(format!("'{}' isn't defined yet", name));
+            }
+        }
+        if divides_by_zero(stmt) {
+            failure.get_or_insert("it divides by zero".to_string());
+        }
+        failure
+    }
+
+    // checks a function's body and returns the outside names it reads
+    fn lint_function(
+        &self,
+        name: &str,
+        params: &[String],
+        body: &Expr,
+        spans: &[StatementSpan],
+        context: &FunctionContext,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<String> {
+        let pos = spans.first().map(|span| span.start).unwrap_or_default();
+        let statements = match body {
+            Expr::Block(statements) => statements.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+
+        for param in params {
+            if context.globals.contains(param.as_str()) || self.globals.is_some_and(|globals| globals.contains_key(param)) {
+                diagnostics.push(Diagnostic {
+                    kind: LintKind::Shadowing,
+                    message: format!("Parameter '{}' of '{}' hides the global '{}'", param, name, param),
+                    pos,
+                    saving: Vec::new(),
+                });
+            }
+        }
+
+        let mut params_used: HashSet<&str> = HashSet::new();
+        let mut locals: Vec<Local> = Vec::new();
+        let mut free = Vec::new();
+        let mut span = 1;
+        for (i, stmt) in statements.iter().enumerate() {
+            let stmt_pos = spans.get(span).map(|span| span.start).unwrap_or(pos);
+            let stmt_spans = spans.get(span..).unwrap_or_default();
+            span += 1 + nested_statements(stmt);
+
+            let mut names: Vec<String> = Vec::new();
+            let mut direct = Vec::new();
+            references(stmt, &mut direct);
+            names.extend(direct.into_iter().map(str::to_string));
+            if let Expr::FnDef(inner, inner_params, inner_body) = stmt {
+                names.extend(self.lint_function(inner, inner_params, inner_body, stmt_spans, context, diagnostics));
+            }
+
+            for read in names {
+                if let Some(local) = locals.iter_mut().rev().find(|local| local.name == read) {
+                    local.used = true;
+                } else if let Some(param) = params.iter().find(|param| **param == read) {
+                    params_used.insert(param);
+                } else {
+                    free.push(read);
+                }
+            }
+
+            let mut found = Vec::new();
+            calls(stmt, &mut found);
+            for (callee, count) in found {
+                let bound = locals.iter().any(|local| local.name == callee) || params.iter().any(|param| param == callee);
+                if bound {
+                    continue;
+                }
+                if let Some(problem) = self.arity_problem(callee, count, context.functions.get(callee).copied()) {
+                    diagnostics.push(Diagnostic {
+                        kind: LintKind::ArityMismatch,
+                        message: format!("{} (in '{}')", problem, name),
+                        pos: stmt_pos,
+                        saving: Vec::new(),
+                    });
+                }
+            }
+
+            if let Expr::Let(local, _) | Expr::FnDef(local, ..) = stmt {
+                if context.globals.contains(local.as_str()) || self.globals.is_some_and(|globals| globals.contains_key(local)) {
+                    diagnostics.push(Diagnostic {
+                        kind: LintKind::Shadowing,
+                        message: format!("'{}' inside '{}' hides the global '{}'", local, name, local),
+                        pos: stmt_pos,
+                        saving: Vec::new(),
+                    });
+                }
+                // the last statement is what the function returns, which is a use
+                let returned = i + 1 == statements.len();
+                locals.push(Local {
+                    name: local,
+                    pos: stmt_pos,
+                    used: returned,
+                    cost: program_costs(stmt, self.natives),
+                });
+            }
+        }
+
+        for param in params {
+            if !params_used.contains(param.as_str()) {
+                diagnostics.push(Diagnostic {
+                    kind: LintKind::UnusedParameter,
+                    message: format!("Parameter '{}' of '{}' is never used", param, name),
+                    pos,
+                    saving: Vec::new(),
+                });
+            }
+        }
+        for local in locals {
+            if !local.used {
+                diagnostics.push(Diagnostic {
+                    kind: LintKind::UnusedVariable,
+                    message: format!("'{}' is set inside '{}' but never used, and it's paid for on every call", local.name, name),
+                    pos: local.pos,
+                    saving: merged(local.cost),
+                });
+            }
+        }
+        free
+    }
+}
+
+// a definition inside a function body, in the order they're made
+struct Local<'e> {
+    name: &'e str,
+    pos: (usize, usize),
+    used: bool,
+    cost: Vec<CoinCost>,
+}
+
+struct FunctionContext<'c> {
+    functions: &'c HashMap<&'c str, usize>,
+    globals: &'c HashSet<&'c str>,
+}
+
+fn collect_vars<'e>(expr: &'e Expr, names: &mut Vec<&'e str>) {
+    match expr {
+        Expr::Var(name) => names.push(name),
+        Expr::FnCall(_, args) => args.iter().for_each(|arg| collect_vars(arg, names)),
+        Expr::Binary(lhs, _, rhs) => {
+            collect_vars(lhs, names);
+            collect_vars(rhs, names);
+        }
+        Expr::Let(_, value) | Expr::Print(value) => collect_vars(value, names),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(source: &str) -> Vec<Diagnostic> {
+        let natives = NativeRegistry::with_stdlib();
+        Linter::new(&natives).lint_source(source).unwrap()
+    }
+
+    fn kinds(diagnostics: &[Diagnostic]) -> Vec<LintKind> {
+        diagnostics.iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn test_unused_definitions() {
+        let diagnostics = lint("let x = 1;\nfn f(a, b) { let t = a; a };\nfn g(n) { g(n) };\nlet y = 2;\ny");
+        assert_eq!(
+            kinds(&diagnostics),
+            vec![
+                LintKind::UnusedVariable,
+                LintKind::UnusedFunction,
+                LintKind::UnusedParameter,
+                LintKind::UnusedVariable,
+                LintKind::UnusedFunction,
+            ]
+        );
+        assert_eq!(diagnostics[0].message, "Variable 'x' is never used");
+        assert_eq!(diagnostics[0].saving_description().as_deref(), Some("saves 1 Variable coin"));
+        assert!(diagnostics[3].message.contains("'t' is set inside 'f'"));
+        // calling itself doesn't count, and the final statement is the result
+        assert!(diagnostics[4].message.contains("'g'"));
+        assert!(lint("let x = 1").is_empty());
+        assert!(lint("fn sq(n) { n * n }; let four = sq(2); print(four)").is_empty());
+    }
+
+    #[test]
+    fn test_redefinition_and_shadowing() {
+        let diagnostics = lint("fn f(n) { n };\nfn f(n) { n + 1 };\nlet total = 1;\nfn g(total) { total };\nf(g(2))");
+        // the first f is reported once, as the redefinition, and g's parameter
+        // means the global total is never read
+        assert_eq!(kinds(&diagnostics), vec![LintKind::Redefinition, LintKind::UnusedVariable, LintKind::Shadowing]);
+        assert_eq!(diagnostics[0].pos.0, 2);
+        assert_eq!(diagnostics[0].saving, vec![CoinCost { coin_type: CoinType::Function, amt: 1 }]);
+        // the earlier definition was used first, so nothing was wasted
+        assert!(lint("let x = 1; print(x); let x = 2; x").is_empty());
+    }
+
+    #[test]
+    fn test_arity_and_unreachable() {
+        let diagnostics = lint("fn add(a, b) { a + b };\nprint(add(1));\nlet x = sqrt(1, 2);\nfn h(n) { add(n) };\nx");
+        // nothing after the failing call is checked further
+        assert_eq!(kinds(&diagnostics), vec![LintKind::ArityMismatch, LintKind::Unreachable]);
+        assert_eq!(diagnostics[0].message, "'add' takes 2 arguments, not 1");
+        assert!(diagnostics[1].message.starts_with("3 statements after line 2 never run"));
+        assert_eq!(
+            diagnostics[1].saving_description().as_deref(),
+            Some("saves 1 Variable coin and 1 Function coin")
+        );
+        let diagnostics = lint("fn add(a, b) { a + b };\nfn h(n) { add(n) };\nh(1)");
+        assert_eq!(kinds(&diagnostics), vec![LintKind::ArityMismatch]);
+        assert_eq!(diagnostics[0].message, "'add' takes 2 arguments, not 1 (in 'h')");
+        assert_eq!(diagnostics[0].pos.0, 2);
+
+        assert_eq!(kinds(&lint("print(missing);\nlet y = 1 / 0")), vec![LintKind::Unreachable]);
+        assert_eq!(kinds(&lint("let y = 1 / 0;\nprint(1)")), vec![LintKind::Unreachable]);
+        assert!(lint("print(input_int(\"n? \") + abs(0 - 1))").is_empty());
+        assert_eq!(kinds(&lint("input(1, 2)")), vec![LintKind::ArityMismatch]);
+    }
+
+    #[test]
+    fn test_repl_globals() {
+        let natives = NativeRegistry::with_stdlib();
+        let mut globals = HashMap::new();
+        globals.insert("total".to_string(), Expr::Number(3));
+        let linter = Linter::new(&natives).with_globals(&globals);
+        // earlier input defined it, and later input may use what this defines
+        assert!(linter.lint_source("let doubled = total * 2; fn f(n) { n }").unwrap().is_empty());
+        assert_eq!(kinds(&linter.lint_source("fn f(total) { total }").unwrap()), vec![LintKind::Shadowing]);
+        assert_eq!(linter.lint_source("let = 1").unwrap_err().kind, RunErrorKind::Parse);
+    }
+}
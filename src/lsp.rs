@@ -0,0 +1,974 @@
+// a language server for editors, spoken over stdio: Content-Length framed
+// JSON-RPC in and out. Documents are synced whole and reanalysed on every
+// change, they're small enough that nothing smarter is needed
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    formatter::format_expr,
+    linter::{LintKind, Linter},
+    natives::NativeRegistry,
+    parser::{input_summary, Parser, INPUT_FUNCTIONS},
+    resource_validator::describe_costs,
+    tokenize_with_comments, CoinCost, CoinManager, CoinType, Comment, Expr, ResourceValidator, Token, TokenTypes,
+};
+
+const KEYWORDS: [&str; 4] = ["let", "fn", "print", "import"];
+
+// the order here is the index semantic tokens refer to
+const TOKEN_TYPES: [&str; 9] = [
+    "keyword", "function", "variable", "parameter", "namespace", "number", "string", "operator", "comment",
+];
+const TOKEN_MODIFIERS: [&str; 2] = ["declaration", "defaultLibrary"];
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// reads one framed message, None once the client hangs up. A message
+// without a length or whose body isn't json is an InvalidData error, after
+// which the next message can still be read
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut headers = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            // a stray blank line before any header isn't the end of one
+            if headers {
+                break;
+            }
+            continue;
+        }
+        headers = true;
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header"));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+// answers messages until `exit`, returning the process exit code: 0 when the
+// client asked for a shutdown first, as the protocol says
+pub fn serve(mut input: impl BufRead, output: &mut impl Write) -> io::Result<i32> {
+    let mut server = LanguageServer::new();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            // a garbled message gets an error back, it doesn't end the session
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                write_message(
+                    output,
+                    &json!({
+                        "jsonrpc": "2.0",
+                        "id": null,
+                        "error": { "code": PARSE_ERROR, "message": e.to_string() },
+                    }),
+                )?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        for reply in server.handle(&message) {
+            write_message(output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(if server.shutdown { 0 } else { 1 })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Function,
+    Variable,
+    Parameter,
+    Module,
+}
+
+#[derive(Debug)]
+struct Symbol {
+    name: String,
+    kind: SymbolKind,
+    token: usize,           // the name where it's defined
+    scope: (usize, usize),  // the tokens it can be seen from, end excluded
+    visible_from: usize,    // lets only exist once their statement is done
+    detail: String,         // e.g. "fn add(a, b)"
+    definition: Option<Expr>,
+}
+
+// everything worked out about one version of a document
+struct Document {
+    lines: Vec<String>,
+    tokens: Vec<Token>,
+    starts: Vec<usize>, // the column each token starts at, counted from 0
+    comments: Vec<Comment>,
+    symbols: Vec<Symbol>,
+    defined_at: HashMap<usize, usize>, // name token to its symbol
+}
+
+impl Document {
+    fn new(text: &str) -> Self {
+        let (tokens, comments) = tokenize_with_comments(text);
+        let lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+        let starts = tokens.iter().map(|tok| token_start(tok, &lines)).collect();
+        let mut document = Self {
+            lines,
+            tokens,
+            starts,
+            comments,
+            symbols: Vec::new(),
+            defined_at: HashMap::new(),
+        };
+        document.collect_symbols();
+        document.attach_definitions();
+        document
+    }
+
+    fn kind(&self, i: usize) -> Option<TokenTypes> {
+        self.tokens.get(i).map(|tok| tok.token_type)
+    }
+
+    fn name(&self, i: usize) -> Option<&str> {
+        self.tokens
+            .get(i)
+            .filter(|tok| tok.token_type == TokenTypes::Identifier)
+            .and_then(|tok| tok.value.as_deref())
+    }
+
+    // the `}` closing the `{` at `open`, or the end when it's missing
+    fn closing_brace(&self, open: usize) -> usize {
+        let mut depth = 0;
+        for (i, tok) in self.tokens.iter().enumerate().skip(open) {
+            match tok.token_type {
+                TokenTypes::LCurly => depth += 1,
+                TokenTypes::RCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.tokens.len()
+    }
+
+    // the `;` or `}` ending the statement that starts at `start`
+    fn statement_end(&self, start: usize) -> usize {
+        let mut depth = 0i32;
+        for (i, tok) in self.tokens.iter().enumerate().skip(start) {
+            match tok.token_type {
+                TokenTypes::LCurly | TokenTypes::LParen => depth += 1,
+                TokenTypes::RParen => depth -= 1,
+                TokenTypes::RCurly if depth == 0 => return i,
+                TokenTypes::RCurly => depth -= 1,
+                TokenTypes::Semicolon if depth == 0 => return i,
+                _ => {}
+            }
+        }
+        self.tokens.len()
+    }
+
+    fn define(&mut self, name: &str, kind: SymbolKind, token: usize, scope: (usize, usize), visible_from: usize, detail: String) {
+        self.defined_at.insert(token, self.symbols.len());
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            token,
+            scope,
+            visible_from,
+            detail,
+            definition: None,
+        });
+    }
+
+    // works from tokens rather than the tree so a document with a syntax
+    // error still gets navigation up to and around it
+    fn collect_symbols(&mut self) {
+        let mut scopes = vec![(0, self.tokens.len())];
+        for i in 0..self.tokens.len() {
+            let scope = *scopes.last().unwrap();
+            match self.tokens[i].token_type {
+                TokenTypes::Fn => {
+                    let Some(name) = self.name(i + 1).map(str::to_string) else { continue };
+                    let mut params = Vec::new();
+                    let mut j = i + 2;
+                    if self.kind(j) == Some(TokenTypes::LParen) {
+                        j += 1;
+                        while let Some(kind) = self.kind(j) {
+                            match kind {
+                                TokenTypes::Identifier => params.push(j),
+                                TokenTypes::Comma => {}
+                                _ => break,
+                            }
+                            j += 1;
+                        }
+                    }
+                    let names: Vec<String> = params.iter().filter_map(|&p| self.name(p).map(str::to_string)).collect();
+                    let signature = format!("fn {}({})", name, names.join(", "));
+                    self.define(&name, SymbolKind::Function, i + 1, scope, i, signature.clone());
+
+                    // parameters live in the body, the `{` after the `)`
+                    let body = match self.kind(j + 1) {
+                        Some(TokenTypes::LCurly) => (j + 2, self.closing_brace(j + 1)),
+                        _ => (j, j),
+                    };
+                    for (p, param) in params.into_iter().zip(names) {
+                        self.define(&param, SymbolKind::Parameter, p, body, p, format!("{}: parameter of {}", param, signature));
+                    }
+                }
+                TokenTypes::Let => {
+                    let Some(name) = self.name(i + 1).map(str::to_string) else { continue };
+                    let visible_from = self.statement_end(i);
+                    self.define(&name, SymbolKind::Variable, i + 1, scope, visible_from, format!("let {}", name));
+                }
+                TokenTypes::Import => {
+                    let Some(spec) = self.tokens.get(i + 1).and_then(|tok| tok.value.clone()) else { continue };
+                    let (token, alias) = match (self.name(i + 2), self.name(i + 3)) {
+                        (Some("as"), Some(alias)) => (i + 3, alias.to_string()),
+                        _ => {
+                            let stem = Path::new(&spec).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+                            (i + 1, stem.to_string())
+                        }
+                    };
+                    let visible_from = self.statement_end(i);
+                    self.define(&alias, SymbolKind::Module, token, scope, visible_from, format!("module {} (import {:?})", alias, spec));
+                }
+                TokenTypes::LCurly => scopes.push((i + 1, self.closing_brace(i))),
+                TokenTypes::RCurly if scopes.len() > 1 => {
+                    scopes.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the parsed definitions, for costs and values. Both a `let` and a `fn`
+    // are statements, so the tree visited in order meets them in the same
+    // order as their keywords appear
+    fn attach_definitions(&mut self) {
+        let mut parser = Parser::new(self.tokens.clone());
+        let Ok(statements) = parser.parse_statements() else { return };
+        if !parser.at_end() {
+            return;
+        }
+        let mut definitions = Vec::new();
+        for (stmt, _) in &statements {
+            collect_definitions(stmt, &mut definitions);
+        }
+
+        let keywords = self
+            .tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, tok)| matches!(tok.token_type, TokenTypes::Let | TokenTypes::Fn))
+            .map(|(i, _)| i + 1);
+        for (token, definition) in keywords.zip(definitions) {
+            if let Some(&symbol) = self.defined_at.get(&token) {
+                self.symbols[symbol].definition = Some(definition.clone());
+            }
+        }
+    }
+
+    // which definition a name refers to, the innermost one in scope
+    fn resolve(&self, i: usize) -> Option<&Symbol> {
+        if let Some(&symbol) = self.defined_at.get(&i) {
+            return self.symbols.get(symbol);
+        }
+        // `m.square` is the module's business
+        if self.kind(i.wrapping_sub(1)) == Some(TokenTypes::Dot) {
+            return None;
+        }
+        let name = self.name(i)?;
+        let in_scope = self
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.name == name && symbol.scope.0 <= i && i < symbol.scope.1);
+        in_scope
+            .clone()
+            .filter(|symbol| symbol.visible_from <= i)
+            .max_by_key(|symbol| (symbol.scope.0, symbol.visible_from))
+            // used too early still points somewhere useful
+            .or_else(|| in_scope.min_by_key(|symbol| symbol.visible_from))
+    }
+
+    // every name usable by code typed after the first `before` tokens
+    fn names_in_scope(&self, before: usize) -> Vec<&Symbol> {
+        let mut names: HashMap<&str, &Symbol> = HashMap::new();
+        for symbol in &self.symbols {
+            let (start, end) = symbol.scope;
+            if !(start <= before && before <= end && symbol.visible_from < before) {
+                continue;
+            }
+            let innermost = |other: &&Symbol| (symbol.scope.0, symbol.visible_from) >= (other.scope.0, other.visible_from);
+            if names.get(symbol.name.as_str()).is_none_or(innermost) {
+                names.insert(&symbol.name, symbol);
+            }
+        }
+        let mut names: Vec<&Symbol> = names.into_values().collect();
+        names.sort_by(|a, b| a.name.cmp(&b.name));
+        names
+    }
+
+    fn range(&self, i: usize) -> Value {
+        let tok = &self.tokens[i];
+        self.range_between(tok.pos.0, self.starts[i], tok.pos.1)
+    }
+
+    fn range_between(&self, line: usize, start: usize, end: usize) -> Value {
+        json!({ "start": self.position(line, start), "end": self.position(line, end) })
+    }
+
+    // lsp positions count lines from 0 and columns in utf-16 units
+    fn position(&self, line: usize, col: usize) -> Value {
+        let text = self.lines.get(line.saturating_sub(1)).map(String::as_str).unwrap_or_default();
+        let character: usize = text.chars().take(col).map(char::len_utf16).sum();
+        json!({ "line": line.saturating_sub(1), "character": character })
+    }
+
+    // the (line, column) a client position points at, in the tokenizer's terms
+    fn source_position(&self, position: &Value) -> Option<(usize, usize)> {
+        let line = position.get("line")?.as_u64()? as usize;
+        let character = position.get("character")?.as_u64()? as usize;
+        let text = self.lines.get(line)?;
+        let mut units = 0;
+        let mut col = 0;
+        for c in text.chars() {
+            if units >= character {
+                break;
+            }
+            units += c.len_utf16();
+            col += 1;
+        }
+        Some((line + 1, col))
+    }
+
+    // the token under the cursor, which includes the cursor just after it
+    fn token_at(&self, (line, col): (usize, usize)) -> Option<usize> {
+        let on_line = |i: &usize| self.tokens[*i].pos.0 == line && self.starts[*i] <= col && col <= self.tokens[*i].pos.1;
+        let mut found = (0..self.tokens.len()).filter(on_line);
+        let first = found.next()?;
+        // between two tokens that touch, prefer the name
+        match found.next() {
+            Some(second) if self.kind(second) == Some(TokenTypes::Identifier) => Some(second),
+            _ => Some(first),
+        }
+    }
+
+    // how many tokens come before the cursor
+    fn tokens_before(&self, (line, col): (usize, usize)) -> usize {
+        self.tokens
+            .iter()
+            .zip(&self.starts)
+            .take_while(|(tok, start)| tok.pos.0 < line || (tok.pos.0 == line && **start < col))
+            .count()
+    }
+}
+
+fn collect_definitions<'e>(expr: &'e Expr, found: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Let(..) => found.push(expr),
+        Expr::FnDef(_, _, body) => {
+            found.push(expr);
+            match body.as_ref() {
+                Expr::Block(statements) => statements.iter().for_each(|stmt| collect_definitions(stmt, found)),
+                other => collect_definitions(other, found),
+            }
+        }
+        _ => {}
+    }
+}
+
+// tokens only record where they end, so the start is worked out from the text
+fn token_start(tok: &Token, lines: &[String]) -> usize {
+    let end = tok.pos.1;
+    let width = match tok.token_type {
+        TokenTypes::String => {
+            // back from the closing quote to the first quote no backslash escapes
+            let chars: Vec<char> = lines.get(tok.pos.0 - 1).map(|line| line.chars().collect()).unwrap_or_default();
+            let mut start = None;
+            let mut j = end.saturating_sub(1).min(chars.len());
+            while j > 0 {
+                j -= 1;
+                let escaped = chars[..j].iter().rev().take_while(|c| **c == '\\').count() % 2 == 1;
+                if chars[j] == '"' && !escaped {
+                    start = Some(j);
+                    break;
+                }
+            }
+            return start.unwrap_or_else(|| end.saturating_sub(tok.value.as_deref().unwrap_or_default().chars().count() + 2));
+        }
+        _ => tok.value.as_deref().map_or(1, |value| value.chars().count()),
+    };
+    end.saturating_sub(width)
+}
+
+pub struct LanguageServer {
+    documents: HashMap<String, Document>,
+    validator: ResourceValidator, // prices definitions, and knows the built-ins
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            validator: ResourceValidator::new(CoinManager::new()).with_natives(NativeRegistry::with_stdlib()),
+            shutdown: false,
+            exited: false,
+        }
+    }
+
+    // the responses and notifications one message produces
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message.get("method").and_then(Value::as_str).unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => Some(Ok(self.capabilities())),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Ok(Value::Null))
+            }
+            "textDocument/hover" => Some(self.with_document(&params, Self::hover)),
+            "textDocument/definition" => Some(self.with_document(&params, Self::definition)),
+            "textDocument/completion" => Some(self.with_document(&params, Self::completion)),
+            "textDocument/semanticTokens/full" => Some(self.with_document(&params, |server, document, _| {
+                Ok(server.semantic_tokens(document))
+            })),
+            _ => None,
+        };
+        if let Some(result) = result {
+            let id = id.unwrap_or(Value::Null);
+            return vec![match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+            }];
+        }
+
+        let uri = params.pointer("/textDocument/uri").and_then(Value::as_str).map(str::to_string);
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params.pointer("/textDocument/text").and_then(Value::as_str).unwrap_or_default();
+                self.open(uri, text)
+            }
+            // only whole document syncing is offered, so the last change is all of it
+            ("textDocument/didChange", Some(uri)) => {
+                let changes = params.get("contentChanges").and_then(Value::as_array);
+                match changes.and_then(|changes| changes.last()).and_then(|change| change.get("text")).and_then(Value::as_str) {
+                    Some(text) => self.open(uri, text),
+                    None => Vec::new(),
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                vec![publish(&uri, Vec::new())]
+            }
+            ("exit", _) => {
+                self.exited = true;
+                Vec::new()
+            }
+            // requests must be answered, notifications like `initialized` needn't be
+            _ => match id {
+                Some(id) => vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": METHOD_NOT_FOUND, "message": format!("Unknown method '{}'", method) },
+                })],
+                None => Vec::new(),
+            },
+        }
+    }
+
+    fn capabilities(&self) -> Value {
+        json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "hoverProvider": true,
+                "definitionProvider": true,
+                "completionProvider": { "triggerCharacters": ["."] },
+                "semanticTokensProvider": {
+                    "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": TOKEN_MODIFIERS },
+                    "full": true,
+                },
+            },
+            "serverInfo": { "name": "cang-lsp", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    fn with_document(
+        &self,
+        params: &Value,
+        answer: impl FnOnce(&Self, &Document, Option<(usize, usize)>) -> Result<Value, (i64, String)>,
+    ) -> Result<Value, (i64, String)> {
+        let uri = params
+            .pointer("/textDocument/uri")
+            .and_then(Value::as_str)
+            .ok_or((INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or((INVALID_PARAMS, format!("'{}' isn't open", uri)))?;
+        let position = params.get("position").and_then(|position| document.source_position(position));
+        answer(self, document, position)
+    }
+
+    fn open(&mut self, uri: String, text: &str) -> Vec<Value> {
+        let document = Document::new(text);
+        let diagnostics = self.diagnostics(&document, text);
+        self.documents.insert(uri.clone(), document);
+        vec![publish(&uri, diagnostics)]
+    }
+
+    // a syntax error, or else whatever the linter finds
+    fn diagnostics(&self, document: &Document, text: &str) -> Vec<Value> {
+        let find = |pos: (usize, usize)| document.tokens.iter().position(|tok| tok.pos == pos);
+        match Linter::new(self.validator.natives()).lint_source(text) {
+            Err(e) => {
+                let range = match e.pos.and_then(find) {
+                    Some(i) => document.range(i),
+                    None => document.range_between(1, 0, 0),
+                };
+                vec![json!({ "range": range, "severity": 1, "source": "cang", "message": e.message })]
+            }
+            Ok(warnings) => warnings
+                .iter()
+                .map(|warning| {
+                    // from the start of the statement to the end of its line
+                    let range = match find(warning.pos) {
+                        Some(i) => {
+                            let end = document.lines.get(warning.pos.0 - 1).map_or(0, |line| line.trim_end().chars().count());
+                            document.range_between(warning.pos.0, document.starts[i], end.max(warning.pos.1))
+                        }
+                        None => document.range_between(warning.pos.0, 0, 0),
+                    };
+                    let message = match warning.saving_description() {
+                        Some(saving) => format!("{} ({})", warning.message, saving),
+                        None => warning.message.clone(),
+                    };
+                    let mut diagnostic = json!({
+                        "range": range,
+                        "severity": 2,
+                        "code": warning.kind.code(),
+                        "source": "cang",
+                        "message": message,
+                    });
+                    // editors fade out code that does nothing
+                    if matches!(
+                        warning.kind,
+                        LintKind::UnusedVariable | LintKind::UnusedFunction | LintKind::UnusedParameter | LintKind::Unreachable
+                    ) {
+                        diagnostic["tags"] = json!([1]);
+                    }
+                    diagnostic
+                })
+                .collect(),
+        }
+    }
+
+    fn hover(&self, document: &Document, position: Option<(usize, usize)>) -> Result<Value, (i64, String)> {
+        let Some(i) = position.and_then(|position| document.token_at(position)) else {
+            return Ok(Value::Null);
+        };
+        let Some(name) = document.name(i) else { return Ok(Value::Null) };
+
+        let text = if let Some(symbol) = document.resolve(i) {
+            let mut text = match (&symbol.kind, &symbol.definition) {
+                (SymbolKind::Variable, Some(Expr::Let(_, value))) => format!("```cang\nlet {} = {}\n```", symbol.name, format_expr(value)),
+                _ => format!("```cang\n{}\n```", symbol.detail),
+            };
+            if let Some(definition) = &symbol.definition {
+                let costs = self.validator.merge_costs(self.validator.calculate_costs(definition));
+                text.push_str(&format!("\nDefining it costs {}", describe_costs(&sorted(costs))));
+            }
+            text
+        } else if let Some(native) = self.validator.natives().get(name) {
+            let cost = match native.cost.as_slice() {
+                [] => "Free to call".to_string(),
+                cost => format!("Each call costs {}", describe_costs(cost)),
+            };
+            format!("```cang\nfn {}\n```\nBuilt-in, takes {}. {}", name, native.arity.description(), cost)
+        } else if INPUT_FUNCTIONS.contains(&name) {
+            format!(
+                "```cang\nfn {}(prompt)\n```\nBuilt-in, {}. The prompt is optional. Free to call",
+                name,
+                input_summary(name)
+            )
+        } else if document.kind(i.wrapping_sub(1)) == Some(TokenTypes::Dot) {
+            let module = document.name(i.wrapping_sub(2)).unwrap_or_default();
+            format!("```cang\n{}.{}\n```\nFrom module {}", module, name, module)
+        } else {
+            return Ok(Value::Null);
+        };
+        Ok(json!({ "contents": { "kind": "markdown", "value": text }, "range": document.range(i) }))
+    }
+
+    fn definition(&self, document: &Document, position: Option<(usize, usize)>) -> Result<Value, (i64, String)> {
+        let symbol = position
+            .and_then(|position| document.token_at(position))
+            .and_then(|i| document.resolve(i));
+        Ok(match symbol {
+            Some(symbol) => json!({ "range": document.range(symbol.token) }),
+            None => Value::Null,
+        })
+    }
+
+    fn completion(&self, document: &Document, position: Option<(usize, usize)>) -> Result<Value, (i64, String)> {
+        let before = position.map_or(document.tokens.len(), |position| document.tokens_before(position));
+        let mut items: Vec<Value> = KEYWORDS
+            .iter()
+            .map(|keyword| json!({ "label": keyword, "kind": 14 }))
+            .collect();
+        for name in self.validator.natives().names() {
+            let native = self.validator.natives().get(name).unwrap();
+            items.push(json!({ "label": name, "kind": 3, "detail": format!("built-in, {}", native.arity.description()) }));
+        }
+        for name in INPUT_FUNCTIONS {
+            items.push(json!({ "label": name, "kind": 3, "detail": format!("built-in, {}", input_summary(name)) }));
+        }
+        for symbol in document.names_in_scope(before) {
+            let kind = match symbol.kind {
+                SymbolKind::Function => 3,
+                SymbolKind::Variable | SymbolKind::Parameter => 6,
+                SymbolKind::Module => 9,
+            };
+            items.push(json!({ "label": symbol.name, "kind": kind, "detail": symbol.detail }));
+        }
+        Ok(json!({ "isIncomplete": false, "items": items }))
+    }
+
+    // positions are relative to the previous token, as the protocol packs them
+    fn semantic_tokens(&self, document: &Document) -> Value {
+        let mut found: Vec<(usize, usize, usize, usize, u32)> = Vec::new(); // line, start, length, type, modifiers
+        for (i, tok) in document.tokens.iter().enumerate() {
+            let (kind, modifiers) = match tok.token_type {
+                TokenTypes::Let | TokenTypes::Fn | TokenTypes::Print | TokenTypes::Import => (0, 0),
+                TokenTypes::Number => (5, 0),
+                TokenTypes::String => (6, 0),
+                TokenTypes::Plus | TokenTypes::Minus | TokenTypes::Star | TokenTypes::Slash | TokenTypes::Eq => (7, 0),
+                TokenTypes::Identifier => self.classify(document, i),
+                _ => continue,
+            };
+            found.push((tok.pos.0, document.starts[i], tok.pos.1 - document.starts[i], kind, modifiers));
+        }
+        for comment in &document.comments {
+            let start = comment.pos.1.saturating_sub(2);
+            found.push((comment.pos.0, start, comment.text.chars().count() + 2, 8, 0));
+        }
+        found.sort();
+
+        let mut data = Vec::new();
+        let (mut last_line, mut last_start) = (1, 0);
+        for (line, start, length, kind, modifiers) in found {
+            // in utf-16 units like every other position
+            let text = document.lines.get(line - 1).map(String::as_str).unwrap_or_default();
+            let units = |from: usize, count: usize| -> usize { text.chars().skip(from).take(count).map(char::len_utf16).sum() };
+            let start_units = units(0, start);
+            let delta_start = if line == last_line { start_units - units(0, last_start) } else { start_units };
+            data.extend([line - last_line, delta_start, units(start, length), kind, modifiers as usize]);
+            last_line = line;
+            last_start = start;
+        }
+        json!({ "data": data })
+    }
+
+    fn classify(&self, document: &Document, i: usize) -> (usize, u32) {
+        let name = document.name(i).unwrap_or_default();
+        let declaration = document.defined_at.contains_key(&i) as u32;
+        if name == "as" && document.kind(i.wrapping_sub(2)) == Some(TokenTypes::Import) {
+            return (0, 0);
+        }
+        if document.kind(i + 1) == Some(TokenTypes::Dot) {
+            return (4, 0);
+        }
+        if let Some(symbol) = document.resolve(i) {
+            let kind = match symbol.kind {
+                SymbolKind::Function => 1,
+                SymbolKind::Variable => 2,
+                SymbolKind::Parameter => 3,
+                SymbolKind::Module => 4,
+            };
+            return (kind, declaration);
+        }
+        let called = document.kind(i + 1) == Some(TokenTypes::LParen);
+        if called && (self.validator.natives().contains(name) || INPUT_FUNCTIONS.contains(&name)) {
+            (1, 2)
+        } else if called {
+            (1, 0)
+        } else {
+            (2, 0)
+        }
+    }
+}
+
+fn sorted(mut costs: Vec<CoinCost>) -> Vec<CoinCost> {
+    costs.sort_by_key(|cost| CoinType::ALL.iter().position(|coin_type| *coin_type == cost.coin_type));
+    costs
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.cang";
+
+    // runs the messages through the server as a client would send them, and
+    // returns what it wrote back along with its exit code
+    fn exchange(messages: &[Value]) -> (Vec<Value>, i32) {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        let code = serve(input.as_slice(), &mut output).unwrap();
+
+        let mut replies = Vec::new();
+        let mut reader = output.as_slice();
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        (replies, code)
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "languageId": "cang", "version": 1, "text": text } },
+        })
+    }
+
+    fn request(id: u64, method: &str, position: Option<(u64, u64)>) -> Value {
+        let mut params = json!({ "textDocument": { "uri": URI } });
+        if let Some((line, character)) = position {
+            params["position"] = json!({ "line": line, "character": character });
+        }
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    // the answer to request `id`
+    fn result(replies: &[Value], id: u64) -> &Value {
+        &replies.iter().find(|reply| reply["id"] == id).unwrap()["result"]
+    }
+
+    #[test]
+    fn test_lifecycle_and_diagnostics() {
+        let (replies, code) = exchange(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            open("let x = 1;\nlet y = 2;\ny"),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": { "textDocument": { "uri": URI, "version": 2 }, "contentChanges": [{ "text": "let = 1" }] },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/rename", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(result(&replies, 1)["capabilities"]["semanticTokensProvider"]["legend"]["tokenTypes"][1], "function");
+
+        let published: Vec<&Value> = replies
+            .iter()
+            .filter(|reply| reply["method"] == "textDocument/publishDiagnostics")
+            .map(|reply| &reply["params"]["diagnostics"])
+            .collect();
+        assert_eq!(
+            published[0],
+            &json!([{
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 10 } },
+                "severity": 2,
+                "code": "unused_variable",
+                "source": "cang",
+                "message": "Variable 'x' is never used (saves 1 Variable coin)",
+                "tags": [1],
+            }])
+        );
+        assert_eq!(published[1][0]["severity"], 1);
+        assert_eq!(published[1][0]["message"], "Expected: identifier after 'let'");
+        assert_eq!(published[1][0]["range"]["start"], json!({ "line": 0, "character": 4 }));
+
+        assert_eq!(replies.iter().find(|reply| reply["id"] == 2).unwrap()["error"]["code"], METHOD_NOT_FOUND);
+        assert!(result(&replies, 3).is_null());
+
+        // exiting without a shutdown first is an error
+        let (_, code) = exchange(&[json!({ "jsonrpc": "2.0", "method": "exit" })]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn test_hover_and_definition() {
+        let source = "let x = 1;\nfn add(a, b) { let t = a; t + b };\nprint(add(x, pow(2, 3)));\nadd(x, 2)";
+        let (replies, _) = exchange(&[
+            open(source),
+            request(1, "textDocument/hover", Some((2, 7))),
+            request(2, "textDocument/hover", Some((2, 14))),
+            request(3, "textDocument/hover", Some((0, 4))),
+            request(4, "textDocument/hover", Some((1, 10))),
+            request(5, "textDocument/definition", Some((1, 26))),
+            request(6, "textDocument/definition", Some((3, 0))),
+            request(7, "textDocument/definition", Some((1, 30))),
+            request(8, "textDocument/hover", Some((2, 0))),
+        ]);
+
+        assert_eq!(
+            result(&replies, 1)["contents"]["value"],
+            "```cang\nfn add(a, b)\n```\nDefining it costs 1 Variable coin and 1 Function coin"
+        );
+        assert_eq!(result(&replies, 1)["range"]["start"], json!({ "line": 2, "character": 6 }));
+        assert_eq!(
+            result(&replies, 2)["contents"]["value"],
+            "```cang\nfn pow\n```\nBuilt-in, takes 2 arguments. Each call costs 1 Function coin"
+        );
+        assert_eq!(result(&replies, 3)["contents"]["value"], "```cang\nlet x = 1\n```\nDefining it costs 1 Variable coin");
+        assert_eq!(result(&replies, 4)["contents"]["value"], "```cang\nb: parameter of fn add(a, b)\n```");
+
+        let at = |id: u64| result(&replies, id)["range"]["start"].clone();
+        assert_eq!(at(5), json!({ "line": 1, "character": 19 }));
+        assert_eq!(at(6), json!({ "line": 1, "character": 3 }));
+        assert_eq!(at(7), json!({ "line": 1, "character": 10 }));
+        // keywords aren't names
+        assert!(result(&replies, 8).is_null());
+    }
+
+    #[test]
+    fn test_completion_follows_scope() {
+        let source = "let x = 1;\nfn f(n) { let inner = n; inner };\nlet later = 2;\nimport \"lib/geo.cang\" as g;\nx";
+        let (replies, _) = exchange(&[
+            open(source),
+            request(1, "textDocument/completion", Some((1, 25))),
+            request(2, "textDocument/completion", Some((2, 0))),
+            request(3, "textDocument/completion", Some((4, 0))),
+        ]);
+        let labels = |id: u64| -> Vec<String> {
+            result(&replies, id)["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let in_body = labels(1);
+        for name in ["let", "sqrt", "input_int", "x", "f", "n", "inner"] {
+            assert!(in_body.contains(&name.to_string()), "missing {}", name);
+        }
+        assert!(!in_body.contains(&"later".to_string()));
+
+        let after_body = labels(2);
+        assert!(after_body.contains(&"f".to_string()));
+        assert!(!after_body.contains(&"inner".to_string()) && !after_body.contains(&"n".to_string()));
+        assert!(labels(3).contains(&"g".to_string()) && labels(3).contains(&"later".to_string()));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let source = "fn f(n) { sqrt(n) }; // root\nlet s = \"a\\\"b\";\nm.f(s)";
+        let (replies, _) = exchange(&[open(source), request(1, "textDocument/semanticTokens/full", None)]);
+        let data: Vec<u64> = result(&replies, 1)["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n.as_u64().unwrap())
+            .collect();
+        let decoded: Vec<&[u64]> = data.chunks(5).collect();
+        let expected: [[u64; 5]; 13] = [
+            [0, 0, 2, 0, 0],  // fn
+            [0, 3, 1, 1, 1],  // f, declared
+            [0, 2, 1, 3, 1],  // n, declared
+            [0, 5, 4, 1, 2],  // sqrt, built in
+            [0, 5, 1, 3, 0],  // n
+            [0, 6, 7, 8, 0],  // the comment
+            [1, 0, 3, 0, 0],  // let
+            [0, 4, 1, 2, 1],  // s, declared
+            [0, 2, 1, 7, 0],  // =
+            [0, 2, 6, 6, 0],  // the string, escape included
+            [1, 0, 1, 4, 0],  // m
+            [0, 2, 1, 1, 0],  // f
+            [0, 2, 1, 2, 0],  // s
+        ];
+        assert_eq!(decoded, expected.iter().map(|t| t.as_slice()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut input = "\r\nContent-Type: application/vscode-jsonrpc\r\ncontent-length: 2\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        // positions count utf-16 units, so an emoji before a name is two
+        let document = Document::new("print(\"😀\"); x");
+        let x = document.tokens.len() - 1;
+        assert_eq!(document.range(x)["start"], json!({ "line": 0, "character": 13 }));
+        assert_eq!(document.token_at(document.source_position(&json!({ "line": 0, "character": 13 })).unwrap()), Some(x));
+    }
+
+    #[test]
+    fn test_bad_messages_keep_the_server_running() {
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        input.extend_from_slice(b"Content-Type: application/vscode-jsonrpc\r\n\r\n");
+        for message in [open("let big = 9999999999999999999999"), json!({ "jsonrpc": "2.0", "method": "exit" })] {
+            write_message(&mut input, &message).unwrap();
+        }
+        let mut output = Vec::new();
+        assert_eq!(serve(input.as_slice(), &mut output).unwrap(), 1);
+
+        let mut reader = output.as_slice();
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        assert_eq!(replies[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(replies[1]["error"]["message"], "Missing Content-Length header");
+
+        // an oversized number is a parse error in the editor, not a crash
+        let diagnostics = &replies[2]["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["message"], "Number too large: 9999999999999999999999");
+        assert_eq!(diagnostics[0]["severity"], 1);
+    }
+}
@@ -1,6 +1,244 @@
-use cang::Repl;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use cang::{format_source, protocol, Debugger, Linter, NativeRegistry, NoInput, NullOutput, ReaderInput, Repl};
+
+const USAGE: &str = "Usage: cang [--quests <file-or-dir>]... [--estimate <file>] [--questmap <ascii|dot|mermaid>] [--run <file> [--debug <commands>]] [--json]
+       cang fmt [--check] [<file>...]
+       cang lint [<file>...]";
 
 fn main() {
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "fmt") {
+        args.next();
+        process::exit(fmt(args.collect()));
+    }
+    if args.peek().is_some_and(|arg| arg == "lint") {
+        args.next();
+        process::exit(lint(args.collect()));
+    }
+
+    let mut quest_packs = Vec::new();
+    let mut estimate = None;
+    let mut quest_map = None;
+    let mut script = None;
+    let mut debug_commands = None;
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        if arg == "--json" {
+            json = true;
+            continue;
+        }
+        match (arg.as_str(), args.next()) {
+            ("--quests", Some(path)) => quest_packs.push(PathBuf::from(path)),
+            ("--estimate", Some(path)) => estimate = Some(path),
+            ("--questmap", Some(format)) => quest_map = Some(format),
+            ("--run", Some(path)) => script = Some(path),
+            ("--debug", Some(path)) => debug_commands = Some(path),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    if debug_commands.is_some() && script.is_none() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
     let mut repl = Repl::new();
+    // extra directories to find imported modules in, like PATH
+    if let Some(dirs) = env::var_os("CANG_PATH") {
+        for dir in env::split_paths(&dirs) {
+            repl.validator.modules_mut().add_search_dir(dir);
+        }
+    }
+    // stdin and stdout carry the protocol, so programs get neither
+    if json {
+        repl = repl.with_output(Box::new(NullOutput)).with_input(Box::new(NoInput));
+    }
+    for path in &quest_packs {
+        if let Err(e) = repl.load_quest_pack(path) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+
+    // machine front end: no daily bonus or saved state, so runs are repeatable
+    if json {
+        if let Err(e) = protocol::serve(&mut repl, io::stdin().lock(), &mut io::stdout()) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(format) = quest_map {
+        if !repl.show_quest_map(&format) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    // script mode: the program's input() reads whatever is piped to stdin, and
+    // its imports are found next to it
+    if let Some(path) = script {
+        if let Some(dir) = Path::new(&path).parent() {
+            repl.validator.modules_mut().set_root(dir.to_path_buf());
+        }
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        };
+        // under the debugger the commands come from a file, so stdin stays the program's
+        let outcome = match debug_commands {
+            Some(commands) => match fs::File::open(&commands) {
+                Ok(file) => {
+                    repl.debugger = Debugger::new().with_commands(Box::new(ReaderInput::new(io::BufReader::new(file))));
+                    repl.debug_source(&source)
+                }
+                Err(e) => {
+                    eprintln!("Error reading {}: {}", commands, e);
+                    process::exit(1);
+                }
+            },
+            None => repl.run_source(&source),
+        };
+        if let Some(e) = outcome.error {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(path) = estimate {
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        };
+        if !repl.show_cost_estimate(&source) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(home) = env::var_os("HOME")
+        && let Err(e) = repl.set_state_dir(PathBuf::from(home).join(".cang"))
+    {
+        eprintln!("Warning: session state won't be saved: {}", e);
+    }
     repl.run();
 }
+
+// formats files in place, or stdin to stdout when there are none. With
+// --check nothing is written and the exit code says whether anything would be
+fn fmt(args: Vec<String>) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if let Some(flag) = files.iter().find(|file| file.starts_with("--")) {
+        eprintln!("Unknown option {}\n{}", flag, USAGE);
+        return 2;
+    }
+
+    if files.is_empty() {
+        let source = match io::read_to_string(io::stdin()) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading stdin: {}", e);
+                return 1;
+            }
+        };
+        return match format_source(&source) {
+            Ok(formatted) if check => (formatted != source) as i32,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(e) => {
+                eprintln!("<stdin>: {}", e);
+                1
+            }
+        };
+    }
+
+    let mut status = 0;
+    for file in files {
+        let formatted = fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|source| format_source(&source).map(|formatted| (formatted != source, formatted)).map_err(|e| e.to_string()));
+        match formatted {
+            Ok((false, _)) => {}
+            Ok((true, _)) if check => {
+                println!("Would reformat {}", file);
+                status = 1;
+            }
+            Ok((true, formatted)) => match fs::write(file, formatted) {
+                Ok(()) => println!("Formatted {}", file),
+                Err(e) => {
+                    eprintln!("{}: {}", file, e);
+                    status = 1;
+                }
+            },
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+// lints files, or stdin when there are none. Exits 1 when anything was found
+fn lint(files: Vec<String>) -> i32 {
+    if let Some(flag) = files.iter().find(|file| file.starts_with("--")) {
+        eprintln!("Unknown option {}\n{}", flag, USAGE);
+        return 2;
+    }
+    let sources: Vec<(String, io::Result<String>)> = if files.is_empty() {
+        vec![("<stdin>".to_string(), io::read_to_string(io::stdin()))]
+    } else {
+        files.into_iter().map(|file| {
+            let source = fs::read_to_string(&file);
+            (file, source)
+        }).collect()
+    };
+
+    let natives = NativeRegistry::with_stdlib();
+    let linter = Linter::new(&natives);
+    let mut warnings = 0;
+    let mut failed = false;
+    for (file, source) in sources {
+        let diagnostics = source.map_err(|e| e.to_string()).and_then(|source| linter.lint_source(&source).map_err(|e| e.to_string()));
+        match diagnostics {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    let (line, col) = diagnostic.pos;
+                    match diagnostic.saving_description() {
+                        Some(saving) => println!("{}:{}:{}: warning[{}]: {} ({})", file, line, col, diagnostic.kind.code(), diagnostic.message, saving),
+                        None => println!("{}:{}:{}: warning[{}]: {}", file, line, col, diagnostic.kind.code(), diagnostic.message),
+                    }
+                }
+                warnings += diagnostics.len();
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+            }
+        }
+    }
+    if warnings > 0 {
+        println!("{} warning{}", warnings, if warnings == 1 { "" } else { "s" });
+    }
+    (failed || warnings > 0) as i32
+}
@@ -0,0 +1,484 @@
+// `import` support: finding .cang files, loading each one once per session
+// and binding what it defines under a prefix like `m.square`
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    natives::NativeRegistry,
+    parser::{Evaluator, Parser, Statement},
+    resource_validator::program_costs,
+    tokenize, CoinCost, CoinError, CoinManager, CoinType, Expr, ResourceValidator,
+};
+
+pub const MODULE_EXTENSION: &str = "cang";
+
+#[derive(Debug)]
+pub enum ModuleError {
+    NotFound { spec: String, searched: Vec<PathBuf> },
+    Io { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, message: String, pos: Option<(usize, usize)> },
+    NotADefinition { path: PathBuf, pos: (usize, usize) },
+    Cycle(Vec<PathBuf>),
+    Coins(CoinError),
+    Runtime { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::NotFound { spec, searched } => {
+                let searched: Vec<String> = searched
+                    .iter()
+                    .map(|dir| match dir.as_os_str().is_empty() {
+                        true => ".".to_string(),
+                        false => dir.display().to_string(),
+                    })
+                    .collect();
+                write!(f, "Module '{}' not found, looked in {}", spec, searched.join(", "))
+            }
+            ModuleError::Io { path, error } => write!(f, "Can't read {}: {}", path.display(), error),
+            ModuleError::Parse { path, message, pos } => match pos {
+                Some((line, col)) => write!(f, "{} (line {}, col {}): {}", path.display(), line, col, message),
+                None => write!(f, "{}: {}", path.display(), message),
+            },
+            ModuleError::NotADefinition { path, pos } => write!(
+                f,
+                "{} (line {}, col {}): modules can only contain let, fn and import statements",
+                path.display(),
+                pos.0,
+                pos.1
+            ),
+            ModuleError::Cycle(chain) => {
+                let chain: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "Import cycle: {}", chain.join(" -> "))
+            }
+            ModuleError::Coins(e) => write!(f, "Coin err: {}", e),
+            ModuleError::Runtime { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ModuleError {}
+
+impl From<CoinError> for ModuleError {
+    fn from(value: CoinError) -> Self {
+        ModuleError::Coins(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String, // file name without the extension
+    pub path: PathBuf,
+    pub cost: Vec<CoinCost>, // what loading it charged
+    // its own definitions, plus `b.f` style names for what it imported itself
+    definitions: Vec<(String, Expr)>,
+}
+
+impl Module {
+    pub fn names(&self) -> Vec<&str> {
+        self.definitions.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    // every definition renamed to `alias.name`, with the references between
+    // them renamed to match so module functions can still call each other
+    pub fn bindings(&self, alias: &str) -> Vec<(String, Expr)> {
+        let own: HashSet<&str> = self.definitions.iter().map(|(name, _)| name.as_str()).collect();
+        self.definitions
+            .iter()
+            .map(|(name, definition)| {
+                let definition = match definition {
+                    Expr::FnDef(_, params, body) => {
+                        let mut bound: HashSet<String> = params.iter().cloned().collect();
+                        Expr::FnDef(
+                            qualify(alias, name),
+                            params.clone(),
+                            Box::new(rename(body, &own, alias, &mut bound)),
+                        )
+                    }
+                    other => other.clone(),
+                };
+                (qualify(alias, name), definition)
+            })
+            .collect()
+    }
+}
+
+fn qualify(alias: &str, name: &str) -> String {
+    format!("{}.{}", alias, name)
+}
+
+// `bound` holds parameters and locals, which hide module names of the same name
+fn rename(expr: &Expr, own: &HashSet<&str>, alias: &str, bound: &mut HashSet<String>) -> Expr {
+    let resolve = |name: &String, bound: &HashSet<String>| {
+        if own.contains(name.as_str()) && !bound.contains(name) {
+            qualify(alias, name)
+        } else {
+            name.clone()
+        }
+    };
+    match expr {
+        Expr::Var(name) => Expr::Var(resolve(name, bound)),
+        Expr::FnCall(name, args) => Expr::FnCall(
+            resolve(name, bound),
+            args.iter().map(|arg| rename(arg, own, alias, bound)).collect(),
+        ),
+        Expr::Let(name, value) => {
+            let value = rename(value, own, alias, bound);
+            bound.insert(name.clone());
+            Expr::Let(name.clone(), Box::new(value))
+        }
+        Expr::FnDef(name, params, body) => {
+            let mut inner = bound.clone();
+            inner.extend(params.iter().cloned());
+            let body = rename(body, own, alias, &mut inner);
+            bound.insert(name.clone());
+            Expr::FnDef(name.clone(), params.clone(), Box::new(body))
+        }
+        Expr::Binary(lhs, op, rhs) => Expr::Binary(
+            Box::new(rename(lhs, own, alias, bound)),
+            *op,
+            Box::new(rename(rhs, own, alias, bound)),
+        ),
+        Expr::Block(statements) => Expr::Block(statements.iter().map(|stmt| rename(stmt, own, alias, bound)).collect()),
+        Expr::Print(value) => Expr::Print(Box::new(rename(value, own, alias, bound))),
+        Expr::Number(_) | Expr::String(_) | Expr::Import(..) => expr.clone(),
+    }
+}
+
+// modules are cached by path for the whole session, so a module is paid for
+// and evaluated once no matter how many programs or other modules import it
+#[derive(Debug, Clone, Default)]
+pub struct ModuleLoader {
+    root: PathBuf, // where top level imports are looked up first, the working directory if empty
+    search_path: Vec<PathBuf>,
+    modules: HashMap<PathBuf, Module>,
+    imports: HashMap<String, PathBuf>, // top level import -> the module it loaded
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_root(&mut self, root: PathBuf) {
+        self.root = root;
+    }
+
+    // searched in order after the importing file's own directory
+    pub fn add_search_dir(&mut self, dir: PathBuf) {
+        self.search_path.push(dir);
+    }
+
+    pub fn search_path(&self) -> &[PathBuf] {
+        &self.search_path
+    }
+
+    pub fn imported(&self, spec: &str) -> Option<&Module> {
+        self.imports.get(spec).and_then(|path| self.modules.get(path))
+    }
+
+    // loaded modules in path order
+    pub fn loaded(&self) -> Vec<&Module> {
+        let mut modules: Vec<&Module> = self.modules.values().collect();
+        modules.sort_by(|a, b| a.path.cmp(&b.path));
+        modules
+    }
+
+    pub fn link(
+        &mut self,
+        statements: &[Statement],
+        coin_manager: &mut CoinManager,
+        natives: &NativeRegistry,
+    ) -> Result<(), (usize, ModuleError)> {
+        for (i, (stmt, _)) in statements.iter().enumerate() {
+            if let Expr::Import(spec, _) = stmt {
+                let root = self.root.clone();
+                let path = self
+                    .load(spec, &root, coin_manager, natives, &mut Vec::new())
+                    .map_err(|e| (i, e))?;
+                self.imports.insert(spec.clone(), path);
+            }
+        }
+        Ok(())
+    }
+
+    // what importing `spec` would charge right now: nothing if it's loaded,
+    // otherwise its definitions plus whatever it imports that isn't loaded
+    // either. `seen` keeps a module shared by several imports from being
+    // priced twice. An import that won't load is free here, linking reports it
+    pub fn import_cost(&self, spec: &str, natives: &NativeRegistry, seen: &mut HashSet<PathBuf>) -> Vec<CoinCost> {
+        self.price(spec, &self.root, natives, seen).unwrap_or_default()
+    }
+
+    fn price(
+        &self,
+        spec: &str,
+        from: &Path,
+        natives: &NativeRegistry,
+        seen: &mut HashSet<PathBuf>,
+    ) -> Option<Vec<CoinCost>> {
+        let path = self.resolve(spec, from).ok()?;
+        if self.modules.contains_key(&path) || !seen.insert(path.clone()) {
+            return Some(Vec::new());
+        }
+        let source = fs::read_to_string(&path).ok()?;
+        let mut parser = Parser::new(tokenize(&source));
+        let statements = parser.parse_statements().ok()?;
+        if !parser.at_end() {
+            return None;
+        }
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut costs = Vec::new();
+        for (stmt, _) in &statements {
+            match stmt {
+                Expr::Import(spec, _) => costs.extend(self.price(spec, &dir, natives, seen)?),
+                Expr::Let(..) | Expr::FnDef(..) => costs.extend(program_costs(stmt, natives)),
+                _ => return None,
+            }
+        }
+        Some(costs)
+    }
+
+    fn resolve(&self, spec: &str, from: &Path) -> Result<PathBuf, ModuleError> {
+        let mut file = PathBuf::from(spec);
+        if file.extension().is_none_or(|ext| ext != MODULE_EXTENSION) {
+            file.set_extension(MODULE_EXTENSION);
+        }
+
+        let dirs: Vec<PathBuf> = if file.is_absolute() {
+            vec![PathBuf::new()]
+        } else {
+            std::iter::once(from.to_path_buf()).chain(self.search_path.iter().cloned()).collect()
+        };
+        for dir in &dirs {
+            let candidate = dir.join(&file);
+            if candidate.is_file() {
+                return Ok(candidate.canonicalize().unwrap_or(candidate));
+            }
+        }
+        Err(ModuleError::NotFound {
+            spec: spec.to_string(),
+            searched: dirs,
+        })
+    }
+
+    // `loading` is the chain of modules being loaded, to catch cycles
+    fn load(
+        &mut self,
+        spec: &str,
+        from: &Path,
+        coin_manager: &mut CoinManager,
+        natives: &NativeRegistry,
+        loading: &mut Vec<PathBuf>,
+    ) -> Result<PathBuf, ModuleError> {
+        let path = self.resolve(spec, from)?;
+        if let Some(start) = loading.iter().position(|p| *p == path) {
+            let mut chain = loading[start..].to_vec();
+            chain.push(path);
+            return Err(ModuleError::Cycle(chain));
+        }
+        if self.modules.contains_key(&path) {
+            return Ok(path);
+        }
+
+        let source = fs::read_to_string(&path).map_err(|error| ModuleError::Io {
+            path: path.clone(),
+            error,
+        })?;
+        let mut parser = Parser::new(tokenize(&source));
+        let statements = parser.parse_statements().map_err(|e| ModuleError::Parse {
+            path: path.clone(),
+            message: e.to_string(),
+            pos: parser.error_pos(),
+        })?;
+        // the parser stops at a missing `;`, which would drop the rest of the module
+        if !parser.at_end() {
+            return Err(ModuleError::Parse {
+                path,
+                message: "Expected ';' between statements".to_string(),
+                pos: parser.peek().map(|tok| tok.pos),
+            });
+        }
+
+        // what it imports is loaded (and paid for) first
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut imports = HashMap::new();
+        loading.push(path.clone());
+        for (stmt, pos) in &statements {
+            match stmt {
+                Expr::Import(spec, _) => {
+                    let imported = self.load(spec, &dir, coin_manager, natives, loading)?;
+                    imports.insert(spec.clone(), imported);
+                }
+                Expr::Let(..) | Expr::FnDef(..) => {}
+                _ => {
+                    return Err(ModuleError::NotADefinition {
+                        path: path.clone(),
+                        pos: *pos,
+                    });
+                }
+            }
+        }
+        loading.pop();
+
+        // priced like a program charged up front, and only once it's known to load
+        let mut env = HashMap::new();
+        let mut output = Vec::new();
+        let mut evaluator = Evaluator::new(&mut output).with_natives(natives);
+        let mut costs: HashMap<_, u32> = HashMap::new();
+        for (stmt, _) in &statements {
+            if let Expr::Import(spec, alias) = stmt {
+                env.extend(self.modules[&imports[spec]].bindings(alias));
+                continue;
+            }
+            coin_manager.check_params(ResourceValidator::max_params(stmt))?;
+            for cost in program_costs(stmt, natives) {
+                *costs.entry(cost.coin_type).or_default() += cost.amt;
+            }
+            evaluator.eval(stmt, &mut env).map_err(|e| ModuleError::Runtime {
+                path: path.clone(),
+                message: e.to_string(),
+            })?;
+        }
+        let mut cost: Vec<CoinCost> = costs
+            .into_iter()
+            .map(|(coin_type, amt)| CoinCost { coin_type, amt })
+            .collect();
+        cost.sort_by_key(|c| CoinType::ALL.iter().position(|t| *t == c.coin_type));
+        for c in &cost {
+            coin_manager.check_affordable(c.coin_type, c.amt)?;
+        }
+        for c in &cost {
+            coin_manager.spend_coins(c.coin_type, c.amt)?;
+        }
+
+        let mut definitions: Vec<(String, Expr)> = env.into_iter().collect();
+        definitions.sort_by(|a, b| a.0.cmp(&b.0));
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.modules.insert(
+            path.clone(),
+            Module {
+                name,
+                path: path.clone(),
+                cost,
+                definitions,
+            },
+        );
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, RunErrorKind, Value};
+
+    fn module_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cang_modules_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (file, source) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_imports_are_namespaced_and_charged_once() {
+        let dir = module_dir(
+            "namespaced",
+            &[
+                ("lib/math.cang", "let two = 2;\nfn double(n) { n * two };\nfn shadow(two) { two + 1 }"),
+                ("geo.cang", "import \"lib/math.cang\" as m;\nfn area(w) { m.double(w) * w }"),
+            ],
+        );
+        let mut interpreter = Interpreter::builder().coins(5, 5).module_path(&dir).build().unwrap();
+
+        let source = "import geo; import \"lib/math.cang\" as m; geo.area(3) + m.double(m.two) + m.shadow(5)";
+        assert_eq!(interpreter.eval(source), Ok(Value::Number(18 + 4 + 6)));
+        // math is 1 variable and 2 function coins, geo 1 function coin
+        assert_eq!(interpreter.coins().get_balance(CoinType::Variable), 4);
+        assert_eq!(interpreter.coins().get_balance(CoinType::Function), 2);
+
+        // loaded once, so importing again under another name is free
+        assert_eq!(interpreter.eval("import geo as g; g.area(2)"), Ok(Value::Number(8)));
+        assert_eq!(interpreter.coins().get_balance(CoinType::Function), 2);
+        let math = &interpreter.modules().loaded()[1];
+        assert_eq!(math.names(), vec!["double", "shadow", "two"]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = module_dir(
+            "errors",
+            &[
+                ("a.cang", "import b"),
+                ("b.cang", "import a"),
+                ("noisy.cang", "let x = 1;\nprint(x)"),
+                ("unsplit.cang", "fn f(n) { n }\nfn g(n) { n }"),
+                ("pricey.cang", "fn f(n) { n };\nfn g(n) { n }"),
+            ],
+        );
+        let mut interpreter = Interpreter::builder().coins(0, 1).module_path(&dir).build().unwrap();
+
+        let err = interpreter.eval("1;\nimport a").unwrap_err();
+        assert_eq!(err.kind, RunErrorKind::Import);
+        assert!(err.message.starts_with("Import cycle: "));
+        assert!(err.message.ends_with("a.cang"));
+        assert_eq!(err.pos.map(|(line, _)| line), Some(2));
+
+        let err = interpreter.eval("import missing").unwrap_err();
+        assert!(err.message.starts_with("Module 'missing' not found"));
+        let err = interpreter.eval("import noisy").unwrap_err();
+        assert!(err.message.ends_with("(line 2, col 5): modules can only contain let, fn and import statements"));
+        assert_eq!(interpreter.eval("fn f() { import a }").unwrap_err().kind, RunErrorKind::Parse);
+        let err = interpreter.eval("import unsplit").unwrap_err();
+        assert_eq!(err.kind, RunErrorKind::Import);
+        assert!(err.message.ends_with("(line 2, col 2): Expected ';' between statements"));
+
+        // a module that can't be paid for isn't loaded, or charged for
+        assert_eq!(interpreter.eval("import pricey").unwrap_err().kind, RunErrorKind::Coins);
+        assert_eq!(interpreter.coins().get_balance(CoinType::Function), 1);
+        assert!(interpreter.modules().loaded().is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_imports_and_program_are_paid_for_together() {
+        let dir = module_dir("together", &[("m.cang", "fn f(n) { n }"), ("n.cang", "import m;\nlet k = 1")]);
+        let mut interpreter = Interpreter::builder().coins(1, 1).module_path(&dir).build().unwrap();
+
+        // the module fits but the program doesn't, so neither is paid for
+        let err = interpreter.eval("import m; let a = 1; let b = 2").unwrap_err();
+        assert_eq!(err.kind, RunErrorKind::Coins);
+        assert_eq!(interpreter.coins().get_balance(CoinType::Variable), 1);
+        assert_eq!(interpreter.coins().get_balance(CoinType::Function), 1);
+        assert!(interpreter.modules().loaded().is_empty());
+
+        // estimates include modules that aren't loaded yet, shared ones once
+        let mut validator = ResourceValidator::new(CoinManager::with_balances(1, 1));
+        validator.modules_mut().set_root(dir.clone());
+        let statements = Parser::new(tokenize("import n; import m as again")).parse_statements().unwrap();
+        let estimate = validator.estimate(&statements);
+        assert_eq!(estimate.total_for(CoinType::Variable), 1);
+        assert_eq!(estimate.total_for(CoinType::Function), 1);
+        assert!(estimate.affordable);
+
+        validator.link(&statements).unwrap();
+        assert!(validator.estimate(&statements).total.is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,102 @@
+// host functions programs can call, each with an arity and optionally a price
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{CoinCost, CoinType};
+
+pub type NativeFn = Rc<dyn Fn(&[i64]) -> Result<i64, String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == *n,
+            Arity::AtLeast(n) => count >= *n,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        let (prefix, n) = match self {
+            Arity::Exact(n) => ("", *n),
+            Arity::AtLeast(n) => ("at least ", *n),
+        };
+        let plural = if n == 1 { "" } else { "s" };
+        format!("{}{} argument{}", prefix, n, plural)
+    }
+}
+
+#[derive(Clone)]
+pub struct Native {
+    pub arity: Arity,
+    pub cost: Vec<CoinCost>, // charged per call, on top of whatever the arguments cost
+    function: NativeFn,
+}
+
+impl Native {
+    pub fn new(arity: Arity, function: impl Fn(&[i64]) -> Result<i64, String> + 'static) -> Self {
+        Self {
+            arity,
+            cost: Vec::new(),
+            function: Rc::new(function),
+        }
+    }
+
+    pub fn with_cost(mut self, coin_type: CoinType, amt: u32) -> Self {
+        self.cost.push(CoinCost { coin_type, amt });
+        self
+    }
+
+    // arity is checked by the caller so the error can name the function
+    pub fn call(&self, args: &[i64]) -> Result<i64, String> {
+        (self.function)(args)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Native")
+            .field("arity", &self.arity)
+            .field("cost", &self.cost)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NativeRegistry {
+    functions: HashMap<String, Native>,
+}
+
+impl NativeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_stdlib() -> Self {
+        let mut registry = Self::new();
+        crate::stdlib::register(&mut registry);
+        registry
+    }
+
+    // replaces any native already registered under the name
+    pub fn register(&mut self, name: &str, native: Native) {
+        self.functions.insert(name.to_string(), native);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Native> {
+        self.functions.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
@@ -0,0 +1,60 @@
+// where printed text goes, so embedders can capture or drop it instead of
+// having it land on stdout
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+pub trait Output {
+    fn write_str(&mut self, text: &str);
+
+    fn flush(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutOutput;
+
+impl Output for StdoutOutput {
+    fn write_str(&mut self, text: &str) {
+        print!("{}", text);
+    }
+
+    fn flush(&mut self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+// clones share the same buffer so a test can keep a handle and read it back
+#[derive(Debug, Clone, Default)]
+pub struct BufferOutput {
+    text: Rc<RefCell<String>>,
+}
+
+impl BufferOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        self.text.borrow().clone()
+    }
+
+    // hands back everything written so far and empties the buffer
+    pub fn take(&self) -> String {
+        self.text.take()
+    }
+}
+
+impl Output for BufferOutput {
+    fn write_str(&mut self, text: &str) {
+        self.text.borrow_mut().push_str(text);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullOutput;
+
+impl Output for NullOutput {
+    fn write_str(&mut self, _text: &str) {}
+}
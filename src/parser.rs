@@ -1,12 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
-use crate::{CoinType, ResourceValidator, Token, TokenTypes, ValidationError};
+use crate::{
+    debugger::Debugger, events::ExecutionEvent, formatter::format_expr, input::{InputSource, NoInput}, modules::ModuleLoader, natives::NativeRegistry, output::{NullOutput, Output}, CoinManager, CoinType, MeteringMode, ResourceValidator, Token, TokenTypes,
+    ValidationError, resource_validator::redefined_builtin,
+};
 
 #[derive(Debug)]
 pub enum ParseError {
     UnexpectedToken(String),
     ExpectedToken(String),
     UnexpectedEof,
+    NumberTooLarge(String),
 }
 
 impl std::fmt::Display for ParseError {
@@ -15,13 +19,14 @@ impl std::fmt::Display for ParseError {
             ParseError::UnexpectedToken(msg) => write!(f, "Unexpected token: {}", msg),
             ParseError::ExpectedToken(msg) => write!(f, "Expected: {}", msg),
             ParseError::UnexpectedEof => write!(f, "Unexpected end of input"),
+            ParseError::NumberTooLarge(n) => write!(f, "Number too large: {}", n),
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Number(i64),
     Binary(Box<Expr>, TokenTypes, Box<Expr>),
@@ -32,22 +37,70 @@ pub enum Expr {
     Block(Vec<Expr>), // for multiple statements
     Print(Box<Expr>), // print expression
     String(String), // string literal
+    Import(String, String), // module path or name, the alias it's bound under
+}
+
+// top level statement with the (line, col) of its first token
+pub type Statement = (Expr, (usize, usize));
+
+// where a statement's source starts and the line its last token is on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatementSpan {
+    pub start: (usize, usize),
+    pub end_line: usize,
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    spans: Vec<StatementSpan>, // every statement parsed, function bodies included, in source order
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        Self {
+            tokens,
+            pos: 0,
+            spans: Vec::new(),
+        }
+    }
+
+    pub fn statement_spans(&self) -> &[StatementSpan] {
+        &self.spans
+    }
+
+    // parse_statements stops quietly at two statements with no `;` between
+    // them, this tells whether it got through everything
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    // the slot is taken before parsing so a function comes before its body
+    fn spanned(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, ParseError>) -> Result<Expr, ParseError> {
+        let start = self.peek().map(|tok| tok.pos).unwrap_or_default();
+        let slot = self.spans.len();
+        self.spans.push(StatementSpan {
+            start,
+            end_line: start.0,
+        });
+        let stmt = parse(self)?;
+        if let Some(last) = self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            self.spans[slot].end_line = last.pos.0;
+        }
+        Ok(stmt)
     }
 
     pub fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.pos)
     }
 
+    // where a failed parse stopped: the last token it looked at, or the
+    // final token when it ran out of input
+    pub fn error_pos(&self) -> Option<(usize, usize)> {
+        let last = self.tokens.len().checked_sub(1)?;
+        self.tokens.get(self.pos.saturating_sub(1).min(last)).map(|tok| tok.pos)
+    }
+
     pub fn eat(&mut self) -> Option<Token> {
         let tok = self.tokens.get(self.pos).cloned();
         self.pos += 1;
@@ -87,7 +140,8 @@ impl Parser {
     pub fn parse_factor(&mut self) -> Result<Expr, ParseError> {
         match self.eat() {
             Some(tok) if tok.token_type == TokenTypes::Number => {
-                let n = tok.value.unwrap().parse::<i64>().unwrap();
+                let digits = tok.value.unwrap();
+                let n = digits.parse::<i64>().map_err(|_| ParseError::NumberTooLarge(digits))?;
                 Ok(Expr::Number(n))
             }
             Some(tok) if tok.token_type == TokenTypes::String => {
@@ -95,7 +149,22 @@ impl Parser {
                 Ok(Expr::String(s))
             }
             Some(tok) if tok.token_type == TokenTypes::Identifier => {
-                let name = tok.value.unwrap();
+                let mut name = tok.value.unwrap();
+                // `m.square` names something an import bound under `m`
+                while matches!(self.peek(), Some(tok) if tok.token_type == TokenTypes::Dot) {
+                    self.eat();
+                    match self.eat() {
+                        Some(Token {
+                            token_type: TokenTypes::Identifier,
+                            value: Some(part),
+                            ..
+                        }) => {
+                            name.push('.');
+                            name.push_str(&part);
+                        }
+                        _ => return Err(ParseError::ExpectedToken("name after '.'".to_string())),
+                    }
+                }
                 if let Some(next) = self.peek() {
                     if next.token_type == TokenTypes::LParen {
                         self.eat();
@@ -202,7 +271,7 @@ impl Parser {
             _ => return Err(ParseError::ExpectedToken("'{' before function body".to_string())),
         };
 
-        let body = self.parse_expr()?;
+        let body = self.parse_fn_body()?;
 
         // expect }
         match self.eat() {
@@ -216,6 +285,29 @@ impl Parser {
         Ok(Expr::FnDef(name, params, Box::new(body)))
     }
 
+    // `;` separated statements, the last one is the return value
+    fn parse_fn_body(&mut self) -> Result<Expr, ParseError> {
+        let mut statements = vec![self.parse_stmt()?];
+
+        while let Some(Token {
+            token_type: TokenTypes::Semicolon,
+            ..
+        }) = self.peek()
+        {
+            self.eat();
+            if matches!(self.peek(), Some(tok) if tok.token_type == TokenTypes::RCurly) {
+                break;
+            }
+            statements.push(self.parse_stmt()?);
+        }
+
+        if statements.len() == 1 {
+            Ok(statements.remove(0))
+        } else {
+            Ok(Expr::Block(statements))
+        }
+    }
+
     fn parse_let(&mut self) -> Result<Expr, ParseError> {
         self.eat();
         let ident = match self.eat() {
@@ -263,7 +355,53 @@ impl Parser {
         Ok(Expr::Print(Box::new(expr)))
     }
 
+    // import "lib/math.cang" or import math, either optionally followed by `as m`
+    fn parse_import(&mut self) -> Result<Expr, ParseError> {
+        self.eat(); // consume 'import'
+
+        let spec = match self.eat() {
+            Some(Token {
+                token_type: TokenTypes::String | TokenTypes::Identifier,
+                value: Some(spec),
+                ..
+            }) => spec,
+            _ => return Err(ParseError::ExpectedToken("module path or name after 'import'".to_string())),
+        };
+
+        let alias = if matches!(self.peek(), Some(tok) if tok.value.as_deref() == Some("as")) {
+            self.eat();
+            match self.eat() {
+                Some(Token {
+                    token_type: TokenTypes::Identifier,
+                    value: Some(alias),
+                    ..
+                }) => alias,
+                _ => return Err(ParseError::ExpectedToken("name after 'as'".to_string())),
+            }
+        } else {
+            // without `as` the module goes by its file name
+            let stem = Path::new(&spec)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default()
+                .to_string();
+            if !is_identifier(&stem) {
+                return Err(ParseError::ExpectedToken(format!(
+                    "'as <name>' since '{}' can't be used as a name",
+                    stem
+                )));
+            }
+            stem
+        };
+
+        Ok(Expr::Import(spec, alias))
+    }
+
     pub fn parse_stmt(&mut self) -> Result<Expr, ParseError> {
+        self.spanned(Self::parse_stmt_kind)
+    }
+
+    fn parse_stmt_kind(&mut self) -> Result<Expr, ParseError> {
         match self.peek() {
             Some(Token {
                 token_type: TokenTypes::Let,
@@ -282,10 +420,22 @@ impl Parser {
     }
 
     pub fn parse_program(&mut self) -> Result<Expr, ParseError> {
+        Ok(program(self.parse_statements()?))
+    }
+
+    pub fn parse_statements(&mut self) -> Result<Vec<Statement>, ParseError> {
         let mut statements = Vec::new();
         
-        while self.pos < self.tokens.len() {
-            statements.push(self.parse_stmt()?);
+        while let Some(start) = self.peek().map(|tok| tok.pos) {
+            // imports only make sense at the top level, never inside a function
+            let stmt = match self.peek() {
+                Some(Token {
+                    token_type: TokenTypes::Import,
+                    ..
+                }) => self.spanned(Self::parse_import)?,
+                _ => self.parse_stmt()?,
+            };
+            statements.push((stmt, start));
             
             // Check for semicolon separator
             if let Some(tok) = self.peek() {
@@ -297,12 +447,24 @@ impl Parser {
                 }
             }
         }
-        
-        if statements.len() == 1 {
-            Ok(statements.into_iter().next().unwrap())
-        } else {
-            Ok(Expr::Block(statements))
-        }
+
+        Ok(statements)
+    }
+}
+
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// a lone statement runs as is, anything more as a block
+pub fn program(statements: Vec<Statement>) -> Expr {
+    let mut statements: Vec<Expr> = statements.into_iter().map(|(stmt, _)| stmt).collect();
+
+    if statements.len() == 1 {
+        statements.remove(0)
+    } else {
+        Expr::Block(statements)
     }
 }
 
@@ -312,110 +474,453 @@ pub fn eval(expr: &Expr, env: &mut HashMap<String, Expr>) -> Result<i64, Validat
 }
 
 pub fn eval_with_output(expr: &Expr, env: &mut HashMap<String, Expr>, output: &mut Vec<String>) -> Result<i64, ValidationError> {
-    match expr {
-        Expr::Number(n) => Ok(*n),
-        Expr::String(_) => Ok(0), // String literals evaluate to 0 for numeric context
-        Expr::Binary(lhs, op, rhs) => {
-            let lval = eval_with_output(lhs, env, output)?;
-            let rval = eval_with_output(rhs, env, output)?;
-            match op {
-                TokenTypes::Plus => Ok(lval + rval),
-                TokenTypes::Minus => Ok(lval - rval),
-                TokenTypes::Star => Ok(lval * rval),
-                TokenTypes::Slash => {
-                    if rval == 0 {
-                        Err(ValidationError::RuntimeError("Division by zero".to_string()))
-                    } else {
-                        Ok(lval / rval)
-                    }
-                }
-                _ => Err(ValidationError::RuntimeError("Invalid operator".to_string())),
+    Evaluator::new(output).eval(expr, env)
+}
+
+// built into the evaluator rather than the native registry since they need
+// its input and output
+pub const INPUT_FUNCTIONS: [&str; 2] = ["input", "input_int"];
+
+// values are whole numbers, so input() can only say whether a line was there
+pub fn input_summary(name: &str) -> &'static str {
+    match name {
+        "input" => "reads a line and throws it away, 1 if there was one and 0 at the end of input",
+        _ => "reads a line and gives the whole number on it",
+    }
+}
+
+pub struct Evaluator<'a> {
+    output: &'a mut Vec<String>,
+    // when set, constructs are paid for as they run instead of up front
+    meter: Option<&'a mut CoinManager>,
+    events: Option<&'a mut Vec<ExecutionEvent>>,
+    sink: Option<&'a mut dyn Output>, // where print writes as it runs, besides `output`
+    depth: usize,
+    max_depth: Option<usize>,
+    statement: Option<usize>, // top level statement being run
+    natives: Option<&'a NativeRegistry>,
+    input: Option<&'a mut dyn InputSource>,
+    modules: Option<&'a ModuleLoader>, // already loaded by the time imports run
+    debugger: Option<&'a mut Debugger>,
+    coins: Option<&'a CoinManager>, // balances the debugger shows when nothing's metered
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(output: &'a mut Vec<String>) -> Self {
+        Self {
+            output,
+            meter: None,
+            events: None,
+            sink: None,
+            depth: 0,
+            max_depth: None,
+            statement: None,
+            natives: None,
+            input: None,
+            modules: None,
+            debugger: None,
+            coins: None,
+        }
+    }
+
+    pub fn with_debugger(mut self, debugger: &'a mut Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    pub fn with_balances(mut self, coins: &'a CoinManager) -> Self {
+        self.coins = Some(coins);
+        self
+    }
+
+    pub fn with_modules(mut self, modules: &'a ModuleLoader) -> Self {
+        self.modules = Some(modules);
+        self
+    }
+
+    pub fn with_input(mut self, input: &'a mut dyn InputSource) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_natives(mut self, natives: &'a NativeRegistry) -> Self {
+        self.natives = Some(natives);
+        self
+    }
+
+    pub fn with_output(mut self, sink: &'a mut dyn Output) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    // there's no way to stop recursion in the language, so untrusted calls
+    // need a ceiling before they blow the stack
+    pub fn with_call_limit(mut self, limit: usize) -> Self {
+        self.max_depth = Some(limit);
+        self
+    }
+
+    pub fn with_meter(mut self, coin_manager: &'a mut CoinManager) -> Self {
+        self.meter = Some(coin_manager);
+        self
+    }
+
+    pub fn with_events(mut self, events: &'a mut Vec<ExecutionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn emit(&mut self, event: ExecutionEvent) {
+        if let Some(events) = self.events.as_deref_mut() {
+            events.push(event);
+        }
+    }
+
+    fn charge(&mut self, expr: &Expr) -> Result<(), ValidationError> {
+        if let Some(coin_manager) = self.meter.as_deref_mut() {
+            for cost in ResourceValidator::construct_cost(expr) {
+                coin_manager.spend_coins(cost.coin_type, cost.amt)?;
             }
         }
-        Expr::Let(name, val) => {
-            let v = eval_with_output(val, env, output)?;
-            env.insert(name.clone(), Expr::Number(v));
-            Ok(v)
+        Ok(())
+    }
+
+    fn pause(&mut self, index: usize, stmt: &Expr, env: &HashMap<String, Expr>) -> Result<(), ValidationError> {
+        let Some(debugger) = self.debugger.as_deref_mut() else {
+            return Ok(());
+        };
+        let coins = self.meter.as_deref().or(self.coins);
+        let (mut no_output, mut no_input) = (NullOutput, NoInput);
+        let out: &mut dyn Output = match self.sink.as_deref_mut() {
+            Some(sink) => sink,
+            None => &mut no_output,
+        };
+        let input: &mut dyn InputSource = match self.input.as_deref_mut() {
+            Some(input) => input,
+            None => &mut no_input,
+        };
+        debugger.statement(index, stmt, env, coins, out, input)
+    }
+
+    // a program or function body, statement by statement so the debugger can
+    // stop before each one
+    pub fn eval_statements(&mut self, body: &Expr, env: &mut HashMap<String, Expr>) -> Result<i64, ValidationError> {
+        match body {
+            Expr::Block(_) => self.eval(body, env),
+            stmt => {
+                self.pause(0, stmt, env)?;
+                self.eval(stmt, env)
+            }
         }
-        Expr::FnDef(name, params, body) => {
-            env.insert(
-                name.clone(),
-                Expr::FnDef(name.clone(), params.clone(), body.clone()),
-            );
-            Ok(0)
+    }
+
+    // input() only probes for end of input: the line it reads is dropped, and
+    // it gives 1 if there was one and 0 once input runs out. input_int() parses
+    // the line. Either takes an optional prompt
+    fn read_input(&mut self, name: &str, args: &[Expr], env: &mut HashMap<String, Expr>) -> Result<i64, ValidationError> {
+        let prompt = match args {
+            [] => None,
+            [Expr::String(text)] => Some(text.clone()),
+            [arg] => Some(self.eval(arg, env)?.to_string()),
+            _ => {
+                return Err(ValidationError::RuntimeError(format!(
+                    "Function '{}' expects at most 1 argument, got {}",
+                    name,
+                    args.len()
+                )));
+            }
+        };
+        self.emit(ExecutionEvent::FunctionCalled {
+            name: name.to_string(),
+            args: Vec::new(),
+        });
+
+        // the prompt has to be on screen before anyone can answer it
+        if let (Some(prompt), Some(sink)) = (&prompt, self.sink.as_deref_mut()) {
+            sink.write_str(prompt);
+            sink.flush();
+        }
+        let line = self.input.as_deref_mut().and_then(|input| input.read_line());
+
+        match (name, line) {
+            ("input", line) => Ok(line.is_some() as i64),
+            (_, None) => Err(ValidationError::RuntimeError(format!("{}: no more input", name))),
+            (_, Some(line)) => line.trim().parse().map_err(|_| {
+                ValidationError::RuntimeError(format!("{}: expected a whole number, got '{}'", name, line.trim()))
+            }),
         }
-        Expr::FnCall(name, args) => {
-            let func = env.get(name).cloned(); // clone out, avoid borrow checker issues
-            if let Some(Expr::FnDef(_, params, body)) = func {
-                if params.len() != args.len() {
-                    return Err(ValidationError::RuntimeError(format!(
-                        "Function '{}' expects {} arguments, got {}",
-                        name,
-                        params.len(),
-                        args.len()
-                    )));
+    }
+
+    pub fn eval(&mut self, expr: &Expr, env: &mut HashMap<String, Expr>) -> Result<i64, ValidationError> {
+        self.charge(expr)?;
+
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::String(_) => Ok(0), // String literals evaluate to 0 for numeric context
+            Expr::Import(spec, alias) => {
+                let module = self
+                    .modules
+                    .and_then(|modules| modules.imported(spec))
+                    .ok_or_else(|| ValidationError::RuntimeError(format!("Module '{}' hasn't been loaded", spec)))?;
+                for (name, definition) in module.bindings(alias) {
+                    env.insert(name, definition);
                 }
-                let mut local_env = env.clone();
-                for (param, arg_expr) in params.iter().zip(args) {
-                    let val = eval_with_output(arg_expr, env, output)?;
-                    local_env.insert(param.clone(), Expr::Number(val));
+                Ok(0)
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let lval = self.eval(lhs, env)?;
+                let rval = self.eval(rhs, env)?;
+                let result = match op {
+                    TokenTypes::Plus => lval.checked_add(rval),
+                    TokenTypes::Minus => lval.checked_sub(rval),
+                    TokenTypes::Star => lval.checked_mul(rval),
+                    TokenTypes::Slash => {
+                        if rval == 0 {
+                            return Err(ValidationError::RuntimeError("Division by zero".to_string()));
+                        }
+                        lval.checked_div(rval)
+                    }
+                    _ => return Err(ValidationError::RuntimeError("Invalid operator".to_string())),
                 }
-                eval_with_output(&body, &mut local_env, output)
-            } else {
-                Err(ValidationError::RuntimeError(format!("Undefined function '{}'", name)))
+                .ok_or_else(|| ValidationError::RuntimeError("Integer overflow".to_string()))?;
+                self.emit(ExecutionEvent::Arithmetic {
+                    op: *op,
+                    lhs: lval,
+                    rhs: rval,
+                    result,
+                });
+                Ok(result)
             }
-        }
-        Expr::Var(name) => {
-            if let Some(val) = env.get(name) {
-                match val {
-                    Expr::Number(n) => Ok(*n),
-                    Expr::FnDef(_, _, _) => {
-                        Err(ValidationError::RuntimeError(format!(
-                            "Cannot use function '{}' as a variable. Did you mean to call it with parentheses?", 
+            Expr::Let(name, val) => {
+                let v = self.eval(val, env)?;
+                env.insert(name.clone(), Expr::Number(v));
+                self.emit(ExecutionEvent::VariableDefined {
+                    name: name.clone(),
+                    value: v,
+                });
+                Ok(v)
+            }
+            Expr::FnDef(name, params, body) => {
+                if INPUT_FUNCTIONS.contains(&name.as_str()) || self.natives.is_some_and(|natives| natives.contains(name)) {
+                    return Err(redefined_builtin(name));
+                }
+                env.insert(
+                    name.clone(),
+                    Expr::FnDef(name.clone(), params.clone(), body.clone()),
+                );
+                self.emit(ExecutionEvent::FunctionDefined {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: format_expr(body),
+                });
+                Ok(0)
+            }
+            Expr::FnCall(name, args) => {
+                let func = env.get(name).cloned(); // clone out, avoid borrow checker issues
+                if let Some(Expr::FnDef(_, params, body)) = func {
+                    if params.len() != args.len() {
+                        return Err(ValidationError::RuntimeError(format!(
+                            "Function '{}' expects {} arguments, got {}",
+                            name,
+                            params.len(),
+                            args.len()
+                        )));
+                    }
+                    let mut local_env = env.clone();
+                    let mut arg_values = Vec::new();
+                    for (param, arg_expr) in params.iter().zip(args) {
+                        let val = self.eval(arg_expr, env)?;
+                        local_env.insert(param.clone(), Expr::Number(val));
+                        arg_values.push(val);
+                    }
+                    self.emit(ExecutionEvent::FunctionCalled {
+                        name: name.clone(),
+                        args: arg_values.clone(),
+                    });
+                    if self.max_depth.is_some_and(|max| self.depth >= max) {
+                        return Err(ValidationError::RuntimeError(format!(
+                            "Call depth limit exceeded in '{}'",
                             name
-                        )))
+                        )));
+                    }
+                    self.depth += 1;
+                    if let Some(debugger) = self.debugger.as_deref_mut() {
+                        debugger.enter(name, &params, &arg_values);
+                    }
+                    let result = self.eval_statements(&body, &mut local_env);
+                    if let Some(debugger) = self.debugger.as_deref_mut() {
+                        debugger.leave();
+                    }
+                    self.depth -= 1;
+                    result
+                } else if INPUT_FUNCTIONS.contains(&name.as_str()) {
+                    self.read_input(name, args, env)
+                } else if let Some(native) = self.natives.and_then(|natives| natives.get(name)) {
+                    if !native.arity.accepts(args.len()) {
+                        return Err(ValidationError::RuntimeError(format!(
+                            "Function '{}' expects {}, got {}",
+                            name,
+                            native.arity.description(),
+                            args.len()
+                        )));
                     }
-                    _ => Err(ValidationError::RuntimeError(format!("Variable '{}' is not a number", name))),
+                    let mut arg_values = Vec::new();
+                    for arg_expr in args {
+                        arg_values.push(self.eval(arg_expr, env)?);
+                    }
+                    self.emit(ExecutionEvent::FunctionCalled {
+                        name: name.clone(),
+                        args: arg_values.clone(),
+                    });
+                    if let Some(coin_manager) = self.meter.as_deref_mut() {
+                        for cost in &native.cost {
+                            coin_manager.spend_coins(cost.coin_type, cost.amt)?;
+                        }
+                    }
+                    native.call(&arg_values).map_err(|e| ValidationError::RuntimeError(format!("{}: {}", name, e)))
+                } else {
+                    Err(ValidationError::RuntimeError(format!("Undefined function '{}'", name)))
                 }
-            } else {
-                Err(ValidationError::RuntimeError(format!("Undefined variable '{}'", name)))
             }
-        }
-        Expr::Block(statements) => {
-            let mut result = 0;
-            for stmt in statements {
-                result = eval_with_output(stmt, env, output)?;
+            Expr::Var(name) => {
+                if let Some(val) = env.get(name) {
+                    match val {
+                        Expr::Number(n) => {
+                            let n = *n;
+                            self.emit(ExecutionEvent::VariableRead {
+                                name: name.clone(),
+                                value: n,
+                            });
+                            Ok(n)
+                        }
+                        Expr::FnDef(_, _, _) => {
+                            Err(ValidationError::RuntimeError(format!(
+                                "Cannot use function '{}' as a variable. Did you mean to call it with parentheses?", 
+                                name
+                            )))
+                        }
+                        _ => Err(ValidationError::RuntimeError(format!("Variable '{}' is not a number", name))),
+                    }
+                } else {
+                    Err(ValidationError::RuntimeError(format!("Undefined variable '{}'", name)))
+                }
             }
-            Ok(result)
-        }
-        Expr::Print(expr) => {
-            let output_str = match expr.as_ref() {
-                Expr::String(s) => s.clone(),
-                Expr::Number(n) => n.to_string(),
-                Expr::Var(name) => {
-                    if let Some(Expr::Number(n)) = env.get(name) {
-                        n.to_string()
-                    } else {
-                        return Err(ValidationError::RuntimeError(format!("Undefined variable: {}", name)));
+            Expr::Block(statements) => {
+                let mut result = 0;
+                for (i, stmt) in statements.iter().enumerate() {
+                    // function bodies are only ever run one call deep
+                    if self.depth == 0 {
+                        self.statement = Some(i);
                     }
+                    self.pause(i, stmt, env)?;
+                    result = self.eval(stmt, env)?;
                 }
-                other => {
-                    let val = eval_with_output(other, env, output)?;
-                    val.to_string()
+                Ok(result)
+            }
+            Expr::Print(expr) => {
+                let output_str = match expr.as_ref() {
+                    Expr::String(s) => s.clone(),
+                    Expr::Number(n) => n.to_string(),
+                    Expr::Var(name) => {
+                        if let Some(Expr::Number(n)) = env.get(name) {
+                            let n = *n;
+                            self.emit(ExecutionEvent::VariableRead {
+                                name: name.clone(),
+                                value: n,
+                            });
+                            n.to_string()
+                        } else {
+                            return Err(ValidationError::RuntimeError(format!("Undefined variable: {}", name)));
+                        }
+                    }
+                    other => {
+                        let val = self.eval(other, env)?;
+                        val.to_string()
+                    }
+                };
+                
+                if let Some(sink) = self.sink.as_deref_mut() {
+                    sink.write_str(&output_str);
+                    sink.write_str("\n");
                 }
-            };
-            
-            println!("{}", output_str);
-            output.push(output_str);
-            Ok(0) // print statements return 0
+                self.emit(ExecutionEvent::Printed {
+                    text: output_str.clone(),
+                });
+                self.output.push(output_str);
+                Ok(0) // print statements return 0
+            }
         }
     }
 }
-pub fn eval_with_validation(
+
+// everything a run produced, kept even when it fails part way through
+pub struct ProgramRun {
+    pub result: Result<i64, ValidationError>,
+    pub output: Vec<String>,
+    pub events: Vec<ExecutionEvent>,
+    pub statement: Option<usize>, // top level statement it ended in, None if nothing ran
+}
+
+pub fn run_program(
     expr: &Expr,
     validator: &mut ResourceValidator,
     env: &mut HashMap<String, Expr>,
-) -> Result<(i64, Vec<String>), ValidationError> {
+    mode: MeteringMode,
+    sink: &mut dyn Output,
+    input: &mut dyn InputSource,
+    debugger: Option<&mut Debugger>,
+) -> ProgramRun {
+    let mut output = Vec::new();
+    let mut events = Vec::new();
+    let mut statement = None;
+
+    // printed lines go to the sink as they happen so prompts show up in order,
+    // and are collected for the front end too
+    let result = match mode {
+        MeteringMode::Static => charge_up_front(expr, validator).and_then(|()| {
+            let mut evaluator = Evaluator::new(&mut output)
+                .with_events(&mut events)
+                .with_natives(validator.natives())
+                .with_modules(validator.modules())
+                .with_balances(validator.coin_manager())
+                .with_output(sink)
+                .with_input(input);
+            if let Some(debugger) = debugger {
+                evaluator = evaluator.with_debugger(debugger);
+            }
+            let result = evaluator.eval_statements(expr, env);
+            statement = evaluator.statement.or(Some(0));
+            result
+        }),
+        // pay as you go: coins are charged as each construct runs, so a let inside a
+        // function body costs once per call. Aborts with InsufficientFunds mid program
+        MeteringMode::Runtime => validator.check_limits(expr).and_then(|()| {
+            let (coin_manager, natives, modules) = validator.parts_mut();
+            let mut evaluator = Evaluator::new(&mut output)
+                .with_events(&mut events)
+                .with_natives(natives)
+                .with_modules(modules)
+                .with_meter(coin_manager)
+                .with_output(sink)
+                .with_input(input);
+            if let Some(debugger) = debugger {
+                evaluator = evaluator.with_debugger(debugger);
+            }
+            let result = evaluator.eval_statements(expr, env);
+            statement = evaluator.statement.or(Some(0));
+            result
+        }),
+    };
+
+    ProgramRun {
+        result,
+        output,
+        events,
+        statement,
+    }
+}
+
+fn charge_up_front(expr: &Expr, validator: &mut ResourceValidator) -> Result<(), ValidationError> {
     let costs = validator.validate_expression(expr)?;
 
     for cost in costs {
@@ -438,8 +943,143 @@ pub fn eval_with_validation(
             }
         }
     }
+    Ok(())
+}
 
-    let mut output = Vec::new();
-    let result = eval_with_output(expr, env, &mut output)?;
-    Ok((result, output))
+pub fn eval_with_validation(
+    expr: &Expr,
+    validator: &mut ResourceValidator,
+    env: &mut HashMap<String, Expr>,
+) -> Result<(i64, Vec<String>), ValidationError> {
+    let run = run_program(expr, validator, env, MeteringMode::Static, &mut NullOutput, &mut NoInput, None);
+    run.result.map(|result| (result, run.output))
+}
+
+pub fn eval_with_metering(
+    expr: &Expr,
+    validator: &mut ResourceValidator,
+    env: &mut HashMap<String, Expr>,
+) -> Result<(i64, Vec<String>), ValidationError> {
+    let run = run_program(expr, validator, env, MeteringMode::Runtime, &mut NullOutput, &mut NoInput, None);
+    run.result.map(|result| (result, run.output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tokenize, CoinError};
+
+    fn parse(source: &str) -> Expr {
+        Parser::new(tokenize(source)).parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_fn_body_with_statements() {
+        let mut env = HashMap::new();
+        let program = parse("fn f(a) { let b = a * 2; b + 1 }; f(4)");
+        assert_eq!(eval(&program, &mut env).unwrap(), 9);
+        assert!(!env.contains_key("b"));
+    }
+
+    #[test]
+    fn test_runtime_metering_charges_per_call() {
+        let program = parse("fn f(a) { let b = a; b }; f(1); f(2); f(3)");
+
+        let mut static_validator = ResourceValidator::new(CoinManager::with_balances(10, 10));
+        eval_with_validation(&program, &mut static_validator, &mut HashMap::new()).unwrap();
+        assert_eq!(static_validator.coin_manager().get_balance(CoinType::Variable), 9);
+
+        let mut metered_validator = ResourceValidator::new(CoinManager::with_balances(10, 10));
+        eval_with_metering(&program, &mut metered_validator, &mut HashMap::new()).unwrap();
+        assert_eq!(metered_validator.coin_manager().get_balance(CoinType::Variable), 7);
+        assert_eq!(metered_validator.coin_manager().get_balance(CoinType::Function), 9);
+    }
+
+    #[test]
+    fn test_runtime_metering_aborts_mid_program() {
+        let program = parse("let a = 1; print(a); let b = 2; print(b)");
+        let mut validator = ResourceValidator::new(CoinManager::with_balances(1, 0));
+        let mut env = HashMap::new();
+
+        let err = eval_with_metering(&program, &mut validator, &mut env).unwrap_err();
+        assert!(matches!(
+            err,
+            ValidationError::CoinError(CoinError::InsufficientFunds {
+                coin_type: CoinType::Variable,
+                ..
+            })
+        ));
+        assert!(env.contains_key("a"));
+        assert!(!env.contains_key("b"));
+        assert_eq!(validator.coin_manager().get_balance(CoinType::Variable), 0);
+    }
+
+    #[test]
+    fn test_run_program_emits_events() {
+        let program = parse("fn add(a, b) { a + b }; let x = add(2, 3)");
+        let mut validator = ResourceValidator::new(CoinManager::new());
+        let run = run_program(&program, &mut validator, &mut HashMap::new(), MeteringMode::Static, &mut NullOutput, &mut NoInput, None);
+
+        assert_eq!(run.result.unwrap(), 5);
+        assert!(run.events.contains(&ExecutionEvent::FunctionCalled {
+            name: "add".to_string(),
+            args: vec![2, 3],
+        }));
+        assert!(run.events.contains(&ExecutionEvent::Arithmetic {
+            op: TokenTypes::Plus,
+            lhs: 2,
+            rhs: 3,
+            result: 5,
+        }));
+        assert_eq!(
+            run.events.last(),
+            Some(&ExecutionEvent::VariableDefined {
+                name: "x".to_string(),
+                value: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_where_runs_and_parses_stop() {
+        let program = parse("fn f(n) { let m = n; m / 0 }; let x = 1; f(x)");
+        let mut validator = ResourceValidator::new(CoinManager::new());
+        let run = run_program(&program, &mut validator, &mut HashMap::new(), MeteringMode::Static, &mut NullOutput, &mut NoInput, None);
+        assert!(run.result.is_err());
+        assert_eq!(run.statement, Some(2));
+
+        let mut poor = ResourceValidator::new(CoinManager::with_balances(0, 0));
+        let run = run_program(&program, &mut poor, &mut HashMap::new(), MeteringMode::Static, &mut NullOutput, &mut NoInput, None);
+        assert_eq!(run.statement, None);
+
+        let mut parser = Parser::new(tokenize("let x = 1;\nlet = 2"));
+        assert!(parser.parse_statements().is_err());
+        assert_eq!(parser.error_pos(), Some((2, 5)));
+    }
+
+    #[test]
+    fn test_runtime_metering_charges_natives() {
+        let program = parse("pow(2, 3) + pow(2, 4)");
+        let natives = crate::NativeRegistry::with_stdlib();
+        let mut validator = ResourceValidator::new(CoinManager::with_balances(0, 1)).with_natives(natives);
+
+        let run = run_program(&program, &mut validator, &mut HashMap::new(), MeteringMode::Runtime, &mut NullOutput, &mut NoInput, None);
+        assert!(matches!(run.result, Err(ValidationError::CoinError(_))));
+        // the first call was paid for before the second ran out
+        assert_eq!(validator.coin_manager().get_balance(CoinType::Function), 0);
+    }
+
+    #[test]
+    fn test_print_writes_to_output() {
+        let program = parse("print(\"a\"); let x = 2; print(x)");
+        let mut buffer = crate::BufferOutput::new();
+        let mut collected = Vec::new();
+        Evaluator::new(&mut collected)
+            .with_output(&mut buffer)
+            .eval(&program, &mut HashMap::new())
+            .unwrap();
+
+        assert_eq!(buffer.contents(), "a\n2\n");
+        assert_eq!(collected, vec!["a", "2"]);
+    }
 }
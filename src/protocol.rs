@@ -0,0 +1,404 @@
+// line delimited json for editors and grading scripts: one request object
+// per line in, one response per line out, and nothing else on the writer
+use std::io::{self, BufRead, Write};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    repl::{parse_source, RunError, RunErrorKind, RunOutcome},
+    CoinCost, CoinReward, CoinType, IncomeSource, ObjectiveProgress, QuestError, QuestGraph, QuestStatus,
+    Repl,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum Request {
+    Eval { source: String },
+    Cost { source: String },
+    Balance,
+    Quests,
+    Progress { quest: Option<String> },
+    Accept { quest: String },
+    Abandon { quest: String },
+    Track { quest: Option<String> }, // no quest untracks
+    Reset,
+}
+
+// lines are read as bytes so one that isn't UTF-8 gets an error response
+// instead of ending the session
+pub fn serve(repl: &mut Repl, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    for line in input.split(b'\n') {
+        let response = match String::from_utf8(line?) {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => handle_line(repl, &line),
+            Err(e) => failure(Value::Null, request_error(format!("Invalid UTF-8: {}", e.utf8_error())), None),
+        };
+        writeln!(output, "{}", response)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// the request's `id`, if any, is echoed back so responses can be matched up
+pub fn handle_line(repl: &mut Repl, line: &str) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return failure(Value::Null, request_error(format!("Invalid JSON: {}", e)), None),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+    match serde_json::from_value::<Request>(request) {
+        Ok(request) => match handle(repl, request) {
+            Ok(result) => json!({ "id": id, "ok": true, "result": result }),
+            Err((error, result)) => failure(id, error, result),
+        },
+        Err(e) => failure(id, request_error(format!("Invalid request: {}", e)), None),
+    }
+}
+
+// a failed eval still reports what ran before it stopped
+fn handle(repl: &mut Repl, request: Request) -> Result<Value, (Value, Option<Value>)> {
+    match request {
+        Request::Eval { source } => {
+            let outcome = repl.run_source(&source);
+            let result = outcome_json(&outcome);
+            match &outcome.error {
+                Some(e) => Err((error_json(e), Some(result))),
+                None => Ok(result),
+            }
+        }
+        Request::Cost { source } => {
+            let statements = parse_source(&source).map_err(|e| (error_json(&e), None))?;
+            let estimate = repl.validator.estimate(&statements);
+            Ok(json!({
+                "total": costs_json(&estimate.total),
+                "statements": estimate
+                    .statements
+                    .iter()
+                    .map(|stmt| json!({ "pos": pos_json(Some(stmt.pos)), "costs": costs_json(&stmt.costs) }))
+                    .collect::<Vec<_>>(),
+                "balance_after": per_coin(|coin_type| estimate.balance_after[&coin_type]),
+                "limit_error": estimate.limit_error.as_ref().map(|e| e.to_string()),
+                "affordable": estimate.affordable,
+            }))
+        }
+        Request::Balance => {
+            let coin_manager = repl.validator.coin_manager();
+            Ok(json!({
+                "balances": per_coin(|coin_type| coin_manager.get_balance(coin_type)),
+                "debt": per_coin(|coin_type| coin_manager.debt(coin_type)),
+                "credit_limits": per_coin(|coin_type| coin_manager.credit_limit(coin_type)),
+            }))
+        }
+        Request::Quests => {
+            let graph = QuestGraph::from_manager(&repl.quest_manager);
+            let quests: Vec<Value> = graph
+                .nodes()
+                .iter()
+                .map(|node| {
+                    let accepted = repl.quest_manager.get_accepted_quests().iter().any(|q| q.id == node.id);
+                    json!({
+                        "id": node.id,
+                        "title": node.title,
+                        "status": match node.status {
+                            QuestStatus::Completed => "completed",
+                            QuestStatus::Available => "available",
+                            QuestStatus::Locked => "locked",
+                        },
+                        "accepted": accepted,
+                        "prerequisites": node.prerequisites,
+                    })
+                })
+                .collect();
+            Ok(json!({ "quests": quests }))
+        }
+        Request::Progress { quest } => {
+            let ids: Vec<String> = match quest {
+                Some(id) if repl.quest_manager.has_quest(&id) => vec![id],
+                Some(id) => {
+                    let error = json!({
+                        "kind": "unknown_quest",
+                        "message": format!("Unknown quest '{}'", id),
+                        "pos": null,
+                    });
+                    return Err((error, None));
+                }
+                None => repl.quest_manager.get_accepted_quests().iter().map(|q| q.id.clone()).collect(),
+            };
+            let quests: Vec<Value> = ids
+                .iter()
+                .filter_map(|id| {
+                    let progress = repl.quest_progress(id)?;
+                    Some(json!({
+                        "id": id,
+                        "percentage": progress.completion_percentage(),
+                        "complete": progress.is_complete,
+                        "objectives": progress.objectives.iter().map(objective_json).collect::<Vec<_>>(),
+                    }))
+                })
+                .collect();
+            Ok(json!({ "quests": quests }))
+        }
+        Request::Accept { quest } => {
+            repl.quest_manager.accept_quest(&quest).map_err(|e| (quest_error_json(&e), None))?;
+            Ok(json!({}))
+        }
+        Request::Abandon { quest } => {
+            repl.quest_manager.abandon_quest(&quest).map_err(|e| (quest_error_json(&e), None))?;
+            Ok(json!({}))
+        }
+        Request::Track { quest } => {
+            match &quest {
+                Some(id) => repl.quest_manager.track_quest(id).map_err(|e| (quest_error_json(&e), None))?,
+                None => repl.quest_manager.untrack_quest(),
+            }
+            Ok(json!({ "tracked": quest }))
+        }
+        Request::Reset => {
+            repl.env.clear();
+            repl.clear_history();
+            Ok(json!({}))
+        }
+    }
+}
+
+fn outcome_json(outcome: &RunOutcome) -> Value {
+    json!({
+        "value": outcome.value,
+        "output": outcome.output,
+        "charged": costs_json(&outcome.charged),
+        "challenges": outcome
+            .challenges
+            .iter()
+            .map(|(quest, report)| json!({
+                "quest": quest,
+                "function": report.function,
+                "passed": report.passed(),
+                "total": report.results.len(),
+            }))
+            .collect::<Vec<_>>(),
+        "completed_quests": outcome.completed_quests,
+        "rewards": rewards_json(&outcome.rewards),
+        "income": outcome.income.as_ref().map(|record| json!({
+            "source": match record.source {
+                IncomeSource::Stipend { .. } => "stipend",
+                IncomeSource::DailyBonus { .. } => "daily_bonus",
+            },
+            "rewards": rewards_json(&record.rewards),
+        })),
+        "interest": outcome
+            .interest
+            .iter()
+            .map(|(coin_type, amount)| json!({ "coin_type": coin_name(*coin_type), "amount": amount }))
+            .collect::<Vec<_>>(),
+        "achievements": outcome.achievements.iter().map(|a| a.id.as_str()).collect::<Vec<_>>(),
+    })
+}
+
+fn objective_json(node: &ObjectiveProgress) -> Value {
+    json!({
+        "description": node.description,
+        "current": node.current,
+        "target": node.target,
+        "complete": node.complete,
+        "children": node.children.iter().map(objective_json).collect::<Vec<_>>(),
+    })
+}
+
+fn error_json(error: &RunError) -> Value {
+    json!({
+        "kind": match error.kind {
+            RunErrorKind::Parse => "parse",
+            RunErrorKind::Coins => "coins",
+            RunErrorKind::Runtime => "runtime",
+            RunErrorKind::Import => "import",
+        },
+        "message": error.message,
+        "pos": pos_json(error.pos),
+    })
+}
+
+fn quest_error_json(error: &QuestError) -> Value {
+    json!({
+        "kind": match error {
+            QuestError::UnknownQuest(_) => "unknown_quest",
+            QuestError::NotAvailable(_) => "not_available",
+            QuestError::NotAccepted(_) => "not_accepted",
+            QuestError::NoMoreHints(_) => "no_more_hints",
+            QuestError::CoinError(_) => "coins",
+        },
+        "message": error.to_string(),
+        "pos": null,
+    })
+}
+
+fn request_error(message: String) -> Value {
+    json!({ "kind": "request", "message": message, "pos": null })
+}
+
+fn failure(id: Value, error: Value, result: Option<Value>) -> Value {
+    let mut response = json!({ "id": id, "ok": false, "error": error });
+    if let Some(result) = result {
+        response["result"] = result;
+    }
+    response
+}
+
+fn pos_json(pos: Option<(usize, usize)>) -> Value {
+    match pos {
+        Some((line, col)) => json!({ "line": line, "col": col }),
+        None => Value::Null,
+    }
+}
+
+fn coin_name(coin_type: CoinType) -> &'static str {
+    match coin_type {
+        CoinType::Variable => "variable",
+        CoinType::Function => "function",
+    }
+}
+
+fn per_coin<T: Into<Value>>(value: impl Fn(CoinType) -> T) -> Value {
+    CoinType::ALL
+        .into_iter()
+        .map(|coin_type| (coin_name(coin_type).to_string(), value(coin_type).into()))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn costs_json(costs: &[CoinCost]) -> Vec<Value> {
+    costs
+        .iter()
+        .map(|cost| json!({ "coin_type": coin_name(cost.coin_type), "amount": cost.amt }))
+        .collect()
+}
+
+fn rewards_json(rewards: &[CoinReward]) -> Vec<Value> {
+    rewards
+        .iter()
+        .map(|reward| json!({ "coin_type": coin_name(reward.coin_type), "amount": reward.amount }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_reports_value_output_and_charges() {
+        let mut repl = Repl::new();
+        let response = handle_line(&mut repl, r#"{"id": 7, "method": "eval", "source": "let x = 2; print(x); x + 3"}"#);
+
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["ok"], true);
+        let result = &response["result"];
+        assert_eq!(result["value"], 5);
+        assert_eq!(result["output"], json!(["2"]));
+        assert_eq!(result["charged"], json!([{ "coin_type": "variable", "amount": 1 }]));
+        // hello_world is accepted from the start and wants arithmetic
+        assert_eq!(result["completed_quests"], json!(["hello_world"]));
+        assert_eq!(result["achievements"], json!(["first_run"]));
+    }
+
+    #[test]
+    fn test_errors_carry_kind_and_pos() {
+        let mut repl = Repl::new();
+
+        let parse = handle_line(&mut repl, r#"{"method": "eval", "source": "let x = 1;\nlet = 2"}"#);
+        assert_eq!(parse["ok"], false);
+        assert_eq!(parse["error"]["kind"], "parse");
+        assert_eq!(parse["error"]["pos"], json!({ "line": 2, "col": 5 }));
+
+        let runtime = handle_line(&mut repl, r#"{"method": "eval", "source": "print(1); 1 / 0"}"#);
+        assert_eq!(runtime["error"]["kind"], "runtime");
+        assert_eq!(runtime["error"]["pos"]["col"], 11);
+        assert_eq!(runtime["result"]["output"], json!(["1"]));
+
+        // overflow is an error, not a crash
+        let overflow = handle_line(&mut repl, r#"{"method": "eval", "source": "let a = 1;\n9999999999 * 9999999999"}"#);
+        assert_eq!(overflow["error"]["kind"], "runtime");
+        assert_eq!(overflow["error"]["message"], "Runtime err: Integer overflow");
+        assert_eq!(overflow["error"]["pos"]["line"], 2);
+
+        let literal = handle_line(&mut repl, r#"{"method": "eval", "source": "9999999999999999999999"}"#);
+        assert_eq!(literal["error"]["kind"], "parse");
+        assert_eq!(literal["error"]["pos"]["line"], 1);
+
+        let bad = handle_line(&mut repl, r#"{"id": "a", "method": "fly"}"#);
+        assert_eq!(bad["id"], "a");
+        assert_eq!(bad["error"]["kind"], "request");
+        assert_eq!(handle_line(&mut repl, "not json")["error"]["kind"], "request");
+    }
+
+    #[test]
+    fn test_queries_and_reset() {
+        let mut repl = Repl::new();
+        let cost = handle_line(&mut repl, r#"{"method": "cost", "source": "let a = 1; fn f(n) { n }"}"#);
+        assert_eq!(cost["result"]["affordable"], true);
+        assert_eq!(cost["result"]["statements"].as_array().unwrap().len(), 2);
+
+        let balance = handle_line(&mut repl, r#"{"method": "balance"}"#);
+        let before = balance["result"]["balances"]["variable"].as_i64().unwrap();
+        handle_line(&mut repl, r#"{"method": "eval", "source": "let a = 1"}"#);
+        let balance = handle_line(&mut repl, r#"{"method": "balance"}"#);
+        assert_eq!(balance["result"]["balances"]["variable"].as_i64().unwrap(), before - 1);
+
+        let quests = handle_line(&mut repl, r#"{"method": "quests"}"#);
+        assert_eq!(quests["result"]["quests"][0]["id"], "hello_world");
+        assert_eq!(quests["result"]["quests"][0]["accepted"], true);
+
+        let progress = handle_line(&mut repl, r#"{"method": "progress", "quest": "hello_world"}"#);
+        assert_eq!(progress["result"]["quests"][0]["objectives"][0]["complete"], false);
+        let unknown = handle_line(&mut repl, r#"{"method": "progress", "quest": "nope"}"#);
+        assert_eq!(unknown["error"]["kind"], "unknown_quest");
+
+        assert_eq!(handle_line(&mut repl, r#"{"method": "reset"}"#)["ok"], true);
+        let after_reset = handle_line(&mut repl, r#"{"method": "eval", "source": "a"}"#);
+        assert_eq!(after_reset["error"]["kind"], "runtime");
+    }
+
+    #[test]
+    fn test_accept_abandon_and_track() {
+        let mut repl = Repl::new();
+        let accepted = |repl: &Repl| repl.quest_manager.get_accepted_quests().iter().map(|q| q.id.clone()).collect::<Vec<_>>();
+
+        let abandon = handle_line(&mut repl, r#"{"method": "abandon", "quest": "hello_world"}"#);
+        assert_eq!(abandon["ok"], true);
+        assert!(accepted(&repl).is_empty());
+        assert!(repl.quest_manager.tracked_quest().is_none());
+
+        let track = handle_line(&mut repl, r#"{"method": "track", "quest": "hello_world"}"#);
+        assert_eq!(track["error"]["kind"], "not_accepted");
+
+        assert_eq!(handle_line(&mut repl, r#"{"method": "accept", "quest": "hello_world"}"#)["ok"], true);
+        assert_eq!(accepted(&repl), vec!["hello_world"]);
+        let track = handle_line(&mut repl, r#"{"method": "track", "quest": "hello_world"}"#);
+        assert_eq!(track["result"]["tracked"], "hello_world");
+        assert_eq!(repl.quest_manager.tracked_quest().unwrap().id, "hello_world");
+
+        handle_line(&mut repl, r#"{"method": "track"}"#);
+        assert!(repl.quest_manager.tracked_quest().is_none());
+
+        let unknown = handle_line(&mut repl, r#"{"method": "accept", "quest": "nope"}"#);
+        assert_eq!(unknown["error"]["kind"], "unknown_quest");
+    }
+
+    #[test]
+    fn test_serve_survives_invalid_utf8() {
+        let mut repl = Repl::new();
+        let input: &[u8] = b"{\"id\": 1, \"method\": \"balance\"}\r\n\xff\xfe\n\n{\"id\": 2, \"method\": \"balance\"}";
+        let mut output = Vec::new();
+        serve(&mut repl, input, &mut output).unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[1]["error"]["kind"], "request");
+        assert_eq!(responses[2]["id"], 2);
+    }
+}
@@ -0,0 +1,402 @@
+// the prerequisite graph as ascii for the repl, or dot/mermaid for docs
+use std::collections::{HashMap, HashSet};
+
+use crate::{quest_pack, QuestManager};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuestStatus {
+    Completed,
+    Available,
+    Locked,
+}
+
+impl QuestStatus {
+    fn icon(&self) -> &str {
+        match self {
+            QuestStatus::Completed => "✅",
+            QuestStatus::Available => "🎯",
+            QuestStatus::Locked => "🔒",
+        }
+    }
+
+    fn class(&self) -> &str {
+        match self {
+            QuestStatus::Completed => "completed",
+            QuestStatus::Available => "available",
+            QuestStatus::Locked => "locked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestNode {
+    pub id: String,
+    pub title: String,
+    pub status: QuestStatus,
+    pub prerequisites: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestGraph {
+    nodes: Vec<QuestNode>, // in the order quests were loaded
+}
+
+impl QuestGraph {
+    pub fn new(nodes: Vec<QuestNode>) -> Self {
+        Self { nodes }
+    }
+
+    pub fn from_manager(quest_manager: &QuestManager) -> Self {
+        let completed: HashSet<&str> = quest_manager
+            .get_completed_quests()
+            .iter()
+            .map(|q| q.id.as_str())
+            .collect();
+
+        // repeatable quests are in both lists, the active copy wins
+        let mut seen = HashSet::new();
+        let nodes = quest_manager
+            .get_active_quests()
+            .iter()
+            .chain(quest_manager.get_completed_quests())
+            .filter(|q| seen.insert(q.id.as_str()))
+            .map(|q| QuestNode {
+                id: q.id.clone(),
+                title: q.title.clone(),
+                status: if completed.contains(q.id.as_str()) {
+                    QuestStatus::Completed
+                } else if q.is_unlocked() {
+                    QuestStatus::Available
+                } else {
+                    QuestStatus::Locked
+                },
+                prerequisites: q.prerequisites.clone(),
+            })
+            .collect();
+
+        Self { nodes }
+    }
+
+    pub fn nodes(&self) -> &[QuestNode] {
+        &self.nodes
+    }
+
+    fn node(&self, id: &str) -> Option<&QuestNode> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    pub fn find_cycle(&self) -> Option<Vec<String>> {
+        let graph: HashMap<&str, &[String]> = self
+            .nodes
+            .iter()
+            .map(|n| (n.id.as_str(), n.prerequisites.as_slice()))
+            .collect();
+        quest_pack::find_cycle(&graph)
+    }
+
+    // locked quests that can never unlock: a prerequisite is missing, in a
+    // cycle, or unreachable itself
+    pub fn unreachable(&self) -> Vec<&str> {
+        let mut reachable: HashSet<&str> = self
+            .nodes
+            .iter()
+            .filter(|n| n.status != QuestStatus::Locked)
+            .map(|n| n.id.as_str())
+            .collect();
+
+        loop {
+            let newly: Vec<&str> = self
+                .nodes
+                .iter()
+                .filter(|n| !reachable.contains(n.id.as_str()))
+                .filter(|n| n.prerequisites.iter().all(|p| reachable.contains(p.as_str())))
+                .map(|n| n.id.as_str())
+                .collect();
+            if newly.is_empty() {
+                break;
+            }
+            reachable.extend(newly);
+        }
+
+        self.nodes
+            .iter()
+            .map(|n| n.id.as_str())
+            .filter(|id| !reachable.contains(id))
+            .collect()
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(cycle) = self.find_cycle() {
+            warnings.push(format!("Prerequisites form a cycle: {}", cycle.join(" -> ")));
+        }
+        for id in self.unreachable() {
+            let missing: Vec<&str> = self
+                .node(id)
+                .map(|n| {
+                    n.prerequisites
+                        .iter()
+                        .map(|p| p.as_str())
+                        .filter(|p| self.node(p).is_none())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if missing.is_empty() {
+                warnings.push(format!("'{}' can never be unlocked", id));
+            } else {
+                warnings.push(format!(
+                    "'{}' can never be unlocked, it needs missing quest(s): {}",
+                    id,
+                    missing.join(", ")
+                ));
+            }
+        }
+        warnings
+    }
+
+    // a tree hanging each quest off its first prerequisite, the others are
+    // listed next to it so nothing is drawn twice
+    pub fn render_ascii(&self) -> String {
+        let mut children: HashMap<&str, Vec<&QuestNode>> = HashMap::new();
+        let mut roots = Vec::new();
+        for node in &self.nodes {
+            match node.prerequisites.first() {
+                Some(parent) if self.node(parent).is_some() => {
+                    children.entry(parent.as_str()).or_default().push(node)
+                }
+                _ => roots.push(node),
+            }
+        }
+
+        let mut out = String::new();
+        let mut drawn = HashSet::new();
+        for root in roots {
+            self.draw(root, "", None, &children, &mut drawn, &mut out);
+        }
+        // whatever only hangs off a cycle never gets reached from a root
+        for node in &self.nodes {
+            if !drawn.contains(node.id.as_str()) {
+                self.draw(node, "", None, &children, &mut drawn, &mut out);
+            }
+        }
+        out
+    }
+
+    fn draw<'a>(
+        &self,
+        node: &'a QuestNode,
+        prefix: &str,
+        last: Option<bool>, // None for roots
+        children: &HashMap<&str, Vec<&'a QuestNode>>,
+        drawn: &mut HashSet<&'a str>,
+        out: &mut String,
+    ) {
+        let branch = match last {
+            None => "",
+            Some(true) => "└── ",
+            Some(false) => "├── ",
+        };
+        let mut line = format!("{}{}{} {} ({})", prefix, branch, node.status.icon(), node.title, node.id);
+        if node.prerequisites.len() > 1 {
+            line.push_str(&format!(" +needs {}", node.prerequisites[1..].join(", ")));
+        }
+        if !drawn.insert(node.id.as_str()) {
+            out.push_str(&format!("{} (see above)\n", line));
+            return;
+        }
+        out.push_str(&line);
+        out.push('\n');
+
+        let child_prefix = match last {
+            None => prefix.to_string(),
+            Some(true) => format!("{}    ", prefix),
+            Some(false) => format!("{}│   ", prefix),
+        };
+        let kids = children.get(node.id.as_str()).map(Vec::as_slice).unwrap_or(&[]);
+        for (i, child) in kids.iter().enumerate() {
+            self.draw(child, &child_prefix, Some(i + 1 == kids.len()), children, drawn, out);
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph quests {\n    rankdir=LR;\n    node [shape=box, style=filled];\n");
+        for node in &self.nodes {
+            let color = match node.status {
+                QuestStatus::Completed => "palegreen",
+                QuestStatus::Available => "lightyellow",
+                QuestStatus::Locked => "lightgrey",
+            };
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\", fillcolor={}];\n",
+                escape_dot(&node.id),
+                escape_dot(&node.title),
+                color
+            ));
+        }
+        for node in &self.nodes {
+            for prerequisite in &node.prerequisites {
+                out.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    escape_dot(prerequisite),
+                    escape_dot(&node.id)
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let ids = self.mermaid_ids();
+        let mut out = String::from("flowchart LR\n");
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "    {}[\"{}\"]:::{}\n",
+                ids[node.id.as_str()],
+                node.title.replace('"', "#quot;"),
+                node.status.class()
+            ));
+        }
+        for node in &self.nodes {
+            for prerequisite in &node.prerequisites {
+                out.push_str(&format!("    {} --> {}\n", ids[prerequisite.as_str()], ids[node.id.as_str()]));
+            }
+        }
+        out.push_str("    classDef completed fill:#9f9\n");
+        out.push_str("    classDef available fill:#ff9\n");
+        out.push_str("    classDef locked fill:#ccc\n");
+        out
+    }
+
+    // ids that sanitize to the same thing get a numbered suffix so nodes
+    // don't merge, e.g. a-b and a_b become q_a_b and q_a_b_2
+    fn mermaid_ids(&self) -> HashMap<&str, String> {
+        let mut ids: HashMap<&str, String> = HashMap::new();
+        let mut taken = HashSet::new();
+        let all = self
+            .nodes
+            .iter()
+            .flat_map(|node| std::iter::once(&node.id).chain(&node.prerequisites));
+        for id in all {
+            if ids.contains_key(id.as_str()) {
+                continue;
+            }
+            let base = mermaid_id(id);
+            let mut unique = base.clone();
+            let mut n = 2;
+            while !taken.insert(unique.clone()) {
+                unique = format!("{}_{}", base, n);
+                n += 1;
+            }
+            ids.insert(id, unique);
+        }
+        ids
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// mermaid ids can't have spaces or punctuation, and the prefix keeps ids
+// like `end` from being read as keywords
+fn mermaid_id(id: &str) -> String {
+    let sanitized: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    format!("q_{}", sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, status: QuestStatus, prerequisites: &[&str]) -> QuestNode {
+        QuestNode {
+            id: id.to_string(),
+            title: id.to_uppercase(),
+            status,
+            prerequisites: prerequisites.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    fn diamond() -> QuestGraph {
+        QuestGraph::new(vec![
+            node("a", QuestStatus::Completed, &[]),
+            node("b", QuestStatus::Available, &["a"]),
+            node("c", QuestStatus::Available, &["a"]),
+            node("d", QuestStatus::Locked, &["b", "c"]),
+        ])
+    }
+
+    #[test]
+    fn test_ascii_tree() {
+        let expected = "\
+✅ A (a)
+├── 🎯 B (b)
+│   └── 🔒 D (d) +needs c
+└── 🎯 C (c)
+";
+        assert_eq!(diamond().render_ascii(), expected);
+    }
+
+    #[test]
+    fn test_dot_and_mermaid_edges() {
+        let graph = diamond();
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"c\" -> \"d\";"));
+        assert!(dot.contains("\"a\" [label=\"A\", fillcolor=palegreen];"));
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("    q_b --> q_d\n"));
+        assert!(mermaid.contains("    q_d[\"D\"]:::locked\n"));
+    }
+
+    #[test]
+    fn test_mermaid_ids_stay_distinct() {
+        let graph = QuestGraph::new(vec![
+            node("a-b", QuestStatus::Available, &[]),
+            node("a_b", QuestStatus::Available, &["a-b"]),
+            node("end", QuestStatus::Locked, &["a_b"]),
+        ]);
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.contains("    q_a_b[\"A-B\"]:::available\n"));
+        assert!(mermaid.contains("    q_a_b --> q_a_b_2\n"));
+        assert!(mermaid.contains("    q_a_b_2 --> q_end\n"));
+    }
+
+    #[test]
+    fn test_cycles_and_unreachable() {
+        assert!(diamond().warnings().is_empty());
+
+        let graph = QuestGraph::new(vec![
+            node("a", QuestStatus::Available, &[]),
+            node("x", QuestStatus::Locked, &["y"]),
+            node("y", QuestStatus::Locked, &["x"]),
+            node("z", QuestStatus::Locked, &["ghost"]),
+            node("after_z", QuestStatus::Locked, &["z", "a"]),
+        ]);
+
+        assert_eq!(graph.find_cycle().unwrap().len(), 3);
+        assert_eq!(graph.unreachable(), vec!["x", "y", "z", "after_z"]);
+        let warnings = graph.warnings();
+        assert!(warnings[0].starts_with("Prerequisites form a cycle"));
+        assert!(warnings.iter().any(|w| w.contains("missing quest(s): ghost")));
+        // cycle members still get drawn
+        assert!(graph.render_ascii().contains("(x)"));
+    }
+
+    #[test]
+    fn test_from_starter_quests() {
+        let mut quest_manager = QuestManager::new();
+        quest_manager.initialize_starter_quests();
+        let graph = QuestGraph::from_manager(&quest_manager);
+
+        assert_eq!(graph.nodes().len(), quest_manager.get_active_quests().len());
+        assert!(graph.warnings().is_empty());
+        assert!(graph.render_ascii().starts_with("🎯 Hello World (hello_world)\n"));
+    }
+}
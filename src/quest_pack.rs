@@ -0,0 +1,665 @@
+// quests defined in toml/json files instead of rust, see quests/default.toml
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    quest_system::{Hint, QuestDifficulty, QuestKind},
+    CoinReward, CoinType, Quest, QuestObjective, TestCase,
+};
+
+pub const DEFAULT_PACK: &str = include_str!("../quests/default.toml");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuestPackError {
+    Io(String),
+    Parse(String),
+    UnsupportedFormat(String),
+    UnknownObjective { quest_id: String, kind: String },
+    MissingField { quest_id: String, field: String },
+    UnexpectedField { quest_id: String, field: String },
+    UnknownCoinType { quest_id: String, name: String },
+    UnknownDifficulty { quest_id: String, name: String },
+    UnknownScope { quest_id: String, name: String },
+    UnknownQuestKind { quest_id: String, name: String },
+    DuplicateQuest(String),
+    DanglingPrerequisite { quest_id: String, prerequisite: String },
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for QuestPackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuestPackError::Io(e) => write!(f, "Could not read quest pack: {}", e),
+            QuestPackError::Parse(e) => write!(f, "Invalid quest pack: {}", e),
+            QuestPackError::UnsupportedFormat(path) => {
+                write!(f, "'{}' is not a .toml or .json quest pack", path)
+            }
+            QuestPackError::UnknownObjective { quest_id, kind } => {
+                write!(f, "Quest '{}' has unknown objective kind '{}'", quest_id, kind)
+            }
+            QuestPackError::MissingField { quest_id, field } => {
+                write!(f, "Quest '{}' is missing field '{}'", quest_id, field)
+            }
+            QuestPackError::UnexpectedField { quest_id, field } => {
+                write!(f, "Quest '{}' sets '{}', which its kind doesn't use", quest_id, field)
+            }
+            QuestPackError::UnknownCoinType { quest_id, name } => {
+                write!(f, "Quest '{}' rewards unknown coin type '{}'", quest_id, name)
+            }
+            QuestPackError::UnknownDifficulty { quest_id, name } => {
+                write!(f, "Quest '{}' has unknown difficulty '{}'", quest_id, name)
+            }
+            QuestPackError::UnknownScope { quest_id, name } => {
+                write!(f, "Quest '{}' has unknown objective scope '{}'", quest_id, name)
+            }
+            QuestPackError::UnknownQuestKind { quest_id, name } => {
+                write!(f, "Quest '{}' has unknown kind '{}'", quest_id, name)
+            }
+            QuestPackError::DuplicateQuest(id) => write!(f, "Quest '{}' is defined twice", id),
+            QuestPackError::DanglingPrerequisite {
+                quest_id,
+                prerequisite,
+            } => {
+                write!(
+                    f,
+                    "Quest '{}' requires '{}', which doesn't exist",
+                    quest_id, prerequisite
+                )
+            }
+            QuestPackError::Cycle(ids) => {
+                write!(f, "Quest prerequisites form a cycle: {}", ids.join(" -> "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for QuestPackError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackFormat {
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PackDef {
+    #[serde(default)]
+    quests: Vec<QuestDef>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuestDef {
+    id: String,
+    title: String,
+    description: String,
+    #[serde(default)]
+    difficulty: Option<String>,
+    #[serde(default)]
+    prerequisites: Vec<String>,
+    kind: Option<String>, // once (the default), repeatable, daily or timed
+    cooldown_secs: Option<u64>,
+    time_limit_secs: Option<u64>,
+    objectives: Vec<ObjectiveDef>,
+    #[serde(default)]
+    rewards: Vec<RewardDef>,
+    #[serde(default)]
+    hints: Vec<HintDef>, // in the order they're revealed
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HintDef {
+    text: String,
+    cost: Option<u32>,
+    #[serde(default)]
+    solution: bool,
+}
+
+// flat on purpose so an unknown `kind` gets our error instead of serde's
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectiveDef {
+    kind: String,
+    pattern: Option<String>,
+    min_params: Option<usize>,
+    count: Option<usize>,
+    expected: Option<String>,
+    name: Option<String>,
+    objectives: Option<Vec<ObjectiveDef>>, // all_of, any_of, in_order
+    objective: Option<Box<ObjectiveDef>>,  // not, at_least, within_run
+    cases: Option<Vec<TestCaseDef>>,       // pass_tests
+    scope: Option<String>,                 // "run" or "cumulative" (the default)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TestCaseDef {
+    #[serde(default)]
+    args: Vec<i64>,
+    expected: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RewardDef {
+    coin_type: String,
+    amount: u32,
+}
+
+impl ObjectiveDef {
+    fn into_objective(self, quest_id: &str) -> Result<QuestObjective, QuestPackError> {
+        let missing = |field: &str| QuestPackError::MissingField {
+            quest_id: quest_id.to_string(),
+            field: field.to_string(),
+        };
+        let scope = self.scope;
+
+        let objective = match self.kind.as_str() {
+            "execute_program" => QuestObjective::ExecuteProgram {
+                pattern: self.pattern.ok_or_else(|| missing("pattern"))?,
+            },
+            "define_function" => QuestObjective::DefineFunction {
+                min_params: self.min_params.unwrap_or(0),
+            },
+            "use_variables" => QuestObjective::UseVariables {
+                count: self.count.ok_or_else(|| missing("count"))?,
+            },
+            "produce_output" => QuestObjective::ProduceOutput {
+                expected: self.expected.ok_or_else(|| missing("expected"))?,
+            },
+            "create_variable" => QuestObjective::CreateVariable { name: self.name },
+            "call_function" => QuestObjective::CallFunction { name: self.name },
+            "perform_arithmetic" => QuestObjective::PerformArithmetic,
+            "run_programs" => QuestObjective::RunPrograms {
+                count: self.count.ok_or_else(|| missing("count"))?,
+            },
+            "pass_tests" => QuestObjective::PassTests {
+                function: self.name.ok_or_else(|| missing("name"))?,
+                cases: self
+                    .cases
+                    .ok_or_else(|| missing("cases"))?
+                    .into_iter()
+                    .map(|c| TestCase {
+                        args: c.args,
+                        expected: c.expected,
+                    })
+                    .collect(),
+            },
+            "all_of" | "any_of" | "in_order" => {
+                let objectives = self
+                    .objectives
+                    .ok_or_else(|| missing("objectives"))?
+                    .into_iter()
+                    .map(|o| o.into_objective(quest_id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match self.kind.as_str() {
+                    "all_of" => QuestObjective::AllOf(objectives),
+                    "any_of" => QuestObjective::AnyOf(objectives),
+                    _ => QuestObjective::InOrder(objectives),
+                }
+            }
+            "not" | "at_least" | "within_run" => {
+                let objective = Box::new(
+                    self.objective
+                        .ok_or_else(|| missing("objective"))?
+                        .into_objective(quest_id)?,
+                );
+                match self.kind.as_str() {
+                    "not" => QuestObjective::Not(objective),
+                    "within_run" => QuestObjective::WithinRun(objective),
+                    _ => QuestObjective::AtLeast {
+                        count: self.count.ok_or_else(|| missing("count"))?,
+                        objective,
+                    },
+                }
+            }
+            _ => {
+                return Err(QuestPackError::UnknownObjective {
+                    quest_id: quest_id.to_string(),
+                    kind: self.kind,
+                });
+            }
+        };
+
+        match scope.as_deref() {
+            None | Some("cumulative") => Ok(objective),
+            Some("run") => Ok(QuestObjective::WithinRun(Box::new(objective))),
+            Some(name) => Err(QuestPackError::UnknownScope {
+                quest_id: quest_id.to_string(),
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+impl QuestDef {
+    fn into_quest(self) -> Result<Quest, QuestPackError> {
+        let difficulty = match self.difficulty.as_deref() {
+            None => QuestDifficulty::Beginner,
+            Some(name) => QuestDifficulty::from_name(name).ok_or_else(|| {
+                QuestPackError::UnknownDifficulty {
+                    quest_id: self.id.clone(),
+                    name: name.to_string(),
+                }
+            })?,
+        };
+
+        let kind = match self.kind.as_deref() {
+            None | Some("once") => QuestKind::Once,
+            Some("repeatable") => QuestKind::Repeatable {
+                cooldown: self.cooldown_secs.unwrap_or(0),
+            },
+            Some("daily") => QuestKind::Daily,
+            Some("timed") => QuestKind::Timed {
+                limit: self.time_limit_secs.ok_or_else(|| QuestPackError::MissingField {
+                    quest_id: self.id.clone(),
+                    field: "time_limit_secs".to_string(),
+                })?,
+            },
+            Some(name) => {
+                return Err(QuestPackError::UnknownQuestKind {
+                    quest_id: self.id.clone(),
+                    name: name.to_string(),
+                });
+            }
+        };
+
+        // a timer on the wrong kind of quest would otherwise be silently ignored
+        let unused = match &kind {
+            QuestKind::Repeatable { .. } => self.time_limit_secs.map(|_| "time_limit_secs"),
+            QuestKind::Timed { .. } => self.cooldown_secs.map(|_| "cooldown_secs"),
+            QuestKind::Once | QuestKind::Daily => self
+                .cooldown_secs
+                .map(|_| "cooldown_secs")
+                .or(self.time_limit_secs.map(|_| "time_limit_secs")),
+        };
+        if let Some(field) = unused {
+            return Err(QuestPackError::UnexpectedField {
+                quest_id: self.id.clone(),
+                field: field.to_string(),
+            });
+        }
+
+        let hints = self
+            .hints
+            .into_iter()
+            .map(|h| Hint {
+                text: h.text,
+                cost: h.cost,
+                solution: h.solution,
+            })
+            .collect();
+
+        let objectives = self
+            .objectives
+            .into_iter()
+            .map(|o| o.into_objective(&self.id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rewards = self
+            .rewards
+            .into_iter()
+            .map(|r| {
+                let coin_type = CoinType::from_name(&r.coin_type).ok_or_else(|| {
+                    QuestPackError::UnknownCoinType {
+                        quest_id: self.id.clone(),
+                        name: r.coin_type.clone(),
+                    }
+                })?;
+                Ok(CoinReward {
+                    coin_type,
+                    amount: r.amount,
+                })
+            })
+            .collect::<Result<Vec<_>, QuestPackError>>()?;
+
+        Ok(Quest::new_with_difficulty(
+            self.id,
+            self.title,
+            self.description,
+            objectives,
+            rewards,
+            difficulty,
+            self.prerequisites,
+        )
+        .with_kind(kind)
+        .with_hints(hints))
+    }
+}
+
+pub fn parse_pack(source: &str, format: PackFormat) -> Result<Vec<Quest>, QuestPackError> {
+    let pack: PackDef = match format {
+        PackFormat::Toml => toml::from_str(source).map_err(|e| QuestPackError::Parse(e.to_string()))?,
+        PackFormat::Json => {
+            serde_json::from_str(source).map_err(|e| QuestPackError::Parse(e.to_string()))?
+        }
+    };
+
+    pack.quests.into_iter().map(QuestDef::into_quest).collect()
+}
+
+pub fn load_file(path: &Path) -> Result<Vec<Quest>, QuestPackError> {
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => PackFormat::Toml,
+        Some("json") => PackFormat::Json,
+        _ => return Err(QuestPackError::UnsupportedFormat(path.display().to_string())),
+    };
+
+    let source = fs::read_to_string(path)
+        .map_err(|e| QuestPackError::Io(format!("{}: {}", path.display(), e)))?;
+    parse_pack(&source, format)
+        .map_err(|e| match e {
+            QuestPackError::Parse(msg) => QuestPackError::Parse(format!("{}: {}", path.display(), msg)),
+            other => other,
+        })
+}
+
+// every .toml/.json in the directory, in file name order
+pub fn load_dir(dir: &Path) -> Result<Vec<Quest>, QuestPackError> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| QuestPackError::Io(format!("{}: {}", dir.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("toml" | "json")))
+        .collect();
+    paths.sort();
+
+    let mut quests = Vec::new();
+    for path in paths {
+        quests.extend(load_file(&path)?);
+    }
+    Ok(quests)
+}
+
+pub fn load_path(path: &Path) -> Result<Vec<Quest>, QuestPackError> {
+    if path.is_dir() {
+        load_dir(path)
+    } else {
+        load_file(path)
+    }
+}
+
+// checks new quests against themselves and the ones already loaded
+pub fn validate(existing: &[&Quest], new: &[Quest]) -> Result<(), QuestPackError> {
+    let mut ids: HashSet<&str> = existing.iter().map(|q| q.id.as_str()).collect();
+    for quest in new {
+        if !ids.insert(quest.id.as_str()) {
+            return Err(QuestPackError::DuplicateQuest(quest.id.clone()));
+        }
+    }
+
+    for quest in new {
+        for prerequisite in &quest.prerequisites {
+            if !ids.contains(prerequisite.as_str()) {
+                return Err(QuestPackError::DanglingPrerequisite {
+                    quest_id: quest.id.clone(),
+                    prerequisite: prerequisite.clone(),
+                });
+            }
+        }
+    }
+
+    let graph: HashMap<&str, &[String]> = existing
+        .iter()
+        .copied()
+        .chain(new.iter())
+        .map(|q| (q.id.as_str(), q.prerequisites.as_slice()))
+        .collect();
+
+    match find_cycle(&graph) {
+        Some(cycle) => Err(QuestPackError::Cycle(cycle)),
+        None => Ok(()),
+    }
+}
+
+// dfs over prerequisite edges, returns the ids on the first cycle found
+pub fn find_cycle(graph: &HashMap<&str, &[String]>) -> Option<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn visit<'a>(
+        id: &'a str,
+        graph: &HashMap<&'a str, &'a [String]>,
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        match marks.get(id) {
+            Some(Mark::Done) => return None,
+            Some(Mark::Visiting) => {
+                let start = path.iter().position(|p| *p == id).unwrap_or(0);
+                let mut cycle: Vec<String> = path[start..].iter().map(|s| s.to_string()).collect();
+                cycle.push(id.to_string());
+                return Some(cycle);
+            }
+            None => {}
+        }
+
+        marks.insert(id, Mark::Visiting);
+        path.push(id);
+        for prerequisite in graph.get(id).copied().unwrap_or(&[]) {
+            if let Some(cycle) = visit(prerequisite, graph, marks, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        marks.insert(id, Mark::Done);
+        None
+    }
+
+    let mut ids: Vec<&str> = graph.keys().copied().collect();
+    ids.sort();
+
+    let mut marks = HashMap::new();
+    for id in ids {
+        if let Some(cycle) = visit(id, graph, &mut marks, &mut Vec::new()) {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObjectiveScope;
+
+    #[test]
+    fn test_default_pack_is_valid() {
+        let quests = parse_pack(DEFAULT_PACK, PackFormat::Toml).unwrap();
+        assert_eq!(quests.len(), 14);
+        validate(&[], &quests).unwrap();
+
+        let caller = quests.iter().find(|q| q.id == "function_caller").unwrap();
+        assert_eq!(caller.difficulty, QuestDifficulty::Advanced);
+        assert_eq!(caller.rewards.len(), 2);
+        assert!(!caller.is_unlocked());
+    }
+
+    #[test]
+    fn test_json_pack() {
+        let source = r#"{"quests": [{
+            "id": "say_hi",
+            "title": "Say Hi",
+            "description": "Print hi",
+            "difficulty": "intermediate",
+            "objectives": [{"kind": "produce_output", "expected": "hi"}],
+            "rewards": [{"coin_type": "function", "amount": 1}]
+        }]}"#;
+
+        let quests = parse_pack(source, PackFormat::Json).unwrap();
+        assert_eq!(quests[0].id, "say_hi");
+        assert_eq!(
+            quests[0].objectives,
+            vec![QuestObjective::ProduceOutput {
+                expected: "hi".to_string()
+            }]
+        );
+        assert_eq!(quests[0].rewards[0].coin_type, CoinType::Function);
+    }
+
+    #[test]
+    fn test_unknown_objective_kind() {
+        let source = r#"
+            [[quests]]
+            id = "bad"
+            title = "Bad"
+            description = "Bad"
+            objectives = [{ kind = "fly_to_moon" }]
+        "#;
+
+        assert_eq!(
+            parse_pack(source, PackFormat::Toml).unwrap_err(),
+            QuestPackError::UnknownObjective {
+                quest_id: "bad".to_string(),
+                kind: "fly_to_moon".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_objective_field() {
+        let source = r#"
+            [[quests]]
+            id = "bad"
+            title = "Bad"
+            description = "Bad"
+            objectives = [{ kind = "use_variables" }]
+        "#;
+
+        assert!(matches!(
+            parse_pack(source, PackFormat::Toml),
+            Err(QuestPackError::MissingField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_timer_on_wrong_kind() {
+        let source = r#"
+            [[quests]]
+            id = "once"
+            title = "Once"
+            description = "Once"
+            cooldown_secs = 60
+            objectives = [{ kind = "create_variable" }]
+        "#;
+
+        assert_eq!(
+            parse_pack(source, PackFormat::Toml),
+            Err(QuestPackError::UnexpectedField {
+                quest_id: "once".to_string(),
+                field: "cooldown_secs".to_string(),
+            })
+        );
+
+        let repeatable = source.replace("cooldown_secs = 60", "kind = \"repeatable\"\ntime_limit_secs = 60");
+        assert!(matches!(
+            parse_pack(&repeatable, PackFormat::Toml),
+            Err(QuestPackError::UnexpectedField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_nested_objectives() {
+        let source = r#"
+            [[quests]]
+            id = "fib_fan"
+            title = "Fib Fan"
+            description = "Call fib five times in one run without new variables"
+
+            [[quests.objectives]]
+            kind = "within_run"
+            objective = { kind = "all_of", objectives = [
+                { kind = "at_least", count = 5, objective = { kind = "call_function", name = "fib" } },
+                { kind = "not", objective = { kind = "create_variable" } },
+            ] }
+        "#;
+
+        let quests = parse_pack(source, PackFormat::Toml).unwrap();
+        assert_eq!(
+            quests[0].objectives,
+            vec![QuestObjective::WithinRun(Box::new(QuestObjective::AllOf(vec![
+                QuestObjective::AtLeast {
+                    count: 5,
+                    objective: Box::new(QuestObjective::CallFunction {
+                        name: Some("fib".to_string())
+                    }),
+                },
+                QuestObjective::Not(Box::new(QuestObjective::CreateVariable { name: None })),
+            ])))]
+        );
+    }
+
+    #[test]
+    fn test_objective_scope() {
+        let source = r#"
+            [[quests]]
+            id = "scoped"
+            title = "Scoped"
+            description = "Scoped"
+            objectives = [{ kind = "perform_arithmetic", scope = "run" }, { kind = "create_variable", scope = "cumulative" }]
+        "#;
+
+        let quests = parse_pack(source, PackFormat::Toml).unwrap();
+        assert_eq!(quests[0].objectives[0].scope(), ObjectiveScope::Run);
+        assert_eq!(quests[0].objectives[1].scope(), ObjectiveScope::Cumulative);
+
+        let bad = source.replace("\"cumulative\"", "\"forever\"");
+        assert!(matches!(
+            parse_pack(&bad, PackFormat::Toml),
+            Err(QuestPackError::UnknownScope { .. })
+        ));
+    }
+
+    fn quest(id: &str, prerequisites: &[&str]) -> Quest {
+        Quest::new_with_difficulty(
+            id.to_string(),
+            id.to_string(),
+            String::new(),
+            vec![],
+            vec![],
+            QuestDifficulty::Beginner,
+            prerequisites.iter().map(|p| p.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_dangling_prerequisite() {
+        let err = validate(&[], &[quest("a", &["missing"])]).unwrap_err();
+        assert_eq!(
+            err,
+            QuestPackError::DanglingPrerequisite {
+                quest_id: "a".to_string(),
+                prerequisite: "missing".to_string(),
+            }
+        );
+
+        // prerequisites can live in an already loaded pack
+        let base = quest("base", &[]);
+        validate(&[&base], &[quest("a", &["base"])]).unwrap();
+    }
+
+    #[test]
+    fn test_cycle_and_duplicate_detection() {
+        let err = validate(&[], &[quest("a", &["c"]), quest("b", &["a"]), quest("c", &["b"])])
+            .unwrap_err();
+        let QuestPackError::Cycle(cycle) = err else {
+            panic!("expected a cycle, got {:?}", err);
+        };
+        assert_eq!(cycle.first(), cycle.last());
+        assert_eq!(cycle.len(), 4);
+
+        assert_eq!(
+            validate(&[], &[quest("a", &[]), quest("a", &[])]).unwrap_err(),
+            QuestPackError::DuplicateQuest("a".to_string())
+        );
+    }
+}
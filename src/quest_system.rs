@@ -1,5 +1,14 @@
-use crate::{CoinReward, CoinType};
-use std::collections::HashMap;
+use crate::{
+    challenge::TestCase,
+    clock::{Clock, SystemClock, SECONDS_PER_DAY},
+    events::ExecutionEvent,
+    quest_pack::{self, PackFormat, QuestPackError},
+    CoinCost, CoinError, CoinManager, CoinReward, CoinType,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum QuestDifficulty {
@@ -9,6 +18,15 @@ pub enum QuestDifficulty {
 }
 
 impl QuestDifficulty {
+    pub fn from_name(name: &str) -> Option<QuestDifficulty> {
+        match name.to_lowercase().as_str() {
+            "beginner" => Some(QuestDifficulty::Beginner),
+            "intermediate" => Some(QuestDifficulty::Intermediate),
+            "advanced" => Some(QuestDifficulty::Advanced),
+            _ => None,
+        }
+    }
+
     pub fn description(&self) -> &str {
         match self {
             QuestDifficulty::Beginner => "Beginner",
@@ -18,6 +36,61 @@ impl QuestDifficulty {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum QuestError {
+    UnknownQuest(String),
+    NotAvailable(String), // locked, done, cooling down or not today's daily
+    NotAccepted(String),
+    NoMoreHints(String),
+    CoinError(CoinError),
+}
+
+impl From<CoinError> for QuestError {
+    fn from(value: CoinError) -> Self {
+        QuestError::CoinError(value)
+    }
+}
+
+impl std::fmt::Display for QuestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuestError::UnknownQuest(id) => write!(f, "There is no quest '{}'", id),
+            QuestError::NotAvailable(id) => write!(f, "Quest '{}' isn't available right now", id),
+            QuestError::NotAccepted(id) => write!(f, "Quest '{}' hasn't been accepted", id),
+            QuestError::NoMoreHints(id) => write!(f, "Quest '{}' has no more hints", id),
+            QuestError::CoinError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for QuestError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuestKind {
+    Once,
+    Repeatable { cooldown: u64 }, // seconds before it can be done again
+    Daily,                        // part of the pool that rotates each day
+    Timed { limit: u64 },         // seconds from starting to finish it
+}
+
+impl QuestKind {
+    pub fn description(&self) -> &str {
+        match self {
+            QuestKind::Once => "Once",
+            QuestKind::Repeatable { .. } => "Repeatable",
+            QuestKind::Daily => "Daily",
+            QuestKind::Timed { .. } => "Timed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub text: String,
+    pub cost: Option<u32>, // overrides the manager's default hint price
+    pub solution: bool,    // a worked answer, seeing it halves the rewards
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Quest {
     pub id: String,
@@ -29,6 +102,14 @@ pub struct Quest {
     pub difficulty: QuestDifficulty,
     pub prerequisites: Vec<String>, 
     pub unlocked: bool,
+    pub accepted: bool, // only accepted quests are checked for completion
+    pub kind: QuestKind,
+    pub times_completed: u32,
+    pub last_completed: Option<u64>,
+    pub started_at: Option<u64>, // when a timed quest's clock started, on accepting
+    pub progress_from: HistoryMark, // where in the history progress counts from
+    pub hints: Vec<Hint>,
+    pub hints_revealed: usize,
 }
 
 impl Quest {
@@ -49,6 +130,14 @@ impl Quest {
             difficulty: QuestDifficulty::Beginner,
             prerequisites: Vec::new(),
             unlocked: true, 
+            accepted: false,
+            kind: QuestKind::Once,
+            times_completed: 0,
+            last_completed: None,
+            started_at: None,
+            progress_from: HistoryMark::default(),
+            hints: Vec::new(),
+            hints_revealed: 0,
         }
     }
 
@@ -72,9 +161,47 @@ impl Quest {
             difficulty,
             prerequisites,
             unlocked,
+            accepted: false,
+            kind: QuestKind::Once,
+            times_completed: 0,
+            last_completed: None,
+            started_at: None,
+            progress_from: HistoryMark::default(),
+            hints: Vec::new(),
+            hints_revealed: 0,
         }
     }
 
+    pub fn with_kind(mut self, kind: QuestKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_hints(mut self, hints: Vec<Hint>) -> Self {
+        self.hints = hints;
+        self
+    }
+
+    pub fn revealed_hints(&self) -> &[Hint] {
+        &self.hints[..self.hints_revealed]
+    }
+
+    pub fn solution_revealed(&self) -> bool {
+        self.revealed_hints().iter().any(|h| h.solution)
+    }
+
+    // what completing the quest pays out, halved (rounding down) after the solution was seen
+    pub fn earned_rewards(&self) -> Vec<CoinReward> {
+        let divisor = if self.solution_revealed() { 2 } else { 1 };
+        self.rewards
+            .iter()
+            .map(|r| CoinReward {
+                coin_type: r.coin_type,
+                amount: r.amount / divisor,
+            })
+            .collect()
+    }
+
     pub fn is_completed(&self) -> bool {
         self.completed
     }
@@ -83,6 +210,10 @@ impl Quest {
         self.unlocked
     }
 
+    pub fn is_accepted(&self) -> bool {
+        self.accepted
+    }
+
     pub fn mark_completed(&mut self) {
         self.completed = true;
     }
@@ -91,11 +222,25 @@ impl Quest {
         self.unlocked = true;
     }
 
+    // timed quests only count what happens after their clock starts
+    fn start_clock(&mut self, now: u64, mark: HistoryMark) {
+        if let QuestKind::Timed { .. } = self.kind {
+            self.started_at = Some(now);
+            self.progress_from = mark;
+        }
+    }
+
     pub fn get_difficulty_description(&self) -> String {
         format!("[{}] {}", self.difficulty.description(), self.title)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectiveScope {
+    Run,        // judged on the program that was just run
+    Cumulative, // judged on everything still in the history
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum QuestObjective {
     ExecuteProgram { pattern: String },
@@ -105,9 +250,45 @@ pub enum QuestObjective {
     CreateVariable { name: Option<String> },
     CallFunction { name: Option<String> },
     PerformArithmetic,
+    RunPrograms { count: usize },
+    PassTests { function: String, cases: Vec<TestCase> }, // cases stay hidden from the player
+    AllOf(Vec<QuestObjective>),
+    AnyOf(Vec<QuestObjective>),
+    Not(Box<QuestObjective>),
+    AtLeast { count: usize, objective: Box<QuestObjective> },
+    InOrder(Vec<QuestObjective>), // each step must happen after the previous one
+    WithinRun(Box<QuestObjective>), // must hold inside one program run, not across runs
 }
 
 impl QuestObjective {
+    // whether a single event satisfies this objective, exact names only
+    pub fn matches_event(&self, event: &ExecutionEvent) -> bool {
+        match (self, event) {
+            (QuestObjective::ExecuteProgram { pattern }, ExecutionEvent::ProgramStarted { source }) => {
+                source.contains(pattern.as_str())
+            }
+            (QuestObjective::DefineFunction { min_params }, ExecutionEvent::FunctionDefined { params, .. }) => {
+                params.len() >= *min_params
+            }
+            (QuestObjective::ProduceOutput { expected }, ExecutionEvent::Printed { text }) => {
+                text == expected
+            }
+            (QuestObjective::CreateVariable { name }, ExecutionEvent::VariableDefined { name: defined, .. }) => {
+                name.as_ref().is_none_or(|n| n == defined)
+            }
+            (QuestObjective::CallFunction { name }, ExecutionEvent::FunctionCalled { name: called, .. }) => {
+                name.as_ref().is_none_or(|n| n == called)
+            }
+            (QuestObjective::PerformArithmetic, ExecutionEvent::Arithmetic { .. }) => true,
+            (QuestObjective::RunPrograms { .. }, ExecutionEvent::ProgramStarted { .. }) => true,
+            (
+                QuestObjective::PassTests { function, cases },
+                ExecutionEvent::ChallengeRun { function: tested, cases: ran, passed },
+            ) => function == tested && cases == ran && *passed == cases.len(),
+            _ => false,
+        }
+    }
+
     pub fn description(&self) -> String {
         match self {
             QuestObjective::ExecuteProgram { pattern } => {
@@ -137,16 +318,397 @@ impl QuestObjective {
                 }
             }
             QuestObjective::PerformArithmetic => "Perform arithmetic operations".to_string(),
+            QuestObjective::RunPrograms { count } => format!("Run {} programs", count),
+            QuestObjective::PassTests { function, cases } => {
+                format!("Define '{}' so it passes {} hidden test cases", function, cases.len())
+            }
+            QuestObjective::AllOf(objectives) => format!("All of: {}", join_descriptions(objectives, "; ")),
+            QuestObjective::AnyOf(objectives) => format!("Any of: {}", join_descriptions(objectives, "; ")),
+            QuestObjective::Not(objective) => format!("Never: {}", objective.description()),
+            QuestObjective::AtLeast { count, objective } => {
+                format!("At least {} times: {}", count, objective.description())
+            }
+            QuestObjective::InOrder(objectives) => {
+                format!("In order: {}", join_descriptions(objectives, ", then "))
+            }
+            QuestObjective::WithinRun(objective) => {
+                format!("In a single run: {}", objective.description())
+            }
+        }
+    }
+
+    // short heading for tree views where the children are listed underneath
+    fn label(&self) -> String {
+        match self {
+            QuestObjective::AllOf(_) => "All of the following".to_string(),
+            QuestObjective::AnyOf(_) => "Any of the following".to_string(),
+            QuestObjective::InOrder(_) => "In this order".to_string(),
+            QuestObjective::WithinRun(_) => "In a single program run".to_string(),
+            _ => self.description(),
+        }
+    }
+
+    pub fn is_met(&self, events: &[ExecutionEvent]) -> bool {
+        match self {
+            QuestObjective::UseVariables { count } => distinct_variables(events) >= *count,
+            QuestObjective::RunPrograms { count } => self.times_met(events) >= *count,
+            QuestObjective::AllOf(objectives) => objectives.iter().all(|o| o.is_met(events)),
+            QuestObjective::AnyOf(objectives) => objectives.iter().any(|o| o.is_met(events)),
+            QuestObjective::Not(objective) => !objective.is_met(events),
+            QuestObjective::AtLeast { count, objective } => objective.times_met(events) >= *count,
+            QuestObjective::InOrder(_) => Matcher::run(self, events).is_met(),
+            QuestObjective::WithinRun(objective) => split_runs(events).any(|run| objective.is_met(run)),
+            _ => events.iter().any(|event| self.matches_event(event)),
+        }
+    }
+
+    // how many times this happened, which is what AtLeast counts
+    pub fn times_met(&self, events: &[ExecutionEvent]) -> usize {
+        match self {
+            QuestObjective::ExecuteProgram { .. }
+            | QuestObjective::DefineFunction { .. }
+            | QuestObjective::ProduceOutput { .. }
+            | QuestObjective::CreateVariable { .. }
+            | QuestObjective::CallFunction { .. }
+            | QuestObjective::PerformArithmetic
+            | QuestObjective::RunPrograms { .. }
+            | QuestObjective::PassTests { .. } => {
+                events.iter().filter(|event| self.matches_event(event)).count()
+            }
+            QuestObjective::InOrder(steps) if !steps.is_empty() => Matcher::run(self, events).times(),
+            QuestObjective::WithinRun(objective) => {
+                split_runs(events).filter(|run| objective.is_met(run)).count()
+            }
+            _ => usize::from(self.is_met(events)),
+        }
+    }
+
+    pub fn progress(&self, events: &[ExecutionEvent]) -> ObjectiveProgress {
+        let label = self.label();
+        match self {
+            QuestObjective::UseVariables { count } => {
+                ObjectiveProgress::leaf(label, distinct_variables(events).min(*count), *count)
+            }
+            QuestObjective::RunPrograms { count } => {
+                ObjectiveProgress::leaf(label, self.times_met(events).min(*count), *count)
+            }
+            QuestObjective::PassTests { function, cases } => {
+                // best attempt so far, so a near miss still shows up
+                let best = events
+                    .iter()
+                    .filter_map(|event| match event {
+                        ExecutionEvent::ChallengeRun { function: tested, cases: ran, passed }
+                            if tested == function && ran == cases => Some(*passed),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                ObjectiveProgress::leaf(label, best, cases.len())
+            }
+            QuestObjective::AllOf(objectives) => {
+                let children: Vec<_> = objectives.iter().map(|o| o.progress(events)).collect();
+                let done = children.iter().filter(|c| c.complete).count();
+                ObjectiveProgress::branch(label, done, children.len(), children)
+            }
+            QuestObjective::AnyOf(objectives) => {
+                let children: Vec<_> = objectives.iter().map(|o| o.progress(events)).collect();
+                let done = usize::from(children.iter().any(|c| c.complete));
+                ObjectiveProgress::branch(label, done, 1, children)
+            }
+            QuestObjective::AtLeast { count, objective } => {
+                ObjectiveProgress::leaf(label, objective.times_met(events).min(*count), *count)
+            }
+            QuestObjective::InOrder(steps) => {
+                let ends = sequence_ends(steps, events);
+                let mut start = 0;
+                let children = steps
+                    .iter()
+                    .enumerate()
+                    .map(|(i, step)| match ends.get(i) {
+                        Some(&end) => {
+                            let child = step.progress(&events[start..end]);
+                            start = end;
+                            child
+                        }
+                        // later steps only count once the earlier ones are done
+                        None if i == ends.len() => step.progress(&events[start..]).pending(),
+                        None => step.progress(&[]).pending(),
+                    })
+                    .collect();
+                ObjectiveProgress::branch(label, ends.len(), steps.len(), children)
+            }
+            QuestObjective::WithinRun(objective) => {
+                let best = split_runs(events)
+                    .map(|run| objective.progress(run))
+                    .fold(None, |best: Option<ObjectiveProgress>, run| match best {
+                        Some(b) if b.fraction() > run.fraction() => Some(b),
+                        _ => Some(run),
+                    })
+                    .unwrap_or_else(|| objective.progress(&[]));
+                let (current, target) = (best.current, best.target);
+                let mut node = ObjectiveProgress::branch(label, current, target, vec![best]);
+                node.complete = self.is_met(events);
+                node
+            }
+            _ => ObjectiveProgress::leaf(label, usize::from(self.is_met(events)), 1),
+        }
+    }
+
+    // a top level WithinRun is how an objective asks to be judged per run
+    pub fn scope(&self) -> ObjectiveScope {
+        match self {
+            QuestObjective::WithinRun(_) => ObjectiveScope::Run,
+            _ => ObjectiveScope::Cumulative,
+        }
+    }
+
+    // every PassTests inside this objective, however deeply nested
+    pub fn challenges(&self) -> Vec<(&str, &[TestCase])> {
+        match self {
+            QuestObjective::PassTests { function, cases } => vec![(function.as_str(), cases.as_slice())],
+            QuestObjective::AllOf(objectives)
+            | QuestObjective::AnyOf(objectives)
+            | QuestObjective::InOrder(objectives) => {
+                objectives.iter().flat_map(|o| o.challenges()).collect()
+            }
+            QuestObjective::Not(objective)
+            | QuestObjective::AtLeast { objective, .. }
+            | QuestObjective::WithinRun(objective) => objective.challenges(),
+            _ => Vec::new(),
         }
     }
 }
 
+fn join_descriptions(objectives: &[QuestObjective], separator: &str) -> String {
+    objectives
+        .iter()
+        .map(|o| o.description())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn distinct_variables(events: &[ExecutionEvent]) -> usize {
+    let mut names: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            ExecutionEvent::VariableDefined { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names.len()
+}
+
+// each run starts at its ProgramStarted marker; anything before the first
+// marker counts as a run of its own
+fn split_runs(events: &[ExecutionEvent]) -> impl Iterator<Item = &[ExecutionEvent]> {
+    let mut starts: Vec<usize> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| matches!(event, ExecutionEvent::ProgramStarted { .. }))
+        .map(|(i, _)| i)
+        .collect();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    let ends: Vec<usize> = starts.iter().skip(1).copied().chain([events.len()]).collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .filter(|(start, end)| start < end)
+        .map(move |(start, end)| &events[start..end])
+}
+
+// end index of the shortest prefix satisfying each step in turn, stops at the
+// first step that can't be satisfied
+fn sequence_ends(steps: &[QuestObjective], events: &[ExecutionEvent]) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let Some(first) = steps.first() else {
+        return ends;
+    };
+    let mut matcher = Matcher::new(first);
+    for (i, event) in events.iter().enumerate() {
+        matcher.push(event);
+        if matcher.is_met() {
+            ends.push(i + 1);
+            match steps.get(ends.len()) {
+                Some(step) => matcher = Matcher::new(step),
+                None => break,
+            }
+        }
+    }
+    ends
+}
+
+// follows an objective event by event so a sequence is matched in one pass
+// instead of rechecking every prefix. Agrees with is_met and times_met on the
+// events pushed so far
+struct Matcher<'a> {
+    objective: &'a QuestObjective,
+    state: MatchState<'a>,
+}
+
+enum MatchState<'a> {
+    Matches(usize),
+    Variables(HashSet<&'a str>),
+    Children(Vec<Matcher<'a>>),
+    Sequence { step: usize, current: Box<Matcher<'a>>, completed: usize },
+    Runs { current: Option<Box<Matcher<'a>>>, met: usize },
+}
+
+impl<'a> Matcher<'a> {
+    fn new(objective: &'a QuestObjective) -> Self {
+        let state = match objective {
+            QuestObjective::UseVariables { .. } => MatchState::Variables(HashSet::new()),
+            QuestObjective::AllOf(objectives) | QuestObjective::AnyOf(objectives) => {
+                MatchState::Children(objectives.iter().map(Matcher::new).collect())
+            }
+            QuestObjective::Not(objective) | QuestObjective::AtLeast { objective, .. } => {
+                MatchState::Children(vec![Matcher::new(objective)])
+            }
+            QuestObjective::InOrder(steps) if !steps.is_empty() => MatchState::Sequence {
+                step: 0,
+                current: Box::new(Matcher::new(&steps[0])),
+                completed: 0,
+            },
+            QuestObjective::WithinRun(_) => MatchState::Runs { current: None, met: 0 },
+            _ => MatchState::Matches(0),
+        };
+        Self { objective, state }
+    }
+
+    fn run(objective: &'a QuestObjective, events: &'a [ExecutionEvent]) -> Self {
+        let mut matcher = Self::new(objective);
+        for event in events {
+            matcher.push(event);
+        }
+        matcher
+    }
+
+    fn push(&mut self, event: &'a ExecutionEvent) {
+        match &mut self.state {
+            MatchState::Matches(count) => *count += usize::from(self.objective.matches_event(event)),
+            MatchState::Variables(names) => {
+                if let ExecutionEvent::VariableDefined { name, .. } = event {
+                    names.insert(name.as_str());
+                }
+            }
+            MatchState::Children(children) => children.iter_mut().for_each(|c| c.push(event)),
+            MatchState::Sequence { step, current, completed } => {
+                current.push(event);
+                if current.is_met() {
+                    let QuestObjective::InOrder(steps) = self.objective else {
+                        unreachable!("only InOrder is matched as a sequence")
+                    };
+                    *step += 1;
+                    if *step == steps.len() {
+                        *completed += 1;
+                        *step = 0;
+                    }
+                    **current = Matcher::new(&steps[*step]);
+                }
+            }
+            MatchState::Runs { current, met } => {
+                let QuestObjective::WithinRun(objective) = self.objective else {
+                    unreachable!("only WithinRun is matched per run")
+                };
+                // a new marker closes the run before it
+                if matches!(event, ExecutionEvent::ProgramStarted { .. })
+                    && let Some(run) = current.take()
+                {
+                    *met += usize::from(run.is_met());
+                }
+                current.get_or_insert_with(|| Box::new(Matcher::new(objective))).push(event);
+            }
+        }
+    }
+
+    fn is_met(&self) -> bool {
+        match (self.objective, &self.state) {
+            (QuestObjective::UseVariables { count }, MatchState::Variables(names)) => names.len() >= *count,
+            (QuestObjective::RunPrograms { count }, MatchState::Matches(matches)) => matches >= count,
+            (QuestObjective::AllOf(_), MatchState::Children(children)) => children.iter().all(|c| c.is_met()),
+            (QuestObjective::AnyOf(_), MatchState::Children(children)) => children.iter().any(|c| c.is_met()),
+            (QuestObjective::Not(_), MatchState::Children(children)) => !children[0].is_met(),
+            (QuestObjective::AtLeast { count, .. }, MatchState::Children(children)) => children[0].times() >= *count,
+            (QuestObjective::InOrder(_), MatchState::Matches(_)) => true, // no steps
+            (_, MatchState::Sequence { .. } | MatchState::Runs { .. }) => self.times() > 0,
+            (_, MatchState::Matches(matches)) => *matches > 0,
+            _ => false,
+        }
+    }
+
+    fn times(&self) -> usize {
+        match &self.state {
+            MatchState::Matches(matches) if !matches!(self.objective, QuestObjective::InOrder(_)) => *matches,
+            MatchState::Sequence { completed, .. } => *completed,
+            MatchState::Runs { current, met } => met + current.as_ref().map_or(0, |run| usize::from(run.is_met())),
+            _ => usize::from(self.is_met()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectiveProgress {
+    pub description: String,
+    pub current: usize,
+    pub target: usize,
+    pub complete: bool,
+    pub children: Vec<ObjectiveProgress>,
+}
+
+impl ObjectiveProgress {
+    fn leaf(description: String, current: usize, target: usize) -> Self {
+        Self::branch(description, current, target, Vec::new())
+    }
+
+    fn branch(description: String, current: usize, target: usize, children: Vec<ObjectiveProgress>) -> Self {
+        Self {
+            description,
+            current,
+            target,
+            complete: current >= target,
+            children,
+        }
+    }
+
+    fn pending(mut self) -> Self {
+        self.complete = false;
+        self
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.complete || self.target == 0 {
+            return 1.0;
+        }
+        self.current.min(self.target) as f32 / self.target as f32
+    }
+}
+
+// events kept by a long lived context before the oldest are dropped
+pub const DEFAULT_RETENTION: usize = 10_000;
+
+// a position in one particular history. A mark only means something to the
+// context it was taken from, any other context counts all of its events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryMark {
+    history: u64, // 0 is never a context's id
+    sequence: usize,
+}
+
+static NEXT_HISTORY: AtomicU64 = AtomicU64::new(1);
+
 #[derive(Debug, Clone)]
 pub struct ExecutionContext {
     pub variables: HashMap<String, i64>,
     pub functions: HashMap<String, FunctionDef>,
     pub output: Vec<String>,
-    pub executed_expressions: Vec<String>, 
+    pub events: Vec<ExecutionEvent>,
+    retention: Option<usize>, // applies to events and output alike
+    programs_run: usize,
+    recorded: usize, // events ever recorded, the sequence number of the next one
+    id: u64,
 }
 
 impl ExecutionContext {
@@ -155,31 +717,104 @@ impl ExecutionContext {
             variables: HashMap::new(),
             functions: HashMap::new(),
             output: Vec::new(),
-            executed_expressions: Vec::new(),
+            events: Vec::new(),
+            retention: None,
+            programs_run: 0,
+            recorded: 0,
+            id: NEXT_HISTORY.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn with_retention(limit: usize) -> Self {
+        Self {
+            retention: Some(limit),
+            ..Self::new()
+        }
+    }
+
+    // forget everything, including the programs run counter; the sequence
+    // keeps counting so quests holding a position in it stay valid
+    pub fn reset(&mut self) {
+        *self = Self {
+            retention: self.retention,
+            recorded: self.recorded,
+            id: self.id,
+            ..Self::new()
+        };
+    }
+
+    pub fn sequence(&self) -> usize {
+        self.recorded
+    }
+
+    // marks the current end of this history
+    pub fn mark(&self) -> HistoryMark {
+        HistoryMark {
+            history: self.id,
+            sequence: self.recorded,
+        }
+    }
+
+    // whatever is still retained since `mark`, or everything if the mark
+    // came from another context
+    pub fn events_since(&self, mark: HistoryMark) -> &[ExecutionEvent] {
+        if mark.history != self.id {
+            return &self.events;
         }
+        let first_retained = self.recorded - self.events.len();
+        &self.events[mark.sequence.saturating_sub(first_retained).min(self.events.len())..]
     }
 
     pub fn add_variable(&mut self, name: String, value: i64) {
-        self.variables.insert(name, value);
+        self.record_event(ExecutionEvent::VariableDefined { name, value });
     }
 
     pub fn add_function(&mut self, name: String, params: Vec<String>, body: String) {
-        self.functions.insert(
-            name.clone(),
-            FunctionDef {
-                name,
-                params,
-                body,
-            },
-        );
+        self.record_event(ExecutionEvent::FunctionDefined { name, params, body });
     }
 
     pub fn add_output(&mut self, output: String) {
-        self.output.push(output);
+        self.record_event(ExecutionEvent::Printed { text: output });
+    }
+
+    // the lookup maps are kept in sync so objectives don't rescan history
+    pub fn record_event(&mut self, event: ExecutionEvent) {
+        match &event {
+            ExecutionEvent::VariableDefined { name, value } => {
+                self.variables.insert(name.clone(), *value);
+            }
+            ExecutionEvent::FunctionDefined { name, params, body } => {
+                self.functions.insert(
+                    name.clone(),
+                    FunctionDef {
+                        name: name.clone(),
+                        params: params.clone(),
+                        body: body.clone(),
+                    },
+                );
+            }
+            ExecutionEvent::Printed { text } => self.output.push(text.clone()),
+            ExecutionEvent::ProgramStarted { .. } => self.programs_run += 1,
+            _ => {}
+        }
+        self.events.push(event);
+        self.recorded += 1;
+
+        if let Some(limit) = self.retention {
+            trim_front(&mut self.events, limit);
+            trim_front(&mut self.output, limit);
+        }
     }
 
-    pub fn record_expression(&mut self, expr_type: String) {
-        self.executed_expressions.push(expr_type);
+    // counted separately so it survives history being trimmed
+    pub fn programs_run(&self) -> usize {
+        self.programs_run
+    }
+}
+
+fn trim_front<T>(items: &mut Vec<T>, limit: usize) {
+    if items.len() > limit {
+        items.drain(..items.len() - limit);
     }
 }
 
@@ -196,18 +831,85 @@ pub struct FunctionDef {
     pub body: String,
 }
 
-#[derive(Debug)]
 pub struct QuestManager {
     active_quests: Vec<Quest>,
     completed_quests: Vec<Quest>,
+    clock: Box<dyn Clock>,
+    daily_count: usize,      // daily quests offered each day
+    last_mark: HistoryMark,  // history position at the previous check
+    daily_day: Option<u64>,  // day the daily quests last rolled over
+    tracked: Option<String>,
+    hint_cost: CoinCost,
+}
+
+impl std::fmt::Debug for QuestManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuestManager")
+            .field("active_quests", &self.active_quests)
+            .field("completed_quests", &self.completed_quests)
+            .field("daily_count", &self.daily_count)
+            .field("tracked", &self.tracked)
+            .finish_non_exhaustive()
+    }
 }
 
 impl QuestManager {
     pub fn new() -> Self {
+        Self::with_clock(Box::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Box<dyn Clock>) -> Self {
         Self {
             active_quests: Vec::new(),
             completed_quests: Vec::new(),
+            clock,
+            daily_count: 1,
+            last_mark: HistoryMark::default(),
+            daily_day: None,
+            tracked: None,
+            hint_cost: CoinCost {
+                coin_type: CoinType::Variable,
+                amt: 1,
+            },
+        }
+    }
+
+    pub fn set_hint_cost(&mut self, cost: CoinCost) {
+        self.hint_cost = cost;
+    }
+
+    // price of the next hint for a quest, None once they've all been shown
+    pub fn next_hint_cost(&self, quest_id: &str) -> Option<CoinCost> {
+        let quest = self.get_quest_by_id(quest_id)?;
+        let hint = quest.hints.get(quest.hints_revealed)?;
+        Some(CoinCost {
+            coin_type: self.hint_cost.coin_type,
+            amt: hint.cost.unwrap_or(self.hint_cost.amt),
+        })
+    }
+
+    // pays for and reveals the next hint, returning it with its 1-based number
+    pub fn reveal_hint(&mut self, quest_id: &str, coin_manager: &mut CoinManager) -> Result<(usize, Hint), QuestError> {
+        let Some(cost) = self.next_hint_cost(quest_id) else {
+            return Err(match self.get_quest_by_id(quest_id) {
+                Some(_) => QuestError::NoMoreHints(quest_id.to_string()),
+                None => QuestError::UnknownQuest(quest_id.to_string()),
+            });
+        };
+        let quest = self.active_quest_mut(quest_id)?;
+        // hints are for quests you're working on, like tracking
+        if !quest.is_accepted() {
+            return Err(QuestError::NotAccepted(quest_id.to_string()));
         }
+        coin_manager.spend_coins(cost.coin_type, cost.amt)?;
+
+        let hint = quest.hints[quest.hints_revealed].clone();
+        quest.hints_revealed += 1;
+        Ok((quest.hints_revealed, hint))
+    }
+
+    pub fn set_daily_count(&mut self, count: usize) {
+        self.daily_count = count;
     }
 
     pub fn add_quest(&mut self, quest: Quest) {
@@ -216,14 +918,171 @@ impl QuestManager {
         }
     }
 
+    // progress only counts from here on, and a timed quest's clock starts.
+    // "here" is the end of the history last checked; a different context
+    // passed in later counts from its own start
+    pub fn accept_quest(&mut self, quest_id: &str) -> Result<(), QuestError> {
+        let now = self.clock.now();
+        let dailies = self.todays_dailies();
+        let Some(quest) = self.active_quests.iter().find(|q| q.id == quest_id) else {
+            return Err(self.missing(quest_id));
+        };
+        if quest.is_accepted() {
+            return Ok(());
+        }
+        if !self.is_available(quest, now, &dailies) {
+            return Err(QuestError::NotAvailable(quest_id.to_string()));
+        }
+
+        let mark = self.last_mark;
+        let quest = self.active_quest_mut(quest_id)?;
+        quest.accepted = true;
+        quest.progress_from = mark;
+        quest.start_clock(now, mark);
+        Ok(())
+    }
+
+    pub fn abandon_quest(&mut self, quest_id: &str) -> Result<(), QuestError> {
+        let quest = self.active_quest_mut(quest_id)?;
+        if !quest.is_accepted() {
+            return Err(QuestError::NotAccepted(quest_id.to_string()));
+        }
+        quest.accepted = false;
+        quest.started_at = None;
+        if self.tracked.as_deref() == Some(quest_id) {
+            self.tracked = None;
+        }
+        Ok(())
+    }
+
+    pub fn track_quest(&mut self, quest_id: &str) -> Result<(), QuestError> {
+        if !self.active_quest_mut(quest_id)?.is_accepted() {
+            return Err(QuestError::NotAccepted(quest_id.to_string()));
+        }
+        self.tracked = Some(quest_id.to_string());
+        Ok(())
+    }
+
+    pub fn untrack_quest(&mut self) {
+        self.tracked = None;
+    }
+
+    pub fn tracked_quest(&self) -> Option<&Quest> {
+        let id = self.tracked.as_deref()?;
+        self.active_quests.iter().find(|q| q.id == id)
+    }
+
+    fn active_quest_mut(&mut self, quest_id: &str) -> Result<&mut Quest, QuestError> {
+        match self.active_quests.iter().position(|q| q.id == quest_id) {
+            Some(index) => Ok(&mut self.active_quests[index]),
+            None => Err(self.missing(quest_id)),
+        }
+    }
+
+    // a finished one-off is still a known quest, just not one you can act on
+    fn missing(&self, quest_id: &str) -> QuestError {
+        if self.has_quest(quest_id) {
+            QuestError::NotAvailable(quest_id.to_string())
+        } else {
+            QuestError::UnknownQuest(quest_id.to_string())
+        }
+    }
+
     pub fn get_available_quests(&self) -> Vec<&Quest> {
-        self.active_quests.iter().filter(|q| q.is_unlocked() && !q.is_completed()).collect()
+        let now = self.clock.now();
+        let dailies = self.todays_dailies();
+        self.active_quests
+            .iter()
+            .filter(|q| self.is_available(q, now, &dailies))
+            .collect()
+    }
+
+    pub fn get_accepted_quests(&self) -> Vec<&Quest> {
+        self.get_available_quests()
+            .into_iter()
+            .filter(|q| q.is_accepted())
+            .collect()
     }
 
     pub fn get_locked_quests(&self) -> Vec<&Quest> {
         self.active_quests.iter().filter(|q| !q.is_unlocked()).collect()
     }
 
+    // timed quests whose clock ran out, they can be restarted
+    pub fn get_expired_quests(&self) -> Vec<&Quest> {
+        self.active_quests
+            .iter()
+            .filter(|q| q.is_accepted() && self.time_left(q) == Some(0))
+            .collect()
+    }
+
+    // ids of the daily quests on offer today, rotating through the pool
+    pub fn todays_dailies(&self) -> Vec<&str> {
+        let pool: Vec<&str> = self
+            .active_quests
+            .iter()
+            .filter(|q| q.kind == QuestKind::Daily && q.is_unlocked())
+            .map(|q| q.id.as_str())
+            .collect();
+        if pool.is_empty() {
+            return Vec::new();
+        }
+
+        let count = self.daily_count.min(pool.len());
+        let start = (self.clock.today() as usize).wrapping_mul(count) % pool.len();
+        (0..count).map(|i| pool[(start + i) % pool.len()]).collect()
+    }
+
+    fn is_available(&self, quest: &Quest, now: u64, dailies: &[&str]) -> bool {
+        if !quest.is_unlocked() || quest.is_completed() {
+            return false;
+        }
+        match quest.kind {
+            QuestKind::Once => true,
+            QuestKind::Repeatable { .. } => self.cooldown_left(quest) == Some(0),
+            QuestKind::Daily => {
+                dailies.contains(&quest.id.as_str())
+                    && quest.last_completed.map(|t| t / SECONDS_PER_DAY) != Some(now / SECONDS_PER_DAY)
+            }
+            QuestKind::Timed { .. } => self.time_left(quest) != Some(0),
+        }
+    }
+
+    // seconds left on a timed quest, None for any other kind
+    pub fn time_left(&self, quest: &Quest) -> Option<u64> {
+        match (quest.kind, quest.started_at) {
+            (QuestKind::Timed { limit }, Some(started)) => {
+                Some((started + limit).saturating_sub(self.clock.now()))
+            }
+            (QuestKind::Timed { limit }, None) => Some(limit),
+            _ => None,
+        }
+    }
+
+    // seconds until a repeatable quest can be done again, None for any other kind
+    pub fn cooldown_left(&self, quest: &Quest) -> Option<u64> {
+        match quest.kind {
+            QuestKind::Repeatable { cooldown } => Some(
+                quest
+                    .last_completed
+                    .map_or(0, |t| (t + cooldown).saturating_sub(self.clock.now())),
+            ),
+            _ => None,
+        }
+    }
+
+    // starts a timed quest's clock over, e.g. after it ran out
+    pub fn restart_quest(&mut self, quest_id: &str) -> bool {
+        let (now, mark) = (self.clock.now(), self.last_mark);
+        match self.active_quests.iter_mut().find(|q| q.id == quest_id) {
+            Some(quest) if quest.is_accepted() && matches!(quest.kind, QuestKind::Timed { .. }) => {
+                quest.start_clock(now, mark);
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn unlock_dependent_quests(&mut self, completed_quest_id: &str) {
         let mut quests_to_unlock = Vec::new();
         
@@ -258,76 +1117,83 @@ impl QuestManager {
     }
 
     pub fn check_completion(&mut self, execution_context: &ExecutionContext) -> Vec<CoinReward> {
+        self.check_run_completion(execution_context, execution_context)
+    }
+
+    // per run objectives look at `run`, the rest at `history`
+    pub fn check_run_completion(&mut self, run: &ExecutionContext, history: &ExecutionContext) -> Vec<CoinReward> {
+        let now = self.clock.now();
+        self.roll_over_dailies();
+
         let mut rewards = Vec::new();
         let mut completed_quest_indices = Vec::new();
+        let dailies = self.todays_dailies();
 
         for (index, quest) in self.active_quests.iter().enumerate() {
-            if quest.completed {
+            if !quest.is_accepted() || !self.is_available(quest, now, &dailies) {
                 continue;
             }
 
             let all_objectives_met = quest.objectives.iter().all(|objective| {
-                QuestManager::check_objective_static(objective, execution_context)
+                objective.is_met(quest_events(quest, objective, run, history))
             });
 
             if all_objectives_met {
-                rewards.extend(quest.rewards.clone());
+                rewards.extend(quest.earned_rewards());
                 completed_quest_indices.push(index);
             }
         }
 
-        for &index in completed_quest_indices.iter().rev() {
-            self.active_quests[index].mark_completed();
-            let completed_quest = self.active_quests.remove(index);
-            let quest_id = completed_quest.id.clone();
-            self.completed_quests.push(completed_quest);
-            
-            self.unlock_dependent_quests(&quest_id);
-        }
-
-        rewards
-    }
-
-    fn check_objective(&self, objective: &QuestObjective, context: &ExecutionContext) -> bool {
-        QuestManager::check_objective_static(objective, context)
-    }
-
-    fn check_objective_static(objective: &QuestObjective, context: &ExecutionContext) -> bool {
-        match objective {
-            QuestObjective::ExecuteProgram { pattern } => {
-                context.executed_expressions.iter().any(|expr| expr.contains(pattern))
-            }
-            QuestObjective::DefineFunction { min_params } => {
-                context.functions.values().any(|func| func.params.len() >= *min_params)
-            }
-            QuestObjective::UseVariables { count } => {
-                context.variables.len() >= *count
-            }
-            QuestObjective::ProduceOutput { expected } => {
-                context.output.iter().any(|output| output == expected)
-            }
-            QuestObjective::CreateVariable { name } => {
-                if let Some(var_name) = name {
-                    context.variables.contains_key(var_name)
-                } else {
-                    !context.variables.is_empty()
+        for &index in completed_quest_indices.iter().rev() {
+            let quest = &mut self.active_quests[index];
+            quest.times_completed += 1;
+            quest.last_completed = Some(now);
+
+            let first_time = quest.times_completed == 1;
+            let quest_id = quest.id.clone();
+            match quest.kind {
+                QuestKind::Once | QuestKind::Timed { .. } => {
+                    quest.mark_completed();
+                    if self.tracked.as_deref() == Some(quest_id.as_str()) {
+                        self.tracked = None;
+                    }
+                    let completed_quest = self.active_quests.remove(index);
+                    self.completed_quests.push(completed_quest);
                 }
-            }
-            QuestObjective::CallFunction { name } => {
-                if let Some(func_name) = name {
-                    context.executed_expressions.iter().any(|expr| {
-                        expr.contains("FnCall") && expr.contains(func_name)
-                    })
-                } else {
-                    context.executed_expressions.iter().any(|expr| expr.contains("FnCall"))
+                // these stay active and start counting again from here
+                QuestKind::Repeatable { .. } | QuestKind::Daily => {
+                    quest.progress_from = history.mark();
+                    quest.hints_revealed = 0;
+                    if first_time {
+                        let mut record = quest.clone();
+                        record.mark_completed();
+                        self.completed_quests.push(record);
+                    }
                 }
             }
-            QuestObjective::PerformArithmetic => {
-                context.executed_expressions.iter().any(|expr| {
-                    expr.contains("Binary") || expr.contains("arithmetic")
-                })
+
+            if first_time {
+                self.unlock_dependent_quests(&quest_id);
+            }
+        }
+
+        self.last_mark = history.mark();
+        rewards
+    }
+
+    // on a new day the daily quests only count what happened since the last
+    // check, so yesterday's work doesn't finish today's dailies
+    fn roll_over_dailies(&mut self) {
+        let today = self.clock.today();
+        if self.daily_day == Some(today) {
+            return;
+        }
+        if self.daily_day.is_some() {
+            for quest in self.active_quests.iter_mut().filter(|q| q.kind == QuestKind::Daily) {
+                quest.progress_from = self.last_mark;
             }
         }
+        self.daily_day = Some(today);
     }
 
     pub fn get_quest_by_id(&self, quest_id: &str) -> Option<&Quest> {
@@ -336,15 +1202,28 @@ impl QuestManager {
     }
 
     pub fn get_quest_progress(&self, quest_id: &str, context: &ExecutionContext) -> Option<QuestProgress> {
+        self.get_run_progress(quest_id, context, context)
+    }
+
+    pub fn get_run_progress(
+        &self,
+        quest_id: &str,
+        run: &ExecutionContext,
+        history: &ExecutionContext,
+    ) -> Option<QuestProgress> {
         if let Some(quest) = self.get_quest_by_id(quest_id) {
             let completed_objectives = quest.objectives.iter()
-                .map(|obj| self.check_objective(obj, context))
+                .map(|obj| obj.is_met(quest_events(quest, obj, run, history)))
+                .collect();
+            let objectives = quest.objectives.iter()
+                .map(|obj| obj.progress(quest_events(quest, obj, run, history)))
                 .collect();
             
             Some(QuestProgress {
                 quest_id: quest_id.to_string(),
                 total_objectives: quest.objectives.len(),
                 completed_objectives,
+                objectives,
                 is_complete: quest.completed,
             })
         } else {
@@ -352,157 +1231,44 @@ impl QuestManager {
         }
     }
 
-    pub fn initialize_starter_quests(&mut self) {
-        let hello_world_quest = Quest::new_with_difficulty(
-            "hello_world".to_string(),
-            "Hello World".to_string(),
-            "Welcome to CAng! Start by performing a simple arithmetic calculation like '2 + 3' to get familiar with the interpreter.".to_string(),
-            vec![QuestObjective::PerformArithmetic],
-            vec![CoinReward {
-                coin_type: CoinType::Variable,
-                amount: 2,
-            }],
-            QuestDifficulty::Beginner,
-            vec![], 
-        );
-
-        let first_variable_quest = Quest::new_with_difficulty(
-            "first_variable".to_string(),
-            "First Variable".to_string(),
-            "Learn to store values by creating your first variable. Try 'let x = 5' to create a variable named 'x' with value 5.".to_string(),
-            vec![QuestObjective::CreateVariable { name: None }],
-            vec![CoinReward {
-                coin_type: CoinType::Variable,
-                amount: 3,
-            }],
-            QuestDifficulty::Beginner,
-            vec!["hello_world".to_string()], 
-        );
-
-        let variable_arithmetic_quest = Quest::new_with_difficulty(
-            "variable_arithmetic".to_string(),
-            "Variable Arithmetic".to_string(),
-            "Combine variables with arithmetic! Create a variable and then use it in a calculation.".to_string(),
-            vec![
-                QuestObjective::CreateVariable { name: None },
-                QuestObjective::PerformArithmetic,
-            ],
-            vec![CoinReward {
-                coin_type: CoinType::Variable,
-                amount: 2,
-            }],
-            QuestDifficulty::Beginner,
-            vec!["first_variable".to_string()],
-        );
-
-        
-        let first_function_quest = Quest::new_with_difficulty(
-            "first_function".to_string(),
-            "First Function".to_string(),
-            "Define your first function to reuse code. Try 'fn add(a, b) { a + b }' to create a function that adds two numbers.".to_string(),
-            vec![QuestObjective::DefineFunction { min_params: 0 }],
-            vec![CoinReward {
-                coin_type: CoinType::Function,
-                amount: 2,
-            }],
-            QuestDifficulty::Intermediate,
-            vec!["variable_arithmetic".to_string()],
-        );
-
-        let function_with_params_quest = Quest::new_with_difficulty(
-            "function_with_params".to_string(),
-            "Parameterized Function".to_string(),
-            "Create a function that takes at least one parameter. Parameters make functions flexible and reusable.".to_string(),
-            vec![QuestObjective::DefineFunction { min_params: 1 }],
-            vec![CoinReward {
-                coin_type: CoinType::Function,
-                amount: 1,
-            }],
-            QuestDifficulty::Intermediate,
-            vec!["first_function".to_string()],
-        );
-
-        let multiple_variables_quest = Quest::new_with_difficulty(
-            "multiple_variables".to_string(),
-            "Variable Master".to_string(),
-            "Show your mastery by creating at least 3 different variables in your program.".to_string(),
-            vec![QuestObjective::UseVariables { count: 3 }],
-            vec![CoinReward {
-                coin_type: CoinType::Variable,
-                amount: 4,
-            }],
-            QuestDifficulty::Intermediate,
-            vec!["variable_arithmetic".to_string()],
-        );
-
-        
-        let function_caller_quest = Quest::new_with_difficulty(
-            "function_caller".to_string(),
-            "Function Caller".to_string(),
-            "Define a function and then call it! This demonstrates the full function lifecycle.".to_string(),
-            vec![
-                QuestObjective::DefineFunction { min_params: 1 },
-                QuestObjective::CallFunction { name: None },
-            ],
-            vec![
-                CoinReward {
-                    coin_type: CoinType::Function,
-                    amount: 2,
-                },
-                CoinReward {
-                    coin_type: CoinType::Variable,
-                    amount: 2,
-                },
-            ],
-            QuestDifficulty::Advanced,
-            vec!["function_with_params".to_string()],
-        );
-
-        let complex_program_quest = Quest::new_with_difficulty(
-            "complex_program".to_string(),
-            "Complex Program".to_string(),
-            "Create a sophisticated program that uses multiple variables, defines a function, and performs calculations.".to_string(),
-            vec![
-                QuestObjective::UseVariables { count: 2 },
-                QuestObjective::DefineFunction { min_params: 1 },
-                QuestObjective::PerformArithmetic,
-            ],
-            vec![
-                CoinReward {
-                    coin_type: CoinType::Variable,
-                    amount: 5,
-                },
-                CoinReward {
-                    coin_type: CoinType::Function,
-                    amount: 3,
-                },
-            ],
-            QuestDifficulty::Advanced,
-            vec!["multiple_variables".to_string(), "function_caller".to_string()],
-        );
+    // validates the whole batch first so a bad pack doesn't half load
+    pub fn load_quests(&mut self, quests: Vec<Quest>) -> Result<(), QuestPackError> {
+        let existing: Vec<&Quest> = self
+            .active_quests
+            .iter()
+            .chain(self.completed_quests.iter())
+            .collect();
+        quest_pack::validate(&existing, &quests)?;
+
+        for mut quest in quests {
+            let prerequisites_met = quest.prerequisites.iter().all(|prereq_id| {
+                self.completed_quests.iter().any(|completed| completed.id == *prereq_id)
+            });
+            if prerequisites_met {
+                quest.unlock();
+            }
+            self.active_quests.push(quest);
+        }
+        Ok(())
+    }
 
-        let print_hello_quest = Quest::new_with_difficulty(
-            "print_hello".to_string(),
-            "Print Hello".to_string(),
-            "Use the print statement to output 'Hello World' to the console. Try: print(\"Hello World\")".to_string(),
-            vec![QuestObjective::ProduceOutput { expected: "Hello World".to_string() }],
-            vec![CoinReward {
-                coin_type: CoinType::Variable,
-                amount: 1,
-            }],
-            QuestDifficulty::Beginner,
-            vec!["hello_world".to_string()],
-        );
+    pub fn initialize_starter_quests(&mut self) {
+        let quests = quest_pack::parse_pack(quest_pack::DEFAULT_PACK, PackFormat::Toml)
+            .expect("bundled quest pack is valid");
+        self.load_quests(quests)
+            .expect("bundled quest pack is valid");
+    }
+}
 
-        self.add_quest(hello_world_quest);
-        self.add_quest(print_hello_quest);
-        self.add_quest(first_variable_quest);
-        self.add_quest(variable_arithmetic_quest);
-        self.add_quest(first_function_quest);
-        self.add_quest(function_with_params_quest);
-        self.add_quest(multiple_variables_quest);
-        self.add_quest(function_caller_quest);
-        self.add_quest(complex_program_quest);
+fn quest_events<'c>(
+    quest: &Quest,
+    objective: &QuestObjective,
+    run: &'c ExecutionContext,
+    history: &'c ExecutionContext,
+) -> &'c [ExecutionEvent] {
+    match objective.scope() {
+        ObjectiveScope::Run => &run.events,
+        ObjectiveScope::Cumulative => history.events_since(quest.progress_from),
     }
 }
 
@@ -517,6 +1283,7 @@ pub struct QuestProgress {
     pub quest_id: String,
     pub total_objectives: usize,
     pub completed_objectives: Vec<bool>,
+    pub objectives: Vec<ObjectiveProgress>,
     pub is_complete: bool,
 }
 
@@ -526,14 +1293,45 @@ impl QuestProgress {
             return 100.0;
         }
         
-        let completed_count = self.completed_objectives.iter().filter(|&&completed| completed).count();
-        (completed_count as f32 / self.total_objectives as f32) * 100.0
+        // partial credit from nested objectives, e.g. 2 of 5 calls
+        let done: f32 = self.objectives.iter().map(|o| o.fraction()).sum();
+        (done / self.total_objectives as f32) * 100.0
     }
 }
 #
 [cfg(test)]
 mod tests {
     use super::*;
+    use crate::{CoinType, TokenTypes};
+
+    fn accept_all(quest_manager: &mut QuestManager) {
+        let ids: Vec<String> = quest_manager
+            .get_available_quests()
+            .iter()
+            .map(|q| q.id.clone())
+            .collect();
+        for id in ids {
+            quest_manager.accept_quest(&id).unwrap();
+        }
+    }
+
+    // the starter pack minus its daily and repeatable extras
+    fn story_quests(quest_manager: &QuestManager) -> Vec<&Quest> {
+        quest_manager
+            .get_available_quests()
+            .into_iter()
+            .filter(|q| q.kind == QuestKind::Once)
+            .collect()
+    }
+
+    fn arithmetic() -> ExecutionEvent {
+        ExecutionEvent::Arithmetic {
+            op: TokenTypes::Plus,
+            lhs: 2,
+            rhs: 3,
+            result: 5,
+        }
+    }
 
     #[test]
     fn test_quest_creation() {
@@ -595,6 +1393,7 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
         
         assert_eq!(quest_manager.get_active_quests().len(), 1);
         assert_eq!(quest_manager.get_completed_quests().len(), 0);
@@ -609,12 +1408,12 @@ mod tests {
         context.add_variable("x".to_string(), 42);
         context.add_function("test_func".to_string(), vec!["param1".to_string()], "body".to_string());
         context.add_output("Hello World".to_string());
-        context.record_expression("Binary".to_string());
+        context.record_event(arithmetic());
 
         assert_eq!(context.variables.len(), 1);
         assert_eq!(context.functions.len(), 1);
         assert_eq!(context.output.len(), 1);
-        assert_eq!(context.executed_expressions.len(), 1);
+        assert_eq!(context.events.len(), 4);
         assert_eq!(context.variables.get("x"), Some(&42));
     }
 
@@ -636,6 +1435,7 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -643,7 +1443,7 @@ mod tests {
         assert_eq!(quest_manager.get_active_quests().len(), 1);
 
         
-        context.record_expression("Binary".to_string());
+        context.record_event(arithmetic());
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -659,7 +1459,7 @@ mod tests {
         let mut quest_manager = QuestManager::new();
         quest_manager.initialize_starter_quests();
 
-        assert_eq!(quest_manager.get_active_quests().len(), 9);
+        assert_eq!(quest_manager.get_active_quests().len(), 14);
         
         let quest_ids: Vec<&String> = quest_manager.get_active_quests().iter().map(|q| &q.id).collect();
         assert!(quest_ids.contains(&&"hello_world".to_string()));
@@ -673,7 +1473,7 @@ mod tests {
         assert!(quest_ids.contains(&&"complex_program".to_string()));
 
         
-        let available_quests = quest_manager.get_available_quests();
+        let available_quests = story_quests(&quest_manager);
         assert_eq!(available_quests.len(), 1);
         assert_eq!(available_quests[0].id, "hello_world");
 
@@ -708,6 +1508,7 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         let progress = quest_manager.get_quest_progress("multi_objective_quest", &context).unwrap();
         assert_eq!(progress.total_objectives, 2);
@@ -733,6 +1534,7 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
         assert_eq!(quest_manager.get_active_quests().len(), 1);
@@ -740,7 +1542,6 @@ mod tests {
 
         
         context.add_variable("test_var".to_string(), 42);
-        context.record_expression("Let(test_var)".to_string());
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -771,10 +1572,10 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
         context.add_function("test_func".to_string(), vec!["a".to_string(), "b".to_string()], "a + b".to_string());
-        context.record_expression("FnDef(test_func, 2 params)".to_string());
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -805,9 +1606,10 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
-        context.record_expression("Binary(2 + 3)".to_string());
+        context.record_event(arithmetic());
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -838,10 +1640,10 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
         context.add_variable("x".to_string(), 42);
-        context.record_expression("Let(x = 42)".to_string());
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -872,6 +1674,7 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
         context.add_variable("x".to_string(), 1);
@@ -919,6 +1722,7 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
         
@@ -937,7 +1741,7 @@ mod tests {
         assert!(rewards.is_empty());
 
         
-        context.record_expression("Binary(x + y)".to_string());
+        context.record_event(arithmetic());
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -952,44 +1756,52 @@ mod tests {
         quest_manager.initialize_starter_quests();
 
         
-        let available = quest_manager.get_available_quests();
+        let available = story_quests(&quest_manager);
         assert_eq!(available.len(), 1);
         assert_eq!(available[0].id, "hello_world");
 
         
         let mut context = ExecutionContext::new();
-        context.record_expression("Binary(2 + 3)".to_string());
+        accept_all(&mut quest_manager);
+        context.record_event(arithmetic());
         
         let rewards = quest_manager.check_completion(&context);
         assert!(!rewards.is_empty());
 
         
-        let available = quest_manager.get_available_quests();
-        assert_eq!(available.len(), 1);
-        assert_eq!(available[0].id, "first_variable");
+        let available = story_quests(&quest_manager);
+        assert_eq!(available.len(), 2);
+        let quest_ids: Vec<&str> = available.iter().map(|q| q.id.as_str()).collect();
+        assert!(quest_ids.contains(&"print_hello"));
+        assert!(quest_ids.contains(&"first_variable"));
 
         
+        accept_all(&mut quest_manager);
         let mut context2 = ExecutionContext::new();
         context2.add_variable("x".to_string(), 5);
         let rewards = quest_manager.check_completion(&context2);
         assert!(!rewards.is_empty());
 
         
-        let available = quest_manager.get_available_quests();
-        assert_eq!(available.len(), 1);
-        assert_eq!(available[0].id, "variable_arithmetic");
+        let available = story_quests(&quest_manager);
+        assert_eq!(available.len(), 2);
+        let quest_ids: Vec<&str> = available.iter().map(|q| q.id.as_str()).collect();
+        assert!(quest_ids.contains(&"print_hello"));
+        assert!(quest_ids.contains(&"variable_arithmetic"));
 
         
+        accept_all(&mut quest_manager);
         let mut context3 = ExecutionContext::new();
         context3.add_variable("y".to_string(), 10);
-        context3.record_expression("Binary(y + 5)".to_string());
+        context3.record_event(arithmetic());
         let rewards = quest_manager.check_completion(&context3);
         assert!(!rewards.is_empty());
 
         
-        let available = quest_manager.get_available_quests();
-        assert_eq!(available.len(), 2);
+        let available = story_quests(&quest_manager);
+        assert_eq!(available.len(), 3);
         let quest_ids: Vec<&str> = available.iter().map(|q| q.id.as_str()).collect();
+        assert!(quest_ids.contains(&"print_hello"));
         assert!(quest_ids.contains(&"first_function"));
         assert!(quest_ids.contains(&"multiple_variables"));
     }
@@ -1031,9 +1843,13 @@ mod tests {
         );
 
         quest_manager.add_quest(quest);
+        accept_all(&mut quest_manager);
 
         
-        context.record_expression("FnCall(test_func)".to_string());
+        context.record_event(ExecutionEvent::FunctionCalled {
+            name: "test_func".to_string(),
+            args: vec![],
+        });
 
         
         let rewards = quest_manager.check_completion(&context);
@@ -1045,4 +1861,498 @@ mod tests {
         assert_eq!(quest_manager.get_active_quests().len(), 0);
         assert_eq!(quest_manager.get_completed_quests().len(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_call_function_matches_exact_name() {
+        let mut quest_manager = QuestManager::new();
+        let mut context = ExecutionContext::new();
+
+        quest_manager.add_quest(Quest::new(
+            "call_ad".to_string(),
+            "Call ad".to_string(),
+            "Call ad".to_string(),
+            vec![QuestObjective::CallFunction {
+                name: Some("ad".to_string()),
+            }],
+            vec![],
+        ));
+        accept_all(&mut quest_manager);
+
+        context.record_event(ExecutionEvent::FunctionCalled {
+            name: "add".to_string(),
+            args: vec![1, 2],
+        });
+        quest_manager.check_completion(&context);
+        assert_eq!(quest_manager.get_completed_quests().len(), 0);
+
+        context.record_event(ExecutionEvent::FunctionCalled {
+            name: "ad".to_string(),
+            args: vec![],
+        });
+        quest_manager.check_completion(&context);
+        assert_eq!(quest_manager.get_completed_quests().len(), 1);
+    }
+
+    #[test]
+    fn test_variable_names_dont_count_as_arithmetic() {
+        let mut quest_manager = QuestManager::new();
+        let mut context = ExecutionContext::new();
+
+        quest_manager.add_quest(Quest::new(
+            "math".to_string(),
+            "Math".to_string(),
+            "Do math".to_string(),
+            vec![QuestObjective::PerformArithmetic],
+            vec![],
+        ));
+        accept_all(&mut quest_manager);
+
+        context.add_variable("Binary".to_string(), 1);
+        context.record_event(ExecutionEvent::VariableRead {
+            name: "arithmetic".to_string(),
+            value: 1,
+        });
+        quest_manager.check_completion(&context);
+        assert_eq!(quest_manager.get_completed_quests().len(), 0);
+    }
+
+    fn started(source: &str) -> ExecutionEvent {
+        ExecutionEvent::ProgramStarted {
+            source: source.to_string(),
+        }
+    }
+
+    fn call(name: &str) -> ExecutionEvent {
+        ExecutionEvent::FunctionCalled {
+            name: name.to_string(),
+            args: vec![],
+        }
+    }
+
+    fn fib_fan() -> QuestObjective {
+        QuestObjective::WithinRun(Box::new(QuestObjective::AllOf(vec![
+            QuestObjective::AtLeast {
+                count: 5,
+                objective: Box::new(QuestObjective::CallFunction {
+                    name: Some("fib".to_string()),
+                }),
+            },
+            QuestObjective::Not(Box::new(QuestObjective::CreateVariable { name: None })),
+        ])))
+    }
+
+    #[test]
+    fn test_within_run_does_not_combine_runs() {
+        let objective = fib_fan();
+        let mut events = vec![started("fib(1)"), call("fib"), call("fib"), call("fib")];
+        events.extend([started("fib(2)"), call("fib"), call("fib")]);
+        assert!(!objective.is_met(&events));
+
+        // a variable in the same run spoils it even with enough calls
+        events.extend([started("let x = fib(5)")]);
+        events.extend((0..5).map(|_| call("fib")));
+        events.push(ExecutionEvent::VariableDefined {
+            name: "x".to_string(),
+            value: 5,
+        });
+        assert!(!objective.is_met(&events));
+
+        events.push(started("fib(5)"));
+        events.extend((0..5).map(|_| call("fib")));
+        assert!(objective.is_met(&events));
+    }
+
+    #[test]
+    fn test_any_of_and_in_order() {
+        let either = QuestObjective::AnyOf(vec![
+            QuestObjective::PerformArithmetic,
+            QuestObjective::CallFunction { name: None },
+        ]);
+        assert!(either.is_met(&[call("f")]));
+        assert!(!either.is_met(&[started("")]));
+
+        let sequence = QuestObjective::InOrder(vec![
+            QuestObjective::DefineFunction { min_params: 0 },
+            QuestObjective::CallFunction { name: Some("f".to_string()) },
+        ]);
+        let defined = ExecutionEvent::FunctionDefined {
+            name: "f".to_string(),
+            params: vec![],
+            body: String::new(),
+        };
+        assert!(!sequence.is_met(&[call("f"), defined.clone()]));
+        assert!(sequence.is_met(&[call("f"), defined.clone(), call("f")]));
+        assert_eq!(sequence.times_met(&[defined.clone(), call("f"), defined, call("f")]), 2);
+    }
+
+    #[test]
+    fn test_in_order_over_long_history() {
+        let sequence = QuestObjective::InOrder(vec![
+            QuestObjective::WithinRun(Box::new(QuestObjective::AtLeast {
+                count: 2,
+                objective: Box::new(QuestObjective::CallFunction { name: Some("f".to_string()) }),
+            })),
+            QuestObjective::Not(Box::new(QuestObjective::CallFunction { name: None })),
+        ]);
+        let mut events = Vec::new();
+        for _ in 0..DEFAULT_RETENTION / 4 {
+            events.extend([started(""), call("f"), call("f"), started("")]);
+        }
+
+        // each run of two calls is followed by a marker that isn't a call
+        assert_eq!(sequence.times_met(&events), DEFAULT_RETENTION / 4);
+        assert_eq!(sequence.progress(&events[..2]).current, 0);
+        assert_eq!(sequence.progress(&events[..3]).current, 1);
+        assert!(sequence.is_met(&events[..4]));
+    }
+
+    #[test]
+    fn test_progress_tree() {
+        let mut quest_manager = QuestManager::new();
+        let mut context = ExecutionContext::new();
+        quest_manager.add_quest(Quest::new(
+            "fib_fan".to_string(),
+            "Fib Fan".to_string(),
+            "Call fib a lot".to_string(),
+            vec![fib_fan()],
+            vec![CoinReward {
+                coin_type: CoinType::Function,
+                amount: 1,
+            }],
+        ));
+        accept_all(&mut quest_manager);
+
+        context.record_event(started("fib(2)"));
+        context.record_event(call("fib"));
+        context.record_event(call("fib"));
+
+        let progress = quest_manager.get_quest_progress("fib_fan", &context).unwrap();
+        let run = &progress.objectives[0];
+        assert!(!run.complete);
+        let all = &run.children[0];
+        assert_eq!((all.current, all.target), (1, 2));
+        assert_eq!((all.children[0].current, all.children[0].target), (2, 5));
+        assert!(all.children[1].complete);
+        assert_eq!(progress.completion_percentage(), 50.0);
+
+        for _ in 0..3 {
+            context.record_event(call("fib"));
+        }
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+    }
+
+    #[test]
+    fn test_pass_tests_needs_every_case() {
+        let cases = vec![
+            TestCase { args: vec![2], expected: 4 },
+            TestCase { args: vec![3], expected: 9 },
+        ];
+        let objective = QuestObjective::PassTests {
+            function: "square".to_string(),
+            cases: cases.clone(),
+        };
+        let run = |passed| ExecutionEvent::ChallengeRun {
+            function: "square".to_string(),
+            cases: cases.clone(),
+            passed,
+        };
+
+        let events = vec![run(1)];
+        assert!(!objective.is_met(&events));
+        assert_eq!(objective.progress(&events).current, 1);
+
+        // same name with different cases belongs to some other quest
+        let other = ExecutionEvent::ChallengeRun {
+            function: "square".to_string(),
+            cases: vec![],
+            passed: 0,
+        };
+        assert!(!objective.is_met(&[other]));
+        assert!(objective.is_met(&[run(1), run(2)]));
+    }
+
+    #[test]
+    fn test_run_scoped_objectives_ignore_history() {
+        let mut quest_manager = QuestManager::new();
+        quest_manager.add_quest(Quest::new(
+            "three_vars".to_string(),
+            "Three Vars".to_string(),
+            "Three variables in one program".to_string(),
+            vec![QuestObjective::WithinRun(Box::new(QuestObjective::UseVariables { count: 3 }))],
+            vec![],
+        ));
+        accept_all(&mut quest_manager);
+
+        let mut history = ExecutionContext::new();
+        for name in ["a", "b"] {
+            let mut run = ExecutionContext::new();
+            for context in [&mut run, &mut history] {
+                context.record_event(started(name));
+                context.add_variable(name.to_string(), 1);
+            }
+            assert!(quest_manager.check_run_completion(&run, &history).is_empty());
+        }
+
+        let mut run = ExecutionContext::new();
+        for context in [&mut run, &mut history] {
+            context.record_event(started("let c = 1"));
+            context.add_variable("c".to_string(), 1);
+        }
+        assert_eq!(history.variables.len(), 3);
+        let progress = quest_manager.get_run_progress("three_vars", &run, &history).unwrap();
+        assert_eq!(progress.objectives[0].current, 1);
+        quest_manager.check_run_completion(&run, &history);
+        assert!(quest_manager.get_completed_quests().is_empty());
+
+        run.add_variable("d".to_string(), 1);
+        run.add_variable("e".to_string(), 1);
+        quest_manager.check_run_completion(&run, &history);
+        assert_eq!(quest_manager.get_completed_quests().len(), 1);
+    }
+
+    #[test]
+    fn test_history_retention_and_reset() {
+        let mut context = ExecutionContext::with_retention(3);
+        for i in 0..5 {
+            context.record_event(started("print(1)"));
+            context.add_output(i.to_string());
+        }
+
+        assert_eq!(context.events.len(), 3);
+        assert_eq!(context.output, vec!["2", "3", "4"]);
+        assert_eq!(context.programs_run(), 5);
+
+        context.reset();
+        assert!(context.events.is_empty());
+        assert_eq!(context.programs_run(), 0);
+        for _ in 0..4 {
+            context.record_event(started(""));
+        }
+        assert_eq!(context.events.len(), 3);
+    }
+
+    fn clocked_manager() -> (QuestManager, crate::clock::ManualClock) {
+        let clock = crate::clock::ManualClock::new(10 * SECONDS_PER_DAY);
+        (QuestManager::with_clock(Box::new(clock.clone())), clock)
+    }
+
+    fn runs_quest(id: &str, count: usize, kind: QuestKind) -> Quest {
+        Quest::new(
+            id.to_string(),
+            id.to_string(),
+            String::new(),
+            vec![QuestObjective::RunPrograms { count }],
+            vec![CoinReward {
+                coin_type: CoinType::Variable,
+                amount: 1,
+            }],
+        )
+        .with_kind(kind)
+    }
+
+    #[test]
+    fn test_repeatable_quest_cooldown() {
+        let (mut quest_manager, clock) = clocked_manager();
+        quest_manager.add_quest(runs_quest("grind", 2, QuestKind::Repeatable { cooldown: 60 }));
+        accept_all(&mut quest_manager);
+        let mut context = ExecutionContext::new();
+
+        context.record_event(started(""));
+        context.record_event(started(""));
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+        assert_eq!(quest_manager.get_completed_quests().len(), 1);
+        assert!(quest_manager.get_available_quests().is_empty());
+
+        // runs during the cooldown still count toward the next completion
+        context.record_event(started(""));
+        context.record_event(started(""));
+        assert!(quest_manager.check_completion(&context).is_empty());
+
+        clock.advance(60);
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+        assert_eq!(quest_manager.get_quest_by_id("grind").unwrap().times_completed, 2);
+        assert_eq!(quest_manager.get_completed_quests().len(), 1);
+    }
+
+    #[test]
+    fn test_daily_quests_rotate() {
+        let (mut quest_manager, clock) = clocked_manager();
+        for id in ["a", "b", "c"] {
+            quest_manager.add_quest(runs_quest(id, 1, QuestKind::Daily));
+        }
+        let mut context = ExecutionContext::new();
+
+        let today = quest_manager.todays_dailies()[0].to_string();
+        clock.advance(SECONDS_PER_DAY);
+        let tomorrow = quest_manager.todays_dailies()[0].to_string();
+        assert_ne!(today, tomorrow);
+
+        accept_all(&mut quest_manager);
+        context.record_event(started(""));
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+        assert!(quest_manager.get_available_quests().is_empty());
+
+        // the new day's quest doesn't get credit for yesterday's run
+        clock.advance(SECONDS_PER_DAY);
+        accept_all(&mut quest_manager);
+        assert!(quest_manager.check_completion(&context).is_empty());
+        context.record_event(started(""));
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+    }
+
+    #[test]
+    fn test_timed_quest_expires_and_restarts() {
+        let (mut quest_manager, clock) = clocked_manager();
+        let mut context = ExecutionContext::new();
+        context.record_event(started(""));
+        quest_manager.check_completion(&context);
+
+        quest_manager.add_quest(runs_quest("sprint", 2, QuestKind::Timed { limit: 300 }));
+        accept_all(&mut quest_manager);
+        let sprint = quest_manager.get_quest_by_id("sprint").unwrap();
+        assert_eq!(quest_manager.time_left(sprint), Some(300));
+
+        clock.advance(200);
+        context.record_event(started(""));
+        assert!(quest_manager.check_completion(&context).is_empty());
+
+        clock.advance(200);
+        context.record_event(started(""));
+        assert!(quest_manager.check_completion(&context).is_empty());
+        assert_eq!(quest_manager.get_expired_quests().len(), 1);
+
+        assert!(quest_manager.restart_quest("sprint"));
+        context.record_event(started(""));
+        assert!(quest_manager.check_completion(&context).is_empty());
+        context.record_event(started(""));
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+    }
+
+    #[test]
+    fn test_accept_abandon_and_track() {
+        let mut quest_manager = QuestManager::new();
+        quest_manager.add_quest(runs_quest("run", 1, QuestKind::Once));
+        let mut context = ExecutionContext::new();
+
+        context.record_event(started(""));
+        assert!(quest_manager.check_completion(&context).is_empty());
+        assert_eq!(
+            quest_manager.track_quest("run"),
+            Err(QuestError::NotAccepted("run".to_string()))
+        );
+        assert_eq!(
+            quest_manager.accept_quest("nope"),
+            Err(QuestError::UnknownQuest("nope".to_string()))
+        );
+
+        // runs from before accepting don't count
+        quest_manager.accept_quest("run").unwrap();
+        quest_manager.track_quest("run").unwrap();
+        assert_eq!(quest_manager.get_accepted_quests().len(), 1);
+        assert!(quest_manager.check_completion(&context).is_empty());
+
+        quest_manager.abandon_quest("run").unwrap();
+        assert!(quest_manager.tracked_quest().is_none());
+        context.record_event(started(""));
+        assert!(quest_manager.check_completion(&context).is_empty());
+
+        quest_manager.accept_quest("run").unwrap();
+        quest_manager.track_quest("run").unwrap();
+        context.record_event(started(""));
+        assert_eq!(quest_manager.check_completion(&context).len(), 1);
+        assert!(quest_manager.tracked_quest().is_none());
+        assert_eq!(
+            quest_manager.accept_quest("run"),
+            Err(QuestError::NotAvailable("run".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_progress_mark_belongs_to_one_context() {
+        let mut quest_manager = QuestManager::new();
+        quest_manager.add_quest(runs_quest("run", 1, QuestKind::Once));
+        let mut history = ExecutionContext::new();
+        for _ in 0..3 {
+            history.record_event(started(""));
+        }
+        quest_manager.check_completion(&history);
+        quest_manager.accept_quest("run").unwrap();
+        assert!(history.events_since(quest_manager.get_quest_by_id("run").unwrap().progress_from).is_empty());
+
+        // a fresh context only holds events from after accepting
+        let mut fresh = ExecutionContext::new();
+        fresh.record_event(started(""));
+        assert_eq!(quest_manager.check_completion(&fresh).len(), 1);
+    }
+
+    #[test]
+    fn test_hints_cost_coins_and_solution_halves_rewards() {
+        let mut quest_manager = QuestManager::new();
+        let hint = |text: &str, cost, solution| Hint {
+            text: text.to_string(),
+            cost,
+            solution,
+        };
+        quest_manager.add_quest(
+            runs_quest("run", 1, QuestKind::Once)
+                .with_hints(vec![hint("just run it", None, false), hint("print(1)", Some(3), true)]),
+        );
+        accept_all(&mut quest_manager);
+        let mut coins = CoinManager::with_balances(4, 0);
+
+        let (number, first) = quest_manager.reveal_hint("run", &mut coins).unwrap();
+        assert_eq!((number, first.text.as_str()), (1, "just run it"));
+        assert_eq!(coins.get_balance(CoinType::Variable), 3);
+        assert_eq!(quest_manager.next_hint_cost("run").unwrap().amt, 3);
+
+        quest_manager.reveal_hint("run", &mut coins).unwrap();
+        assert_eq!(coins.get_balance(CoinType::Variable), 0);
+        assert_eq!(
+            quest_manager.reveal_hint("run", &mut coins),
+            Err(QuestError::NoMoreHints("run".to_string()))
+        );
+
+        let mut context = ExecutionContext::new();
+        context.record_event(started(""));
+        let rewards = quest_manager.check_completion(&context);
+        assert_eq!(rewards[0].amount, 0);
+    }
+
+    #[test]
+    fn test_hint_needs_coins() {
+        let mut quest_manager = QuestManager::new();
+        quest_manager.add_quest(runs_quest("run", 1, QuestKind::Once).with_hints(vec![Hint {
+            text: "run it".to_string(),
+            cost: None,
+            solution: false,
+        }]));
+        accept_all(&mut quest_manager);
+        let mut coins = CoinManager::with_balances(0, 0);
+
+        assert!(matches!(
+            quest_manager.reveal_hint("run", &mut coins),
+            Err(QuestError::CoinError(CoinError::InsufficientFunds { .. }))
+        ));
+        assert_eq!(quest_manager.get_quest_by_id("run").unwrap().hints_revealed, 0);
+    }
+
+    #[test]
+    fn test_hint_needs_accepted_quest() {
+        let mut quest_manager = QuestManager::new();
+        quest_manager.add_quest(runs_quest("run", 1, QuestKind::Once).with_hints(vec![Hint {
+            text: "run it".to_string(),
+            cost: None,
+            solution: false,
+        }]));
+        let mut coins = CoinManager::with_balances(1, 0);
+
+        assert_eq!(
+            quest_manager.reveal_hint("run", &mut coins),
+            Err(QuestError::NotAccepted("run".to_string()))
+        );
+        assert_eq!(coins.get_balance(CoinType::Variable), 1);
+
+        quest_manager.accept_quest("run").unwrap();
+        assert!(quest_manager.reveal_hint("run", &mut coins).is_ok());
+    }
+}
@@ -1,44 +1,240 @@
 use std::{
+    cell::RefCell,
     collections::HashMap,
-    io::{self, Write},
+    fmt, fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
 };
 
 use crate::{
-    parser::{eval_with_validation, Parser},
-    tokenize, CoinManager, Expr, ResourceValidator, QuestManager, ExecutionContext,
+    achievements::{Achievement, AchievementTracker},
+    challenge::{run_challenge, ChallengeReport, TestCase},
+    clock::format_date,
+    coin_manager::MAX_CREDIT_LIMIT,
+    debugger::Debugger,
+    events::ExecutionEvent,
+    input::{InputSource, ReaderInput, StdinInput},
+    linter::Linter,
+    modules::ModuleError,
+    output::{Output, StdoutOutput},
+    natives::NativeRegistry,
+    parser::{input_summary, program, run_program, Parser, Statement, INPUT_FUNCTIONS},
+    quest_pack,
+    quest_system::DEFAULT_RETENTION,
+    tokenize, CoinCost, CoinManager, CoinReward, CoinType, Expr, IncomeRecord, IncomeSource, IncomeTracker,
+    MeteringMode, ObjectiveProgress, Quest, QuestGraph, QuestKind, ResourceValidator, QuestManager, QuestPackError, QuestProgress,
+    ExecutionContext, Upgrade, ValidationError,
 };
 
+const INCOME_STATE_FILE: &str = "income";
+const ACHIEVEMENTS_STATE_FILE: &str = "achievements";
+const QUEST_PACK_DIR: &str = "quests";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunErrorKind {
+    Parse,
+    Coins,
+    Runtime,
+    Import, // an imported module couldn't be found or loaded
+}
+
+impl RunErrorKind {
+    fn of(error: &ValidationError) -> Self {
+        match error {
+            ValidationError::CoinError(_) => RunErrorKind::Coins,
+            ValidationError::ParseError(_) => RunErrorKind::Parse,
+            ValidationError::RuntimeError(_) => RunErrorKind::Runtime,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunError {
+    pub kind: RunErrorKind,
+    pub message: String,
+    pub pos: Option<(usize, usize)>, // (line, col) of the token or statement at fault
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pos {
+            Some((line, col)) => write!(f, "{} (line {}, col {})", self.message, line, col),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+// everything a run did, for front ends that render it themselves
+#[derive(Debug, Default)]
+pub struct RunOutcome {
+    pub value: Option<i64>, // None for print statements and failed runs
+    pub output: Vec<String>,
+    pub charged: Vec<CoinCost>,
+    pub challenges: Vec<(String, ChallengeReport)>, // quest title, report
+    pub completed_quests: Vec<String>,
+    pub rewards: Vec<CoinReward>,
+    pub income: Option<IncomeRecord>,
+    pub interest: Vec<(CoinType, u32)>,
+    pub achievements: Vec<Achievement>,
+    pub error: Option<RunError>,
+}
+
+pub fn parse_source(source: &str) -> Result<Vec<Statement>, RunError> {
+    let tokens = tokenize(source);
+    if tokens.is_empty() {
+        return Err(RunError {
+            kind: RunErrorKind::Parse,
+            message: "No valid tokens".to_string(),
+            pos: None,
+        });
+    }
+
+    let mut parser = Parser::new(tokens);
+    parser.parse_statements().map_err(|e| RunError {
+        kind: RunErrorKind::Parse,
+        message: e.to_string(),
+        pos: parser.error_pos(),
+    })
+}
+
+// everything the repl shows goes through its output so it can be captured
+macro_rules! say {
+    ($repl:expr) => {
+        $repl.say(format_args!(""))
+    };
+    ($repl:expr, $($arg:tt)*) => {
+        $repl.say(format_args!($($arg)*))
+    };
+}
+
 pub struct Repl {
     pub validator: ResourceValidator,
     pub env: HashMap<String, Expr>,
     pub quest_manager: QuestManager,
-    pub execution_context: ExecutionContext,
+    pub execution_context: ExecutionContext, // bounded history across runs
+    last_run: ExecutionContext,
+    pub metering: MeteringMode,
+    pub lint: bool, // lint each program before it runs
+    pub debugger: Debugger, // keeps breakpoints between debug runs
+    pub income: IncomeTracker,
+    pub achievements: AchievementTracker,
+    out: RefCell<Box<dyn Output>>,
+    // commands and a program's input() calls share it, so typed answers go to
+    // the program that asked rather than to the command prompt
+    input: RefCell<Box<dyn InputSource>>,
+    // where session state is persisted, nothing is saved when unset
+    state_dir: Option<PathBuf>,
 }
 
 impl Repl {
     pub fn new() -> Self {
         let coin_manager = CoinManager::new();
-        let validator = ResourceValidator::new(coin_manager);
+        let validator = ResourceValidator::new(coin_manager).with_natives(NativeRegistry::with_stdlib());
         let mut quest_manager = QuestManager::new();
         quest_manager.initialize_starter_quests();
+        // the first quest is the tutorial, so it starts accepted and tracked
+        if quest_manager.accept_quest("hello_world").is_ok() {
+            let _ = quest_manager.track_quest("hello_world");
+        }
 
         Self {
             validator,
             env: HashMap::new(),
             quest_manager,
-            execution_context: ExecutionContext::new(),
+            execution_context: ExecutionContext::with_retention(DEFAULT_RETENTION),
+            last_run: ExecutionContext::new(),
+            metering: MeteringMode::Static,
+            lint: false,
+            debugger: Debugger::new(),
+            income: IncomeTracker::default(),
+            achievements: AchievementTracker::default(),
+            out: RefCell::new(Box::new(StdoutOutput)),
+            input: RefCell::new(Box::new(StdinInput)),
+            state_dir: None,
+        }
+    }
+
+    pub fn with_output(mut self, out: Box<dyn Output>) -> Self {
+        self.out = RefCell::new(out);
+        self
+    }
+
+    pub fn with_input(mut self, input: Box<dyn InputSource>) -> Self {
+        self.input = RefCell::new(input);
+        self
+    }
+
+    fn say(&self, line: fmt::Arguments) {
+        let mut out = self.out.borrow_mut();
+        out.write_str(&line.to_string());
+        out.write_str("\n");
+    }
+
+    pub fn set_state_dir(&mut self, dir: PathBuf) -> io::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        self.income.load(&dir.join(INCOME_STATE_FILE))?;
+        self.achievements.load(&dir.join(ACHIEVEMENTS_STATE_FILE))?;
+
+        let quest_dir = dir.join(QUEST_PACK_DIR);
+        if quest_dir.is_dir()
+            && let Err(e) = self.load_quest_pack(&quest_dir)
+        {
+            say!(self, "Warning: skipped quest packs in {}: {}", quest_dir.display(), e);
+        }
+
+        self.state_dir = Some(dir);
+        Ok(())
+    }
+
+    // a single .toml/.json pack or a directory of them
+    pub fn load_quest_pack(&mut self, path: &Path) -> Result<usize, QuestPackError> {
+        let quests = quest_pack::load_path(path)?;
+        let count = quests.len();
+        self.quest_manager.load_quests(quests)?;
+        Ok(count)
+    }
+
+    fn save_state(&self) {
+        if let Some(dir) = &self.state_dir
+            && let Err(e) = self
+                .income
+                .save(&dir.join(INCOME_STATE_FILE))
+                .and_then(|_| self.achievements.save(&dir.join(ACHIEVEMENTS_STATE_FILE)))
+        {
+            say!(self, "Warning: could not save session state: {}", e);
+        }
+    }
+
+    pub fn claim_daily_bonus(&mut self) {
+        if let Some(record) = self.income.claim_daily_bonus(self.validator.coin_manager_mut()) {
+            self.display_income(&record);
+            self.save_state();
         }
     }
 
+    // the session loop over any line source, which is how tests drive it
+    pub fn run_with(&mut self, lines: impl BufRead + 'static) {
+        self.input = RefCell::new(Box::new(ReaderInput::new(lines)));
+        self.run();
+    }
+
     pub fn run(&mut self) {
+        self.claim_daily_bonus();
+
         loop {
-            print!("\nCAng> ");
-            io::stdout().flush().unwrap();
+            let prompt = format!("\n{}CAng> ", self.prompt_status());
+            let mut out = self.out.borrow_mut();
+            out.write_str(&prompt);
+            out.flush();
+            drop(out);
 
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-                Ok(_) => {
-                    let input = input.trim();
+            let line = self.input.get_mut().read_line();
+            match line {
+                None => break,
+                Some(line) => {
+                    let input = line.trim();
 
                     if input.is_empty() {
                         continue;
@@ -46,7 +242,7 @@ impl Repl {
 
                     match input {
                         "quit" | "exit" => {
-                            println!("Goodbye!");
+                            say!(self, "Goodbye!");
                             break;
                         }
                         "help" => self.show_help(),
@@ -56,160 +252,326 @@ impl Repl {
                         "progress" => self.show_detailed_quest_progress(),
                         "available" => self.show_available_quests(),
                         "completed" => self.show_completed_quests(),
-                        _ => self.execute(input),
+                        "shop" => self.show_shop(),
+                        "meter" => self.show_metering(),
+                        "income" => self.show_income(),
+                        "credit" => self.show_credit(),
+                        "achievements" => self.show_achievements(),
+                        "builtins" => self.show_builtins(),
+                        "modules" => self.show_modules(),
+                        "lint" => self.show_lint(),
+                        "breakpoints" => say!(self, "{}", self.debugger.describe_breakpoints()),
+                        // these only mean something at the (debug) prompt
+                        "step" | "next" | "out" | "continue" | "locals" | "stack" => {
+                            say!(self, "Nothing is being debugged. Start with debug <program>");
+                        }
+                        "challenge" => self.run_challenge_command(None),
+                        "cost" => say!(self, "Usage: cost <program>"),
+                        "questmap" => {
+                            self.show_quest_map("");
+                        }
+                        _ => match input.split_once(' ') {
+                            Some(("shop", args)) => self.handle_shop_command(args.trim()),
+                            Some(("meter", mode)) => self.set_metering(mode.trim()),
+                            Some(("lint", setting)) => self.set_lint(setting.trim()),
+                            Some(("debug", program)) => self.debug(program.trim()),
+                            Some(("break", line)) => self.add_breakpoint(line.trim()),
+                            Some(("delete", line)) => self.remove_breakpoint(line.trim()),
+                            Some(("credit", args)) => self.set_credit(args),
+                            Some(("progress", "reset")) => self.reset_progress(),
+                            Some(("quest", args)) => self.handle_quest_command(args.trim()),
+                            Some(("hint", quest_id)) => self.reveal_hint(quest_id.trim()),
+                            Some(("restart", quest_id)) => self.restart_quest(quest_id.trim()),
+                            Some(("challenge", quest_id)) => self.run_challenge_command(Some(quest_id.trim())),
+                            Some(("questmap", args)) => {
+                                self.show_quest_map(args);
+                            }
+                            Some(("cost", program)) => {
+                                self.show_cost_estimate(program);
+                            }
+                            _ => self.execute(input),
+                        },
                     }
                 }
-                Err(e) => {
-                    println!("Error reading input: {}", e);
-                    break;
-                }
             }
         }
     }
 
-    fn execute(&mut self, input: &str) {
-        let tokens = tokenize(input);
+    // runs a program and settles everything that follows from it (quests,
+    // income, interest, achievements). Only what the program prints reaches
+    // the output, the rest is left for the caller to show
+    pub fn run_source(&mut self, source: &str) -> RunOutcome {
+        self.run_statements(source, false)
+    }
 
-        if tokens.is_empty() {
-            println!("No valid tokens");
-            return;
-        }
+    // the same under the debugger, which takes its commands from the session
+    // input unless it was given a script
+    pub fn debug_source(&mut self, source: &str) -> RunOutcome {
+        self.run_statements(source, true)
+    }
+
+    fn run_statements(&mut self, source: &str, debug: bool) -> RunOutcome {
+        let mut outcome = RunOutcome::default();
 
-        let mut parser = Parser::new(tokens);
-        let ast = match parser.parse_program() {
-            Ok(ast) => ast,
+        let statements = match parse_source(source) {
+            Ok(statements) => statements,
             Err(e) => {
-                println!("Parse Error: {}", e);
-                return;
+                outcome.error = Some(e);
+                return outcome;
             }
         };
+        if debug {
+            // the spans give every statement a line, function bodies included
+            let mut parser = Parser::new(tokenize(source));
+            let _ = parser.parse_statements();
+            self.debugger.load(&statements, parser.statement_spans());
+        }
+        let positions: Vec<(usize, usize)> = statements.iter().map(|(_, pos)| *pos).collect();
+        if self.metering == MeteringMode::Static
+            && let Err((i, e)) = self.validator.check_program(&statements)
+        {
+            outcome.error = Some(RunError {
+                kind: RunErrorKind::of(&e),
+                message: e.to_string(),
+                pos: i.and_then(|i| positions.get(i).copied()),
+            });
+            return outcome;
+        }
+        if let Err((i, e)) = self.validator.link(&statements) {
+            outcome.error = Some(RunError {
+                kind: match e {
+                    ModuleError::Coins(_) => RunErrorKind::Coins,
+                    _ => RunErrorKind::Import,
+                },
+                message: e.to_string(),
+                pos: positions.get(i).copied(),
+            });
+            return outcome;
+        }
+        let ast = program(statements);
 
-        
-        self.track_expression_execution(&ast);
+        self.last_run = ExecutionContext::new();
 
-        match eval_with_validation(&ast, &mut self.validator, &mut self.env) {
-            Ok((res, output)) => {
-                
-                if !matches!(ast, Expr::Print(_)) {
-                    println!("Result: {}", res);
-                }
-                
-                
-                self.update_execution_context(&ast, res);
-                
-                
-                for output_line in output {
-                    self.execution_context.add_output(output_line);
-                }
-                
-                
-                let rewards = self.quest_manager.check_completion(&self.execution_context);
-                if !rewards.is_empty() {
-                    self.display_quest_completion_notification(&rewards);
+        let before = self.validator.coin_manager().get_all_balances().clone();
+        let run = run_program(
+            &ast,
+            &mut self.validator,
+            &mut self.env,
+            self.metering,
+            self.out.get_mut().as_mut(),
+            self.input.get_mut().as_mut(),
+            debug.then_some(&mut self.debugger),
+        );
+        outcome.charged = CoinType::ALL
+            .into_iter()
+            .filter_map(|coin_type| {
+                let spent = before[&coin_type] - self.validator.coin_manager().get_balance(coin_type);
+                (spent > 0).then_some(CoinCost {
+                    coin_type,
+                    amt: spent as u32,
+                })
+            })
+            .collect();
+        outcome.output = run.output;
+
+        let defined: Vec<String> = run
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                ExecutionEvent::FunctionDefined { name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+
+        match run.result {
+            Ok(value) => {
+                // only runs that finish count, a failed or rejected run leaves
+                // nothing behind for quests but its error
+                self.record_event(ExecutionEvent::ProgramStarted {
+                    source: source.to_string(),
+                });
+                for event in run.events {
+                    self.record_event(event);
                 }
-                
-                
-                self.show_quest_progress_summary();
+                // print and import have nothing worth showing as a result
+                outcome.value = (!matches!(ast, Expr::Print(_) | Expr::Import(..))).then_some(value);
+                outcome.challenges = self.run_challenges(None, Some(&defined));
+                (outcome.completed_quests, outcome.rewards) = self.complete_quests();
+                outcome.income = self.income.record_run(self.validator.coin_manager_mut());
+                outcome.interest = self.validator.coin_manager_mut().accrue_interest();
             }
             Err(e) => {
-                println!("Error: {}", e);
-                
-                let error_string = format!("{}", e);
-                if error_string.contains("Insufficient") {
-                    self.suggest_quests_for_coins(&error_string);
+                // running short of coins isn't a runtime error
+                if let ValidationError::RuntimeError(_) = e {
+                    self.record_event(ExecutionEvent::RuntimeError {
+                        message: e.to_string(),
+                    });
                 }
+                outcome.error = Some(RunError {
+                    kind: RunErrorKind::of(&e),
+                    message: e.to_string(),
+                    pos: run.statement.and_then(|i| positions.get(i).copied()),
+                });
             }
         }
+
+        // a rejected run neither counts as a run nor breaks a recovery
+        if outcome.error.as_ref().is_none_or(|e| e.kind == RunErrorKind::Runtime) {
+            outcome.achievements = self
+                .achievements
+                .record_run(&self.last_run.events, self.validator.coin_manager())
+                .into_iter()
+                .cloned()
+                .collect();
+        }
+        self.save_state();
+        outcome
     }
 
-    fn track_expression_execution(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Number(_) => {
-                self.execution_context.record_expression("Number".to_string());
-            }
-            Expr::String(_) => {
-                self.execution_context.record_expression("String".to_string());
-            }
-            Expr::Binary(_, _op, _) => {
-                self.execution_context.record_expression("Binary".to_string());
-                self.execution_context.record_expression("arithmetic".to_string());
-            }
-            Expr::Let(name, _) => {
-                self.execution_context.record_expression(format!("Let({})", name));
-            }
-            Expr::FnDef(name, params, _) => {
-                self.execution_context.record_expression(format!("FnDef({}, {} params)", name, params.len()));
-            }
-            Expr::FnCall(name, args) => {
-                self.execution_context.record_expression(format!("FnCall({}, {} args)", name, args.len()));
-            }
-            Expr::Var(name) => {
-                self.execution_context.record_expression(format!("Var({})", name));
-            }
-            Expr::Print(inner_expr) => {
-                self.execution_context.record_expression("Print".to_string());
-                self.track_expression_execution(inner_expr);
-            }
-            Expr::Block(statements) => {
-                self.execution_context.record_expression("Block".to_string());
-                for stmt in statements {
-                    self.track_expression_execution(stmt);
-                }
+    fn execute(&mut self, input: &str) {
+        if self.lint {
+            // parse errors are left for the run to report
+            let linter = Linter::new(self.validator.natives()).with_globals(&self.env);
+            for diagnostic in linter.lint_source(input).unwrap_or_default() {
+                say!(self, "⚠️  {}", diagnostic);
             }
         }
+        // the program's own output was shown as it ran
+        let outcome = self.run_source(input);
+        self.show_outcome(&outcome);
+    }
+
+    // steps through a program, with the debugger reading its commands from
+    // the same input as the session
+    fn debug(&mut self, input: &str) {
+        say!(self, "🐞 Debugging. Type help at the (debug) prompt for commands.");
+        let outcome = self.debug_source(input);
+        if outcome.error.is_none() {
+            say!(self, "Program finished");
+        }
+        self.show_outcome(&outcome);
+    }
+
+    fn add_breakpoint(&mut self, line: &str) {
+        match line.parse() {
+            Ok(line) if self.debugger.add_breakpoint(line) => say!(self, "Breakpoint set at line {}", line),
+            Ok(line) => say!(self, "There's already a breakpoint at line {}", line),
+            Err(_) => say!(self, "Usage: break <line>"),
+        }
     }
 
-    fn update_execution_context(&mut self, expr: &Expr, result: i64) {
-        match expr {
-            Expr::Let(name, _) => {
-                self.execution_context.add_variable(name.clone(), result);
+    fn remove_breakpoint(&mut self, line: &str) {
+        match line.parse() {
+            Ok(line) if self.debugger.remove_breakpoint(line) => say!(self, "Breakpoint at line {} removed", line),
+            Ok(line) => say!(self, "There's no breakpoint at line {}", line),
+            Err(_) => say!(self, "Usage: delete <line>"),
+        }
+    }
+
+    fn show_outcome(&mut self, outcome: &RunOutcome) {
+        if let Some(e) = &outcome.error {
+            match e.kind {
+                RunErrorKind::Parse => say!(self, "Parse Error: {}", e.message),
+                _ => say!(self, "Error: {}", e.message),
             }
-            Expr::FnDef(name, params, body) => {
-                
-                let body_str = format!("{:?}", body);
-                self.execution_context.add_function(name.clone(), params.clone(), body_str);
-            }
-            Expr::Block(statements) => {
-                for stmt in statements {
-                    
-                    match stmt {
-                        Expr::Let(name, _) => {
-                            
-                            if let Some(Expr::Number(val)) = self.env.get(name) {
-                                self.execution_context.add_variable(name.clone(), *val);
-                            }
-                        }
-                        Expr::FnDef(name, params, body) => {
-                            let body_str = format!("{:?}", body);
-                            self.execution_context.add_function(name.clone(), params.clone(), body_str);
-                        }
-                        _ => {}
-                    }
-                }
+            self.display_achievements(&outcome.achievements);
+            if e.message.contains("Insufficient") || e.message.contains("Credit limit") {
+                self.suggest_quests_for_coins(&e.message);
             }
-            _ => {}
+            return;
+        }
+
+        if let Some(value) = outcome.value {
+            say!(self, "Result: {}", value);
+        }
+        for (title, report) in &outcome.challenges {
+            self.display_challenge_report(title, report);
+        }
+        if !outcome.completed_quests.is_empty() {
+            self.display_quest_completion_notification(&outcome.rewards);
+        }
+        if let Some(record) = &outcome.income {
+            self.display_income(record);
+        }
+        for (coin_type, interest) in &outcome.interest {
+            say!(self, "💳 Interest: your {:?} coin debt grew by {}", coin_type, interest);
+        }
+        self.display_achievements(&outcome.achievements);
+
+        // a tracked quest is already in the prompt
+        if self.quest_manager.tracked_quest().is_none() {
+            self.show_quest_progress_summary();
         }
     }
 
+    // checks accepted quests against the latest run and pays out, returns
+    // the ids completed and the coins credited (reward bonus included)
+    fn complete_quests(&mut self) -> (Vec<String>, Vec<CoinReward>) {
+        let completions = |quest_manager: &QuestManager| -> HashMap<String, u32> {
+            quest_manager
+                .get_active_quests()
+                .iter()
+                .chain(quest_manager.get_completed_quests())
+                .map(|quest| (quest.id.clone(), quest.times_completed))
+                .collect()
+        };
+
+        let before = completions(&self.quest_manager);
+        let rewards = self
+            .quest_manager
+            .check_run_completion(&self.last_run, &self.execution_context);
+        let mut completed: Vec<String> = completions(&self.quest_manager)
+            .into_iter()
+            .filter(|(id, times)| before.get(id).is_none_or(|was| times > was))
+            .map(|(id, _)| id)
+            .collect();
+        completed.sort();
+
+        let credited = self.validator.coin_manager_mut().apply_rewards(&rewards);
+        (completed, credited)
+    }
+
     fn show_help(&self) {
-        println!("\nAvailable commands:");
-        println!("  help       - Show this help message");
-        println!("  status     - Show current status and coin balances");
-        println!("  balance    - Show coin balances");
-        println!("  coins      - Show coin balances");
-        println!("  quests     - Show all quests (active and completed)");
-        println!("  available  - Show only available quests");
-        println!("  completed  - Show only completed quests");
-        println!("  progress   - Show detailed progress on all active quests");
-        println!("  quit       - Exit the REPL");
-        println!("\nYou can also enter expressions to evaluate:");
-        println!("  Examples: 1 + 2 * 3");
-        println!("           let x = 10 + 5");
-        println!("           fn add(a, b) {{ a + b }}");
+        say!(self, "\nAvailable commands:");
+        say!(self, "  help       - Show this help message");
+        say!(self, "  status     - Show current status and coin balances");
+        say!(self, "  balance    - Show coin balances");
+        say!(self, "  coins      - Show coin balances");
+        say!(self, "  quests     - Show all quests (active and completed)");
+        say!(self, "  quest accept|abandon|track <id> - Take on, drop or focus a quest");
+        say!(self, "  quest untrack - Stop showing a quest in the prompt");
+        say!(self, "  hint <id>  - Buy the next hint for a quest");
+        say!(self, "  available  - Show only available quests");
+        say!(self, "  completed  - Show only completed quests");
+        say!(self, "  questmap [ascii|dot|mermaid] [<file>] - Draw or export the quest prerequisite graph");
+        say!(self, "  progress   - Show detailed progress on your accepted quests");
+        say!(self, "  progress reset - Clear the history that quest progress is counted from");
+        say!(self, "  cost <program> - Show what a program would cost without running it");
+        say!(self, "  meter [static|runtime] - Show or switch how coins are charged");
+        say!(self, "  lint [on|off] - Show or switch linting programs before they run");
+        say!(self, "  debug <program> - Run a program in the step debugger");
+        say!(self, "  break|delete <line> - Set or remove a breakpoint for debug runs");
+        say!(self, "  breakpoints - List breakpoints");
+        say!(self, "  achievements - Show unlocked and upcoming achievements");
+        say!(self, "  builtins   - List built-in functions and what they cost");
+        say!(self, "  modules    - List imported modules and what loading them cost");
+        say!(self, "  income     - Show stipends, daily bonus streak and income history");
+        say!(self, "  credit [<type> <limit>] - Show or open a coin credit line");
+        say!(self, "  challenge [<quest>] - Run hidden tests for challenge quests (free)");
+        say!(self, "  restart <quest> - Start a timed quest's clock again");
+        say!(self, "  shop       - Show upgrades and exchange rates");
+        say!(self, "  shop buy <upgrade>              - Buy an upgrade");
+        say!(self, "  shop exchange <amt> <from> <to> - Trade coins, e.g. shop exchange 3 variable function");
+        say!(self, "  shop limit <on|off>             - Cap function params (extra_param raises the cap)");
+        say!(self, "  quit       - Exit the REPL");
+        say!(self, "\nYou can also enter expressions to evaluate:");
+        say!(self, "  Examples: 1 + 2 * 3");
+        say!(self, "           let x = 10 + 5");
+        say!(self, "           fn add(a, b) {{ a + b }}");
     }
 
     pub fn display_status(&self) {
-        println!("\n🎮 CAng Interpreter Status");
+        say!(self, "\n🎮 CAng Interpreter Status");
         
         
         self.show_coinbal();
@@ -219,157 +581,636 @@ impl Repl {
         let locked_quests = self.quest_manager.get_locked_quests();
         let completed_quests = self.quest_manager.get_completed_quests();
         
-        println!("\n📊 Quest Progress:");
-        println!("  Available: {} | Locked: {} | Completed: {}", 
+        say!(self, "\n📊 Quest Progress:");
+        say!(self, "  Available: {} | Locked: {} | Completed: {}", 
                  available_quests.len(), locked_quests.len(), completed_quests.len());
         
         
-        if !available_quests.is_empty() {
-            println!("\n🎯 Current Quest Progress:");
-            for quest in available_quests.iter().take(3) {
-                if let Some(progress) = self.quest_manager.get_quest_progress(&quest.id, &self.execution_context) {
+        let accepted_quests = self.quest_manager.get_accepted_quests();
+        if !accepted_quests.is_empty() {
+            say!(self, "\n🎯 Current Quest Progress:");
+            for quest in accepted_quests.iter().take(3) {
+                if let Some(progress) = self.quest_progress(&quest.id) {
                     let percentage = progress.completion_percentage();
                     let progress_bar = self.create_progress_bar(percentage);
-                    println!("  {} {} ({:.0}%)", progress_bar, quest.title, percentage);
+                    say!(self, "  {} {} ({:.0}%)", progress_bar, quest.title, percentage);
                 }
             }
         }
         
         
-        println!("\n📈 Session Statistics:");
-        println!("  Variables created: {}", self.execution_context.variables.len());
-        println!("  Functions defined: {}", self.execution_context.functions.len());
-        println!("  Expressions executed: {}", self.execution_context.executed_expressions.len());
+        say!(self, "\n📈 Session Statistics:");
+        say!(self, "  Variables created: {}", self.execution_context.variables.len());
+        say!(self, "  Functions defined: {}", self.execution_context.functions.len());
+        say!(self, "  Programs run: {}", self.execution_context.programs_run());
         
         
-        if let Some(next_quest) = available_quests.first() {
-            if let Some(progress) = self.quest_manager.get_quest_progress(&next_quest.id, &self.execution_context) {
-                if progress.completion_percentage() == 0.0 {
-                    println!("\n💡 Suggested next action:");
-                    println!("  Try working on: {} - {}", next_quest.title, next_quest.description);
-                }
-            }
+        if let Some(next_quest) = available_quests.first()
+            && let Some(progress) = self.quest_progress(&next_quest.id)
+            && progress.completion_percentage() == 0.0
+        {
+            say!(self, "\n💡 Suggested next action:");
+            say!(self, "  Try working on: {} - {}", next_quest.title, next_quest.description);
         }
         
-        println!("\n✅ Status: Ready for input");
+        say!(self, "\n✅ Status: Ready for input");
     }
 
     fn show_quests(&self) {
-        println!("\n🎯 Quest Overview");
+        say!(self, "\n🎯 Quest Overview");
         
         
         let available_quests = self.quest_manager.get_available_quests();
         let locked_quests = self.quest_manager.get_locked_quests();
         let completed_quests = self.quest_manager.get_completed_quests();
         
-        println!("📊 Quest Statistics:");
-        println!("  Available: {} | Locked: {} | Completed: {}", 
+        say!(self, "📊 Quest Statistics:");
+        say!(self, "  Available: {} | Locked: {} | Completed: {}", 
                  available_quests.len(), locked_quests.len(), completed_quests.len());
         
         
         if !available_quests.is_empty() {
-            println!("\n🎯 Available Quests:");
+            say!(self, "\n🎯 Available Quests:");
             for quest in available_quests.iter().take(5) { 
-                if let Some(progress) = self.quest_manager.get_quest_progress(&quest.id, &self.execution_context) {
+                if let Some(progress) = self.quest_progress(&quest.id) {
                     let percentage = progress.completion_percentage();
                     let progress_bar = self.create_progress_bar(percentage);
-                    println!("  {} {} [{}] ({:.0}%)", progress_bar, quest.title, quest.difficulty.description(), percentage);
+                    say!(self, "  {} {} [{}] ({:.0}%)", progress_bar, quest.title, quest.difficulty.description(), percentage);
                 } else {
-                    println!("  [░░░░░░░░░░] {} [{}] (0%)", quest.title, quest.difficulty.description());
+                    say!(self, "  [░░░░░░░░░░] {} [{}] (0%)", quest.title, quest.difficulty.description());
                 }
             }
             
             if available_quests.len() > 5 {
-                println!("  ... and {} more (use 'available' to see all)", available_quests.len() - 5);
+                say!(self, "  ... and {} more (use 'available' to see all)", available_quests.len() - 5);
             }
         }
         
         
         if !completed_quests.is_empty() {
-            println!("\n🏆 Recently Completed:");
+            say!(self, "\n🏆 Recently Completed:");
             for quest in completed_quests.iter().rev().take(3) { 
-                println!("  ✅ {} [{}]", quest.title, quest.difficulty.description());
+                say!(self, "  ✅ {} [{}]", quest.title, quest.difficulty.description());
             }
             
             if completed_quests.len() > 3 {
-                println!("  ... and {} more (use 'completed' to see all)", completed_quests.len() - 3);
+                say!(self, "  ... and {} more (use 'completed' to see all)", completed_quests.len() - 3);
             }
         }
         
         
         if !locked_quests.is_empty() {
-            println!("\n🔒 {} quests are locked. Complete prerequisites to unlock them!", locked_quests.len());
+            say!(self, "\n🔒 {} quests are locked. Complete prerequisites to unlock them!", locked_quests.len());
+        }
+
+        for quest in self.quest_manager.get_expired_quests() {
+            say!(self, "⌛ Time ran out on {}. Use 'restart {}' to try again.", quest.title, quest.id);
         }
         
-        println!("\nUse 'available', 'completed', or 'progress' for detailed views.");
+        say!(self, "\nUse 'available', 'completed', or 'progress' for detailed views.");
     }
 
     pub fn show_coinbal(&self) {
-        let bal = self.validator.coin_manager().get_all_balances();
-        println!("💰 Coin Balances:");
+        let coin_manager = self.validator.coin_manager();
+        let bal = coin_manager.get_all_balances();
+        say!(self, "💰 Coin Balances:");
         for (coint_type, amt) in bal {
             let coin_name = match coint_type {
                 crate::CoinType::Variable => "Variable",
                 crate::CoinType::Function => "Function",
             };
-            println!("  {} coins: {}", coin_name, amt);
+            let limit = coin_manager.credit_limit(*coint_type);
+            if *amt < 0 {
+                say!(self, 
+                    "  {} coins: {} (💳 {} in debt, credit limit {})",
+                    coin_name,
+                    amt,
+                    coin_manager.debt(*coint_type),
+                    limit
+                );
+            } else if limit > 0 {
+                say!(self, "  {} coins: {} (💳 credit limit {})", coin_name, amt, limit);
+            } else {
+                say!(self, "  {} coins: {}", coin_name, amt);
+            }
         }
     }
 
-    fn display_quest_completion_notification(&mut self, rewards: &[crate::CoinReward]) {
-        println!("\n🎉 QUEST COMPLETED! 🎉");
-        println!("Congratulations! You've completed one or more quests!");
+    fn show_credit(&self) {
+        let coin_manager = self.validator.coin_manager();
+        say!(self, "\n💳 Credit");
+        for coin_type in CoinType::ALL {
+            say!(self, 
+                "  {:?}: limit {}, debt {}",
+                coin_type,
+                coin_manager.credit_limit(coin_type),
+                coin_manager.debt(coin_type)
+            );
+        }
+        say!(self, 
+            "  Debt grows by {}% every execution. Rewards pay debt off first.",
+            coin_manager.interest_percent()
+        );
+        say!(self, "Use 'credit <variable|function> <limit>' to open a credit line (0 closes it).");
+    }
+
+    fn set_credit(&mut self, args: &str) {
+        let parts: Vec<&str> = args.split_whitespace().collect();
+        let (Some(coin_type), Ok(limit)) = (
+            parts.first().and_then(|name| CoinType::from_name(name)),
+            parts.get(1).copied().unwrap_or("").parse::<u32>(),
+        ) else {
+            say!(self, "Usage: credit <variable|function> <limit>");
+            return;
+        };
+        if limit > MAX_CREDIT_LIMIT {
+            say!(self, "Credit lines are capped at {} coins.", MAX_CREDIT_LIMIT);
+            return;
+        }
+
+        self.validator.coin_manager_mut().set_credit_limit(coin_type, limit);
+        self.show_credit();
+    }
+
+    // returns whether the program parsed and is affordable
+    pub fn show_cost_estimate(&self, source: &str) -> bool {
+        let statements = match parse_source(source) {
+            Ok(statements) => statements,
+            Err(e) => {
+                say!(self, "Parse Error: {}", e.message);
+                return false;
+            }
+        };
+
+        let estimate = self.validator.estimate(&statements);
+
+        say!(self, "\n🧮 Cost Estimate (nothing was run or charged)");
+        say!(self, "Total cost:");
+        if estimate.total.is_empty() {
+            say!(self, "  Free!");
+        }
+        for cost in &estimate.total {
+            say!(self, "  {:?} coins: {}", cost.coin_type, cost.amt);
+        }
+
+        say!(self, "Per statement:");
+        for (stmt, cost) in statements.iter().map(|(stmt, _)| stmt).zip(&estimate.statements) {
+            let (line, col) = cost.pos;
+            let breakdown = if cost.costs.is_empty() {
+                "free".to_string()
+            } else {
+                cost.costs
+                    .iter()
+                    .map(|c| format!("{} {:?}", c.amt, c.coin_type))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            say!(self, "  {}:{} {} - {}", line, col, Self::describe_statement(stmt), breakdown);
+        }
+
+        say!(self, "Balance after running:");
+        for coin_type in CoinType::ALL {
+            say!(self, "  {:?} coins: {}", coin_type, estimate.balance_after[&coin_type]);
+        }
+
+        if let Some(e) = &estimate.limit_error {
+            say!(self, "❌ This program can't run yet: {}", e);
+        } else if estimate.affordable {
+            say!(self, "✅ You can afford this program.");
+        } else {
+            say!(self, "❌ You can't afford this program yet.");
+        }
+
+        estimate.affordable
+    }
+
+    fn show_metering(&self) {
+        match self.metering {
+            MeteringMode::Static => {
+                say!(self, "⚖️  Static metering: programs are charged up front from their source.")
+            }
+            MeteringMode::Runtime => {
+                say!(self, "⏱️  Runtime metering: coins are charged as code runs, e.g. per function call.")
+            }
+        }
+        say!(self, "Use 'cost <program>' for a pre-flight estimate either way.");
+    }
+
+    fn set_metering(&mut self, mode: &str) {
+        self.metering = match mode {
+            "static" => MeteringMode::Static,
+            "runtime" => MeteringMode::Runtime,
+            _ => {
+                say!(self, "Usage: meter [static|runtime]");
+                return;
+            }
+        };
+        self.show_metering();
+    }
+
+    fn show_lint(&self) {
+        if self.lint {
+            say!(self, "🔍 Linting is on: warnings about wasted coins are shown before each program runs.");
+        } else {
+            say!(self, "🔍 Linting is off. Use 'lint on' to check programs before they run.");
+        }
+    }
+
+    fn set_lint(&mut self, setting: &str) {
+        self.lint = match setting {
+            "on" => true,
+            "off" => false,
+            _ => {
+                say!(self, "Usage: lint [on|off]");
+                return;
+            }
+        };
+        self.show_lint();
+    }
+
+    fn describe_statement(stmt: &Expr) -> String {
+        match stmt {
+            Expr::Let(name, _) => format!("let {}", name),
+            Expr::FnDef(name, params, _) => format!("fn {}({})", name, params.join(", ")),
+            Expr::FnCall(name, _) => format!("call {}", name),
+            Expr::Print(_) => "print".to_string(),
+            _ => "expression".to_string(),
+        }
+    }
+
+    fn display_income(&self, record: &IncomeRecord) {
+        let rewards = record
+            .rewards
+            .iter()
+            .map(|r| format!("{} {:?}", r.amount, r.coin_type))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match record.source {
+            IncomeSource::Stipend { runs } => {
+                say!(self, "\n💸 Stipend for {} successful runs: {} coins", runs, rewards)
+            }
+            IncomeSource::DailyBonus { streak } => {
+                say!(self, "\n📅 Daily bonus (streak: {} days): {} coins", streak, rewards)
+            }
+        }
+    }
+
+    fn show_income(&self) {
+        say!(self, "\n💸 Income");
+        say!(self, "  Successful runs: {}", self.income.successful_runs());
+        match self.income.runs_until_stipend() {
+            Some(runs) => say!(self, "  Next stipend in {} runs", runs),
+            None => say!(self, "  Stipends are turned off"),
+        }
+        say!(self, 
+            "  Daily streak: {} days (bonus multiplier capped at x{})",
+            self.income.streak(),
+            self.income.config().max_streak_multiplier
+        );
+
+        let history = self.income.history();
+        if !history.is_empty() {
+            say!(self, "  Recent payouts:");
+            for record in history.iter().rev().take(5) {
+                let total: u32 = record.rewards.iter().map(|r| r.amount).sum();
+                let source = match record.source {
+                    IncomeSource::Stipend { .. } => "stipend",
+                    IncomeSource::DailyBonus { .. } => "daily bonus",
+                };
+                say!(self, "    {} coins from {} at {}", total, source, record.timestamp);
+            }
+        }
+    }
+
+    fn display_achievements(&self, unlocked: &[Achievement]) {
+        for achievement in unlocked {
+            say!(self, 
+                "\n🏆 Achievement unlocked: {} [{}] - {}",
+                achievement.title,
+                achievement.rarity.description(),
+                achievement.description
+            );
+        }
+    }
+
+    fn show_achievements(&self) {
+        let achievements = self.achievements.achievements();
+        let (unlocked, locked): (Vec<_>, Vec<_>) = achievements
+            .iter()
+            .partition(|a| self.achievements.unlocked_at(&a.id).is_some());
+
+        say!(self, "\n🏆 Achievements ({}/{})", unlocked.len(), achievements.len());
+        for achievement in unlocked {
+            let timestamp = self.achievements.unlocked_at(&achievement.id).unwrap_or_default();
+            say!(self, 
+                "  ✅ {} [{}] - {} (unlocked {})",
+                achievement.title,
+                achievement.rarity.description(),
+                achievement.description,
+                format_date(timestamp)
+            );
+        }
+        for achievement in locked {
+            let (current, target) = self.achievements.progress(achievement);
+            say!(self, 
+                "  🔒 {} [{}] - {} ({}/{})",
+                achievement.title,
+                achievement.rarity.description(),
+                achievement.description,
+                current,
+                target
+            );
+        }
+    }
+
+    fn show_builtins(&self) {
+        let natives = self.validator.natives();
+
+        say!(self, "\n🧰 Built-in functions (can't be redefined)");
+        for name in natives.names() {
+            let Some(native) = natives.get(name) else { continue };
+            let cost = if native.cost.is_empty() {
+                "free".to_string()
+            } else {
+                native
+                    .cost
+                    .iter()
+                    .map(|cost| format!("{} {:?}", cost.amt, cost.coin_type))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            say!(self, "  {:<6} - {}, {}", name, native.arity.description(), cost);
+        }
+        for name in INPUT_FUNCTIONS {
+            say!(self, "  {:<9} - {}, optional prompt, free", name, input_summary(name));
+        }
+    }
+
+    fn show_modules(&self) {
+        let modules = self.validator.modules();
+        if modules.loaded().is_empty() {
+            say!(self, "\n📦 No modules imported yet. Try: import \"lib/math.cang\" as m");
+            return;
+        }
+
+        say!(self, "\n📦 Modules (paid for once, when first imported)");
+        for module in modules.loaded() {
+            let cost: Vec<String> = module
+                .cost
+                .iter()
+                .map(|cost| format!("{} {:?}", cost.amt, cost.coin_type))
+                .collect();
+            say!(self, "  {} - {}", module.name, module.path.display());
+            say!(
+                self,
+                "    defines: {} | cost: {}",
+                module.names().join(", "),
+                if cost.is_empty() { "free".to_string() } else { cost.join(", ") }
+            );
+        }
+    }
+
+    fn show_shop(&self) {
+        let coin_manager = self.validator.coin_manager();
+
+        say!(self, "\n🛒 Coin Shop");
+        say!(self, "Upgrades:");
+        for item in coin_manager.shop_items() {
+            say!(self, 
+                "  {} - {} ({} {:?} coins, owned: {})",
+                item.upgrade.name(),
+                item.upgrade.description(),
+                item.price,
+                item.coin_type,
+                coin_manager.upgrade_level(item.upgrade)
+            );
+        }
+
+        say!(self, "Exchange rates:");
+        let mut rates: Vec<_> = coin_manager.exchange_rates().iter().collect();
+        rates.sort_by_key(|((from, to), _)| (format!("{:?}", from), format!("{:?}", to)));
+        for ((from, to), rate) in rates {
+            say!(self, "  {} {:?} -> {} {:?}", rate.give, from, rate.receive, to);
+        }
+
+        match coin_manager.param_limit() {
+            Some(limit) => say!(self, "Function param limit: {}", limit),
+            None => say!(self, "Function params: unlimited ('shop limit on' to play with a limit)"),
+        }
+        say!(self, "\nUse 'shop buy <upgrade>' or 'shop exchange <amt> <from> <to>'.");
+    }
+
+    fn handle_shop_command(&mut self, args: &str) {
+        let parts: Vec<&str> = args.split_whitespace().collect();
+        match parts.as_slice() {
+            ["buy", name] => {
+                let Some(upgrade) = Upgrade::from_name(name) else {
+                    say!(self, "Unknown upgrade '{}'. Type 'shop' to see what's for sale.", name);
+                    return;
+                };
+                match self.validator.coin_manager_mut().buy_upgrade(upgrade) {
+                    Ok(()) => {
+                        say!(self, "✅ Bought {} ({})", upgrade.name(), upgrade.description());
+                        self.show_coinbal();
+                    }
+                    Err(e) => say!(self, "Error: {}", e),
+                }
+            }
+            ["exchange", amt, from, to] => {
+                let (Ok(amt), Some(from), Some(to)) = (
+                    amt.parse::<u32>(),
+                    CoinType::from_name(from),
+                    CoinType::from_name(to),
+                ) else {
+                    say!(self, "Usage: shop exchange <amt> <variable|function> <variable|function>");
+                    return;
+                };
+                match self.validator.coin_manager_mut().exchange(from, to, amt) {
+                    Ok(received) => {
+                        say!(self, "✅ Received {} {:?} coins", received, to);
+                        self.show_coinbal();
+                    }
+                    Err(e) => say!(self, "Error: {}", e),
+                }
+            }
+            ["limit", setting @ ("on" | "off")] => {
+                self.validator.coin_manager_mut().set_param_limit(*setting == "on");
+                self.show_shop();
+            }
+            _ => say!(self, "Usage: shop | shop buy <upgrade> | shop exchange <amt> <from> <to> | shop limit <on|off>"),
+        }
+    }
+
+    // rewards have already been credited by the time this is shown
+    fn display_quest_completion_notification(&self, rewards: &[CoinReward]) {
+        say!(self, "\n🎉 QUEST COMPLETED! 🎉");
+        say!(self, "Congratulations! You've completed one or more quests!");
         
         let mut total_variable_coins = 0;
         let mut total_function_coins = 0;
         
         for reward in rewards {
             match reward.coin_type {
-                crate::CoinType::Variable => total_variable_coins += reward.amount,
-                crate::CoinType::Function => total_function_coins += reward.amount,
+                CoinType::Variable => total_variable_coins += reward.amount,
+                CoinType::Function => total_function_coins += reward.amount,
             }
-            self.validator.coin_manager_mut().add_coins(reward.amount, reward.coin_type);
         }
         
-        println!("Rewards earned:");
+        say!(self, "Rewards earned:");
         if total_variable_coins > 0 {
-            println!("  💎 {} Variable coins", total_variable_coins);
+            say!(self, "  💎 {} Variable coins", total_variable_coins);
         }
         if total_function_coins > 0 {
-            println!("  🔧 {} Function coins", total_function_coins);
+            say!(self, "  🔧 {} Function coins", total_function_coins);
         }
         
         
         let available_quests = self.quest_manager.get_available_quests();
         let newly_unlocked: Vec<_> = available_quests.iter()
-            .filter(|q| !q.prerequisites.is_empty())
+            .filter(|q| !q.prerequisites.is_empty() && !q.is_accepted())
             .collect();
             
         if !newly_unlocked.is_empty() {
-            println!("\n🔓 New quests unlocked:");
+            say!(self, "\n🔓 New quests unlocked:");
             for quest in newly_unlocked {
-                println!("  📋 {} - {}", quest.title, quest.description);
+                say!(self, "  📋 {} ({}) - {}", quest.title, quest.id, quest.description);
             }
+            say!(self, "Take one on with 'quest accept <id>'.");
         }
     }
 
     fn show_quest_progress_summary(&self) {
-        let available_quests = self.quest_manager.get_available_quests();
-        if available_quests.is_empty() {
+        let accepted_quests = self.quest_manager.get_accepted_quests();
+        if accepted_quests.is_empty() {
             return;
         }
 
-        println!("\n📊 Quest Progress Summary:");
-        for quest in available_quests.iter().take(3) { 
-            if let Some(progress) = self.quest_manager.get_quest_progress(&quest.id, &self.execution_context) {
+        say!(self, "\n📊 Quest Progress Summary:");
+        for quest in accepted_quests.iter().take(3) { 
+            if let Some(progress) = self.quest_progress(&quest.id) {
                 let percentage = progress.completion_percentage();
                 let progress_bar = self.create_progress_bar(percentage);
-                println!("  {} {} ({:.0}%)", progress_bar, quest.title, percentage);
+                say!(self, "  {} {} ({:.0}%)", progress_bar, quest.title, percentage);
             }
         }
         
-        if available_quests.len() > 3 {
-            println!("  ... and {} more quests (use 'quests' to see all)", available_quests.len() - 3);
+        if accepted_quests.len() > 3 {
+            say!(self, "  ... and {} more quests (use 'progress' to see all)", accepted_quests.len() - 3);
+        }
+    }
+
+    fn handle_quest_command(&mut self, args: &str) {
+        let result = match args.split_once(' ').map(|(action, id)| (action, id.trim())) {
+            Some(("accept", id)) => self.quest_manager.accept_quest(id).map(|_| {
+                say!(self, "📋 Accepted '{}'. Progress counts from now on.", id);
+            }),
+            Some(("abandon", id)) => self.quest_manager.abandon_quest(id).map(|_| {
+                say!(self, "🏳️  Abandoned '{}'. You can accept it again any time.", id);
+            }),
+            Some(("track", id)) => self.quest_manager.track_quest(id).map(|_| {
+                say!(self, "📌 Tracking '{}' in the prompt.", id);
+            }),
+            _ if args == "untrack" => {
+                self.quest_manager.untrack_quest();
+                say!(self, "📌 No longer tracking a quest.");
+                Ok(())
+            }
+            _ => {
+                say!(self, "Usage: quest accept <id> | quest abandon <id> | quest track <id> | quest untrack");
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            say!(self, "{}", e);
+        }
+    }
+
+    fn reveal_hint(&mut self, quest_id: &str) {
+        match self
+            .quest_manager
+            .reveal_hint(quest_id, self.validator.coin_manager_mut())
+        {
+            Ok((number, hint)) => {
+                if hint.solution {
+                    say!(self, "📖 {}", hint.text);
+                    say!(self, "   Rewards for this quest are halved now that you've seen the solution.");
+                } else {
+                    say!(self, "💡 Hint {}: {}", number, hint.text);
+                }
+                self.save_state();
+            }
+            Err(e) => say!(self, "{}", e),
+        }
+    }
+
+    // `questmap [ascii|dot|mermaid] [<file>]`, returns false on a bad format
+    // or a failed write
+    pub fn show_quest_map(&self, args: &str) -> bool {
+        let mut args = args.split_whitespace();
+        let format = args.next().unwrap_or("ascii");
+        let graph = QuestGraph::from_manager(&self.quest_manager);
+
+        // warnings go in as comments so exports stay valid
+        let warnings = graph.warnings();
+        let (mut rendered, comment) = match format {
+            "ascii" => (
+                format!("🗺️  Quest Map  (✅ completed, 🎯 available, 🔒 locked)\n\n{}", graph.render_ascii()),
+                "⚠️ ",
+            ),
+            "dot" => (graph.to_dot(), "//"),
+            "mermaid" => (graph.to_mermaid(), "%%"),
+            _ => {
+                say!(self, "Unknown quest map format '{}'. Use ascii, dot or mermaid.", format);
+                return false;
+            }
+        };
+        for warning in warnings {
+            rendered.push_str(&format!("{} {}\n", comment, warning));
+        }
+
+        match args.next() {
+            Some(path) => {
+                if let Err(e) = fs::write(path, &rendered) {
+                    say!(self, "Error writing {}: {}", path, e);
+                    return false;
+                }
+                say!(self, "Quest map written to {}", path);
+            }
+            None => self.out.borrow_mut().write_str(&rendered),
+        }
+        true
+    }
+
+    // compact progress for the tracked quest, shown before the prompt
+    fn prompt_status(&self) -> String {
+        self.quest_manager
+            .tracked_quest()
+            .and_then(|quest| {
+                let progress = self.quest_progress(&quest.id)?;
+                Some(format!("[{} {:.0}%] ", quest.title, progress.completion_percentage()))
+            })
+            .unwrap_or_default()
+    }
+
+    // extra line for quests that aren't one-offs
+    fn quest_timing(&self, quest: &Quest) -> Option<String> {
+        match quest.kind {
+            QuestKind::Once => None,
+            QuestKind::Repeatable { .. } => Some(format!("🔁 Repeatable, done {} times", quest.times_completed)),
+            QuestKind::Daily => Some("📅 Today's daily quest".to_string()),
+            QuestKind::Timed { .. } => self
+                .quest_manager
+                .time_left(quest)
+                .map(|secs| format!("⏱  {} left", format_duration(secs))),
+        }
+    }
+
+    fn restart_quest(&mut self, quest_id: &str) {
+        if self.quest_manager.restart_quest(quest_id) {
+            say!(self, "⏱  The clock on '{}' has started again. Good luck!", quest_id);
+        } else {
+            say!(self, "'{}' isn't a timed quest you can restart.", quest_id);
         }
     }
 
@@ -384,125 +1225,360 @@ impl Repl {
     }
 
     fn suggest_quests_for_coins(&self, error_message: &str) {
-        println!("\n💡 Hint: You need more coins!");
+        say!(self, "\n💡 Hint: You need more coins!");
         
         let available_quests = self.quest_manager.get_available_quests();
         
         if error_message.contains("Variable") {
-            println!("To earn Variable coins, try these available quests:");
+            say!(self, "To earn Variable coins, try these available quests:");
             for quest in available_quests.iter().take(2) {
                 if quest.rewards.iter().any(|r| matches!(r.coin_type, crate::CoinType::Variable)) {
-                    println!("  📋 {} - {}", quest.title, quest.description);
+                    say!(self, "  📋 {} - {}", quest.title, quest.description);
                 }
             }
         } else if error_message.contains("Function") {
-            println!("To earn Function coins, try these available quests:");
+            say!(self, "To earn Function coins, try these available quests:");
             for quest in available_quests.iter().take(2) {
                 if quest.rewards.iter().any(|r| matches!(r.coin_type, crate::CoinType::Function)) {
-                    println!("  📋 {} - {}", quest.title, quest.description);
+                    say!(self, "  📋 {} - {}", quest.title, quest.description);
                 }
             }
         }
         
         if available_quests.is_empty() {
-            println!("Complete some basic quests first to unlock more opportunities!");
+            say!(self, "Complete some basic quests first to unlock more opportunities!");
+        }
+    }
+
+    // every event goes to both the current run and the long lived history
+    fn record_event(&mut self, event: ExecutionEvent) {
+        self.last_run.record_event(event.clone());
+        self.execution_context.record_event(event);
+    }
+
+    pub fn quest_progress(&self, quest_id: &str) -> Option<QuestProgress> {
+        self.quest_manager
+            .get_run_progress(quest_id, &self.last_run, &self.execution_context)
+    }
+
+    // completed quests and coins are kept
+    pub fn clear_history(&mut self) {
+        self.execution_context.reset();
+        self.last_run.reset();
+    }
+
+    fn reset_progress(&mut self) {
+        self.clear_history();
+        say!(self, "🧹 Quest progress history cleared. Completed quests and coins are kept.");
+    }
+
+    // hidden tests for challenge quests; after a run only the functions it
+    // (re)defined are tested so unrelated input doesn't rerun everything
+    fn run_challenges(&mut self, quest_id: Option<&str>, only: Option<&[String]>) -> Vec<(String, ChallengeReport)> {
+        let challenges: Vec<(String, String, Vec<TestCase>)> = self
+            .quest_manager
+            .get_accepted_quests()
+            .into_iter()
+            .filter(|quest| quest_id.is_none_or(|id| quest.id == id))
+            .flat_map(|quest| {
+                quest.objectives.iter().flat_map(|objective| objective.challenges()).map(|(function, cases)| {
+                    (quest.title.clone(), function.to_string(), cases.to_vec())
+                })
+            })
+            .filter(|(_, function, _)| only.is_none_or(|names| names.contains(function)))
+            .collect();
+
+        let mut reports = Vec::new();
+        for (title, function, cases) in challenges {
+            if quest_id.is_none() && !matches!(self.env.get(&function), Some(Expr::FnDef(..))) {
+                continue;
+            }
+            let report = run_challenge(&function, &cases, &self.env, self.validator.natives());
+            self.record_event(ExecutionEvent::ChallengeRun {
+                function,
+                cases,
+                passed: report.passed(),
+            });
+            reports.push((title, report));
+        }
+        reports
+    }
+
+    fn display_challenge_report(&self, title: &str, report: &ChallengeReport) {
+        say!(self, 
+            "🧪 Challenge '{}' ({}): {}/{} hidden cases passed",
+            report.function,
+            title,
+            report.passed(),
+            report.results.len()
+        );
+        for (i, result) in report.results.iter().enumerate() {
+            match &result.actual {
+                _ if result.passed => say!(self, "   ✅ case {}", i + 1),
+                Ok(value) => say!(self, "   ❌ case {} (got {})", i + 1, value),
+                Err(e) => say!(self, "   ❌ case {} ({})", i + 1, e),
+            }
+        }
+    }
+
+    fn run_challenge_command(&mut self, quest_id: Option<&str>) {
+        if let Some(id) = quest_id
+            && !self.quest_manager.get_accepted_quests().iter().any(|q| q.id == id)
+        {
+            say!(self, "You haven't accepted a quest '{}'. Try 'quest accept {}' first.", id, id);
+            return;
+        }
+
+        let reports = self.run_challenges(quest_id, None);
+        if reports.is_empty() {
+            say!(self, "No challenges to run. Define the function a challenge quest asks for first.");
+            return;
+        }
+        for (title, report) in &reports {
+            self.display_challenge_report(title, report);
+        }
+
+        let (completed, rewards) = self.complete_quests();
+        if !completed.is_empty() {
+            self.display_quest_completion_notification(&rewards);
+            self.save_state();
         }
     }
 
     fn show_available_quests(&self) {
-        println!("\n🎯 Available Quests:");
+        say!(self, "\n🎯 Available Quests:");
         let available_quests = self.quest_manager.get_available_quests();
         
         if available_quests.is_empty() {
-            println!("No quests currently available. Complete existing quests to unlock more!");
+            say!(self, "No quests currently available. Complete existing quests to unlock more!");
             return;
         }
 
         for quest in available_quests {
-            println!("\n📋 {} [{}]", quest.title, quest.difficulty.description());
-            println!("   {}", quest.description);
+            let accepted = if quest.is_accepted() { " ✔ accepted" } else { "" };
+            say!(self, "\n📋 {} [{}] ({}){}", quest.title, quest.difficulty.description(), quest.id, accepted);
+            say!(self, "   {}", quest.description);
+            if let Some(timing) = self.quest_timing(quest) {
+                say!(self, "   {}", timing);
+            }
             
             
-            if let Some(progress) = self.quest_manager.get_quest_progress(&quest.id, &self.execution_context) {
+            if let Some(progress) = self.quest_progress(&quest.id) {
                 let percentage = progress.completion_percentage();
                 let progress_bar = self.create_progress_bar(percentage);
-                println!("   Progress: {} {:.0}%", progress_bar, percentage);
+                say!(self, "   Progress: {} {:.0}%", progress_bar, percentage);
             }
             
-            println!("   Objectives:");
-            for (i, objective) in quest.objectives.iter().enumerate() {
-                let status = if let Some(progress) = self.quest_manager.get_quest_progress(&quest.id, &self.execution_context) {
-                    if *progress.completed_objectives.get(i).unwrap_or(&false) {
-                        "✅"
-                    } else {
-                        "⭕"
-                    }
-                } else {
-                    "⭕"
-                };
-                println!("     {} {}", status, objective.description());
+            say!(self, "   Objectives:");
+            if let Some(progress) = self.quest_progress(&quest.id) {
+                for objective in &progress.objectives {
+                    self.print_objective_tree(objective, 5);
+                }
             }
             
-            println!("   Rewards:");
+            say!(self, "   Rewards:");
             for reward in &quest.rewards {
-                println!("     💰 {} {:?} coins", reward.amount, reward.coin_type);
+                say!(self, "     💰 {} {:?} coins", reward.amount, reward.coin_type);
             }
         }
     }
 
     fn show_completed_quests(&self) {
-        println!("\n🏆 Completed Quests:");
+        say!(self, "\n🏆 Completed Quests:");
         let completed_quests = self.quest_manager.get_completed_quests();
         
         if completed_quests.is_empty() {
-            println!("No quests completed yet. Start with some basic arithmetic to begin your journey!");
+            say!(self, "No quests completed yet. Start with some basic arithmetic to begin your journey!");
             return;
         }
 
         for quest in completed_quests {
-            println!("✅ {} [{}] - {}", quest.title, quest.difficulty.description(), quest.description);
+            say!(self, "✅ {} [{}] - {}", quest.title, quest.difficulty.description(), quest.description);
             let total_rewards: u32 = quest.rewards.iter().map(|r| r.amount).sum();
-            println!("   Earned {} total coins", total_rewards);
+            say!(self, "   Earned {} total coins", total_rewards);
         }
         
-        println!("\nTotal completed: {}", completed_quests.len());
+        say!(self, "\nTotal completed: {}", completed_quests.len());
     }
 
     fn show_detailed_quest_progress(&self) {
-        println!("\n📈 Detailed Quest Progress:");
-        let available_quests = self.quest_manager.get_available_quests();
+        say!(self, "\n📈 Detailed Quest Progress:");
+        let available_quests = self.quest_manager.get_accepted_quests();
         
         if available_quests.is_empty() {
-            println!("No active quests to track progress for.");
+            say!(self, "No accepted quests. Use 'available' to find one and 'quest accept <id>' to take it on.");
             return;
         }
 
         for quest in available_quests {
-            println!("\n📋 {} [{}]", quest.title, quest.difficulty.description());
+            say!(self, "\n📋 {} [{}]", quest.title, quest.difficulty.description());
             
-            if let Some(progress) = self.quest_manager.get_quest_progress(&quest.id, &self.execution_context) {
+            if let Some(progress) = self.quest_progress(&quest.id) {
                 let percentage = progress.completion_percentage();
                 let progress_bar = self.create_progress_bar(percentage);
-                println!("   Overall Progress: {} {:.0}%", progress_bar, percentage);
+                say!(self, "   Overall Progress: {} {:.0}%", progress_bar, percentage);
                 
-                println!("   Objective Status:");
-                for (i, objective) in quest.objectives.iter().enumerate() {
-                    let status = if *progress.completed_objectives.get(i).unwrap_or(&false) {
-                        "✅ COMPLETED"
-                    } else {
-                        "⭕ PENDING"
-                    };
-                    println!("     {} {}", status, objective.description());
+                say!(self, "   Objective Status:");
+                for objective in &progress.objectives {
+                    self.print_objective_tree(objective, 5);
                 }
                 
                 if percentage == 100.0 {
-                    println!("   🎉 Ready to claim rewards! Execute any code to complete this quest.");
+                    say!(self, "   🎉 Ready to claim rewards! Execute any code to complete this quest.");
                 } else if percentage > 0.0 {
-                    println!("   💪 Keep going! You're making progress.");
+                    say!(self, "   💪 Keep going! You're making progress.");
                 } else {
-                    println!("   🚀 Ready to start! Try the suggested actions above.");
+                    say!(self, "   🚀 Ready to start! Try the suggested actions above.");
+                }
+
+                for (i, hint) in quest.revealed_hints().iter().enumerate() {
+                    say!(self, "   💡 Hint {}: {}", i + 1, hint.text);
+                }
+                if let Some(cost) = self.quest_manager.next_hint_cost(&quest.id) {
+                    let remaining = quest.hints.len() - quest.hints_revealed;
+                    say!(self, 
+                        "   💡 {} more hint(s): 'hint {}' for {} {:?} coin(s)",
+                        remaining, quest.id, cost.amt, cost.coin_type
+                    );
+                    if quest.hints[quest.hints_revealed].solution {
+                        say!(self, "      The next one is the worked solution and halves this quest's rewards.");
+                    }
                 }
             }
         }
     }
+
+    // nested objectives are indented under their parent, counters only where
+    // there's more than one step to take
+    fn print_objective_tree(&self, node: &ObjectiveProgress, indent: usize) {
+        let status = if node.complete { "✅" } else { "⭕" };
+        if node.target > 1 && node.children.is_empty() {
+            say!(self, "{:indent$}{} {} ({}/{})", "", status, node.description, node.current, node.target);
+        } else {
+            say!(self, "{:indent$}{} {}", "", status, node.description);
+        }
+        for child in &node.children {
+            self.print_objective_tree(child, indent + 3);
+        }
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferOutput, NullOutput, ScriptedInput};
+
+    fn session(input: &str) -> String {
+        let buffer = BufferOutput::new();
+        let mut repl = Repl::new()
+            .with_output(Box::new(buffer.clone()))
+            .with_input(Box::new(ScriptedInput::new(input.lines())));
+        repl.run();
+        buffer.contents()
+    }
+
+    #[test]
+    fn test_session_renders_through_output() {
+        let output = session("print(\"hi\")\n2 + 3\nquest accept first_variable\nx\nquit\n");
+
+        assert!(output.contains("[Hello World 0%] CAng> hi\n"));
+        assert!(output.contains("Result: 5\n"));
+        assert!(output.contains("🎉 QUEST COMPLETED! 🎉"));
+        assert!(output.contains("Error: Runtime err: Undefined variable 'x'"));
+        assert!(output.trim_end().ends_with("Goodbye!"));
+    }
+
+    #[test]
+    fn test_commands_without_a_terminal() {
+        let output = session("balance\nquestmap mermaid\nbuiltins\nlet = 1\n");
+        assert!(output.contains("flowchart LR\n"));
+        assert!(output.contains("  pow    - 2 arguments, 1 Function\n"));
+        assert!(output.contains("Parse Error:"));
+
+        // running out of input ends the session like quit does
+        let mut repl = Repl::new().with_output(Box::new(NullOutput));
+        repl.run_with("let a = 1\n".as_bytes());
+        assert_eq!(repl.env.get("a"), Some(&Expr::Number(1)));
+    }
+
+    #[test]
+    fn test_programs_read_from_the_session_input() {
+        let output = session("print(\"go\"); let n = input_int(\"n? \")\n41\nn + 1\ninput_int()\nabc\n");
+
+        // the prompt follows what was printed before it, and the answer isn't
+        // taken as a command
+        assert!(output.contains("CAng> go\nn? Result: 41\n"));
+        assert!(output.contains("Result: 42\n"));
+        assert!(output.contains("Error: Runtime err: input_int: expected a whole number, got 'abc'"));
+
+        let err = Repl::new().with_output(Box::new(NullOutput)).run_source("input_int(1, 2)").error.unwrap();
+        assert_eq!(err.message, "Runtime err: Function 'input_int' expects at most 1 argument, got 2");
+    }
+
+    #[test]
+    fn test_lint_before_running() {
+        let output = session("let total = 2\nfn f(total) { total }\nlint on\nfn g(total) { total }\nlint off\nfn h(total) { total }\n");
+        assert!(output.contains("Linting is on"));
+        assert_eq!(output.matches("warning[shadowing]: Parameter 'total' of").count(), 1);
+        assert!(output.contains("of 'g' hides the global 'total'"));
+    }
+
+    #[test]
+    fn test_debugging_from_the_session() {
+        let output = session("locals\nbreak 2\nbreakpoints\ndelete 2\ndelete 2\n");
+        assert!(output.contains("Nothing is being debugged. Start with debug <program>"));
+        assert!(output.contains("Breakpoint set at line 2"));
+        assert!(output.contains("Breakpoints at line 2"));
+        assert!(output.contains("Breakpoint at line 2 removed"));
+        assert!(output.contains("There's no breakpoint at line 2"));
+
+        // the debugger's commands come from the session input
+        let output = session("debug let a = 1; let b = a + 1; b\nnext\nlocals\ncontinue\n");
+        assert!(output.contains("⏸️  Paused at line 1: let a = 1\n(debug) ⏸️  Paused at line 1: let b = a + 1\n"));
+        assert!(output.contains("(debug)   a = 1\n"));
+        assert!(output.contains("Program finished\nResult: 2\n"));
+    }
+
+    #[test]
+    fn test_only_finished_runs_count_for_quests() {
+        let mut repl = Repl::new().with_output(Box::new(NullOutput));
+        assert!(repl.run_source("let a = 1; missing").error.is_some());
+        repl.validator = ResourceValidator::new(CoinManager::with_balances(0, 0));
+        assert!(repl.run_source("let b = 1").error.is_some());
+
+        assert_eq!(repl.execution_context.programs_run(), 0);
+        assert!(repl.execution_context.variables.is_empty());
+
+        repl.run_source("1 + 1");
+        assert_eq!(repl.execution_context.programs_run(), 1);
+    }
+
+    #[test]
+    fn test_achievements_count_finished_runs_and_runtime_errors() {
+        let mut repl = Repl::new().with_output(Box::new(NullOutput));
+        repl.run_source("missing");
+        repl.validator = ResourceValidator::new(CoinManager::with_balances(0, 0));
+        repl.run_source("let b = 1");
+
+        let stats = repl.achievements.stats();
+        assert_eq!((stats.programs_run, stats.runtime_errors), (0, 1));
+
+        repl.run_source("1 + 1");
+        let stats = repl.achievements.stats();
+        assert_eq!((stats.programs_run, stats.recoveries), (1, 1));
+    }
 }
@@ -1,5 +1,12 @@
 // purpose of this is to analyze the ast and check if user can run
-use crate::{CoinError, CoinManager, CoinType, Expr};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    modules::{ModuleError, ModuleLoader},
+    natives::NativeRegistry,
+    parser::{Statement, INPUT_FUNCTIONS},
+    CoinError, CoinManager, CoinType, Expr,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CoinCost {
@@ -7,6 +14,40 @@ pub struct CoinCost {
     pub amt: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeteringMode {
+    Static,  // charge everything up front from the ast
+    Runtime, // charge each construct when it actually runs
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementCost {
+    pub pos: (usize, usize),
+    pub costs: Vec<CoinCost>,
+}
+
+// dry run result, nothing gets spent
+#[derive(Debug, Clone)]
+pub struct CostEstimate {
+    pub total: Vec<CoinCost>,
+    pub statements: Vec<StatementCost>,
+    pub balance_after: HashMap<CoinType, i64>,
+    // set when a function needs more params than the shop allows, the
+    // program would be rejected even with enough coins
+    pub limit_error: Option<CoinError>,
+    pub affordable: bool,
+}
+
+impl CostEstimate {
+    pub fn total_for(&self, coin_type: CoinType) -> u32 {
+        self.total
+            .iter()
+            .filter(|c| c.coin_type == coin_type)
+            .map(|c| c.amt)
+            .sum()
+    }
+}
+
 #[derive(Debug)]
 pub enum ValidationError {
     CoinError(CoinError),
@@ -34,86 +75,180 @@ impl std::error::Error for ValidationError {}
 
 pub struct ResourceValidator {
     coin_manager: CoinManager,
+    natives: NativeRegistry, // kept here since priced natives add to a program's cost
+    modules: ModuleLoader,   // and imported modules are paid for on first load
 }
 
 impl ResourceValidator {
     pub fn new(coin_manager: CoinManager) -> Self {
-        Self { coin_manager }
+        Self {
+            coin_manager,
+            natives: NativeRegistry::new(),
+            modules: ModuleLoader::new(),
+        }
+    }
+
+    pub fn with_natives(mut self, natives: NativeRegistry) -> Self {
+        self.natives = natives;
+        self
     }
 
     pub fn validate_expression(&self, expr: &Expr) -> Result<Vec<CoinCost>, ValidationError> {
+        self.check_limits(expr)?;
+
         let costs = self.calculate_costs(expr);
 
-        for cost in &costs {
-            let available = self.coin_manager.get_balance(cost.coin_type);
-            if available < cost.amt {
-                return Err(ValidationError::CoinError(CoinError::InsufficientFunds {
-                    required: cost.amt,
-                    available,
-                    coin_type: cost.coin_type,
-                }));
-            }
+        for cost in &self.merge_costs(costs.clone()) {
+            self.coin_manager.check_affordable(cost.coin_type, cost.amt)?;
         }
 
         Ok(costs)
     }
 
-    pub fn calculate_costs(&self, expr: &Expr) -> Vec<CoinCost> {
+    // everything that rules a statement out before it's charged for
+    pub fn check_limits(&self, expr: &Expr) -> Result<(), ValidationError> {
+        if let Some(name) = self.builtin_definition(expr) {
+            return Err(redefined_builtin(name));
+        }
+        self.coin_manager.check_params(Self::max_params(expr))?;
+        Ok(())
+    }
+
+    // the first fn definition that would replace a native or input function
+    fn builtin_definition<'a>(&self, expr: &'a Expr) -> Option<&'a str> {
         match expr {
-            Expr::Number(_) | Expr::Var(_) | Expr::String(_) => vec![],
-            Expr::FnDef(_, _, body) => {
-                let mut costs = vec![CoinCost {
-                    coin_type: CoinType::Function,
-                    amt: 1,
-                }];
-                costs.extend(self.calculate_costs(body));
-                costs
-            }
-            Expr::Binary(lhs, _, rhs) => {
-                let mut costs = vec![];
-                costs.extend(self.calculate_costs(lhs));
-                costs.extend(self.calculate_costs(rhs));
-                costs
-            }
-            Expr::Let(_, val) => {
-                let mut costs = vec![CoinCost {
-                    coin_type: CoinType::Variable,
-                    amt: 1,
-                }];
-                costs.extend(self.calculate_costs(val));
-                costs
-            }
-            Expr::FnCall(_, args) => {
-                let mut costs = vec![];
-                for arg in args {
-                    costs.extend(self.calculate_costs(arg));
+            Expr::FnDef(name, _, body) => {
+                if INPUT_FUNCTIONS.contains(&name.as_str()) || self.natives.contains(name) {
+                    Some(name)
+                } else {
+                    self.builtin_definition(body)
                 }
-                costs
             }
-            Expr::Block(statements) => {
-                let mut costs = vec![];
-                for stmt in statements {
-                    costs.extend(self.calculate_costs(stmt));
+            Expr::Binary(lhs, _, rhs) => self.builtin_definition(lhs).or_else(|| self.builtin_definition(rhs)),
+            Expr::Let(_, val) | Expr::Print(val) => self.builtin_definition(val),
+            Expr::FnCall(_, args) => args.iter().find_map(|arg| self.builtin_definition(arg)),
+            Expr::Block(statements) => statements.iter().find_map(|stmt| self.builtin_definition(stmt)),
+            Expr::Number(_) | Expr::Var(_) | Expr::String(_) | Expr::Import(..) => None,
+        }
+    }
+
+    // what running this one node costs, not counting its children
+    pub fn construct_cost(expr: &Expr) -> Vec<CoinCost> {
+        match expr {
+            Expr::FnDef(_, _, _) => vec![CoinCost {
+                coin_type: CoinType::Function,
+                amt: 1,
+            }],
+            Expr::Let(_, _) => vec![CoinCost {
+                coin_type: CoinType::Variable,
+                amt: 1,
+            }],
+            _ => vec![],
+        }
+    }
+
+    pub fn calculate_costs(&self, expr: &Expr) -> Vec<CoinCost> {
+        program_costs(expr, &self.natives)
+    }
+
+    pub(crate) fn max_params(expr: &Expr) -> usize {
+        match expr {
+            Expr::FnDef(_, params, body) => params.len().max(Self::max_params(body)),
+            Expr::Binary(lhs, _, rhs) => Self::max_params(lhs).max(Self::max_params(rhs)),
+            Expr::Let(_, val) | Expr::Print(val) => Self::max_params(val),
+            Expr::FnCall(_, args) => args.iter().map(Self::max_params).max().unwrap_or(0),
+            Expr::Block(statements) => statements.iter().map(Self::max_params).max().unwrap_or(0),
+            Expr::Number(_) | Expr::Var(_) | Expr::String(_) | Expr::Import(..) => 0,
+        }
+    }
+
+    // imports are priced at what loading them would cost, so modules that
+    // are already loaded are free
+    pub fn estimate(&self, stmts: &[Statement]) -> CostEstimate {
+        let mut seen = HashSet::new();
+        let statements: Vec<StatementCost> = stmts
+            .iter()
+            .map(|(stmt, pos)| {
+                let mut costs = self.calculate_costs(stmt);
+                if let Expr::Import(spec, _) = stmt {
+                    costs.extend(self.modules.import_cost(spec, &self.natives, &mut seen));
                 }
-                costs
-            }
-            Expr::Print(expr) => {
-                self.calculate_costs(expr)
-            }
+                StatementCost {
+                    pos: *pos,
+                    costs: self.merge_costs(costs),
+                }
+            })
+            .collect();
+
+        let total = self.merge_costs(
+            statements
+                .iter()
+                .flat_map(|s| s.costs.iter().cloned())
+                .collect(),
+        );
+
+        let balance_after: HashMap<CoinType, i64> = CoinType::ALL
+            .iter()
+            .map(|&coin_type| {
+                let spent: u32 = total
+                    .iter()
+                    .filter(|c| c.coin_type == coin_type)
+                    .map(|c| c.amt)
+                    .sum();
+                let balance = self.coin_manager.get_balance(coin_type);
+                (coin_type, balance - spent as i64)
+            })
+            .collect();
+
+        let limit_error = stmts.iter().find_map(|(stmt, _)| match self.check_limits(stmt) {
+            Err(ValidationError::CoinError(e)) => Some(e),
+            _ => None,
+        });
+
+        let affordable = limit_error.is_none()
+            && balance_after
+                .iter()
+                .all(|(&coin_type, &b)| b >= -(self.coin_manager.credit_limit(coin_type) as i64));
+
+        CostEstimate {
+            total,
+            statements,
+            balance_after,
+            limit_error,
+            affordable,
         }
     }
 
-    pub fn merge_costs(&self, costs: Vec<CoinCost>) -> Vec<CoinCost> {
-        use std::collections::HashMap;
+    // static runs are all or nothing: the program and the modules it still
+    // has to load must be affordable together before anything is spent.
+    // Errors come with the statement at fault, if there is one
+    pub fn check_program(&self, statements: &[Statement]) -> Result<(), (Option<usize>, ValidationError)> {
+        for (i, (stmt, _)) in statements.iter().enumerate() {
+            self.check_limits(stmt).map_err(|e| (Some(i), e))?;
+        }
+        let estimate = self.estimate(statements);
+        for cost in &estimate.total {
+            self.coin_manager
+                .check_affordable(cost.coin_type, cost.amt)
+                .map_err(|e| (None, e.into()))?;
+        }
+        Ok(())
+    }
 
+    // one cost per coin type, in CoinType::ALL order
+    pub fn merge_costs(&self, costs: Vec<CoinCost>) -> Vec<CoinCost> {
         let mut merged: HashMap<CoinType, u32> = HashMap::new();
         for cost in costs {
             *merged.entry(cost.coin_type).or_insert(0) += cost.amt;
         }
 
-        merged
-            .into_iter()
-            .map(|(coin_type, amt)| CoinCost { coin_type, amt })
+        CoinType::ALL
+            .iter()
+            .filter_map(|&coin_type| {
+                merged
+                    .get(&coin_type)
+                    .map(|&amt| CoinCost { coin_type, amt })
+            })
             .collect()
     }
 
@@ -124,4 +259,166 @@ impl ResourceValidator {
     pub fn coin_manager_mut(&mut self) -> &mut CoinManager {
         &mut self.coin_manager
     }
+
+    pub fn natives(&self) -> &NativeRegistry {
+        &self.natives
+    }
+
+    pub fn natives_mut(&mut self) -> &mut NativeRegistry {
+        &mut self.natives
+    }
+
+    pub fn modules(&self) -> &ModuleLoader {
+        &self.modules
+    }
+
+    pub fn modules_mut(&mut self) -> &mut ModuleLoader {
+        &mut self.modules
+    }
+
+    // loads what the program imports, charging for modules not loaded before.
+    // Fails with the index of the import that couldn't be loaded
+    pub fn link(&mut self, statements: &[Statement]) -> Result<(), (usize, ModuleError)> {
+        self.modules.link(statements, &mut self.coin_manager, &self.natives)
+    }
+
+    // for metering a run, which spends coins while calling natives
+    pub fn parts_mut(&mut self) -> (&mut CoinManager, &NativeRegistry, &ModuleLoader) {
+        (&mut self.coin_manager, &self.natives, &self.modules)
+    }
+}
+
+// e.g. "1 Variable coin and 2 Function coins"
+pub fn describe_costs(costs: &[CoinCost]) -> String {
+    if costs.is_empty() {
+        return "no coins".to_string();
+    }
+    costs
+        .iter()
+        .map(|cost| format!("{} {:?} coin{}", cost.amt, cost.coin_type, if cost.amt == 1 { "" } else { "s" }))
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+pub(crate) fn redefined_builtin(name: &str) -> ValidationError {
+    ValidationError::RuntimeError(format!("'{}' is a built-in function and can't be redefined", name))
+}
+
+// everything a program costs when priced up front, natives included
+pub fn program_costs(expr: &Expr, natives: &NativeRegistry) -> Vec<CoinCost> {
+    let mut costs = ResourceValidator::construct_cost(expr);
+    match expr {
+        // imported modules are paid for when they're first loaded
+        Expr::Number(_) | Expr::Var(_) | Expr::String(_) | Expr::Import(..) => {}
+        Expr::FnDef(_, _, body) => {
+            costs.extend(program_costs(body, natives));
+        }
+        Expr::Binary(lhs, _, rhs) => {
+            costs.extend(program_costs(lhs, natives));
+            costs.extend(program_costs(rhs, natives));
+        }
+        Expr::Let(_, val) => {
+            costs.extend(program_costs(val, natives));
+        }
+        Expr::FnCall(name, args) => {
+            if let Some(native) = natives.get(name) {
+                costs.extend(native.cost.iter().cloned());
+            }
+            for arg in args {
+                costs.extend(program_costs(arg, natives));
+            }
+        }
+        Expr::Block(statements) => {
+            for stmt in statements {
+                costs.extend(program_costs(stmt, natives));
+            }
+        }
+        Expr::Print(expr) => {
+            costs.extend(program_costs(expr, natives));
+        }
+    }
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{coin_manager::Upgrade, parser::Parser, tokenize};
+
+    fn statements(source: &str) -> Vec<Statement> {
+        Parser::new(tokenize(source)).parse_statements().unwrap()
+    }
+
+    #[test]
+    fn test_estimate_does_not_charge() {
+        let validator = ResourceValidator::new(CoinManager::with_balances(1, 1));
+        let estimate = validator.estimate(&statements("let x = 1;\nfn f(a) { a };\nf(x)"));
+
+        assert_eq!(estimate.total_for(CoinType::Variable), 1);
+        assert_eq!(estimate.total_for(CoinType::Function), 1);
+        assert_eq!(estimate.statements.len(), 3);
+        assert_eq!(estimate.statements[1].pos.0, 2);
+        assert!(estimate.statements[2].costs.is_empty());
+        assert_eq!(estimate.balance_after[&CoinType::Variable], 0);
+        assert!(estimate.affordable);
+
+        assert_eq!(validator.coin_manager().get_balance(CoinType::Variable), 1);
+        assert_eq!(validator.coin_manager().get_balance(CoinType::Function), 1);
+    }
+
+    #[test]
+    fn test_statement_costs_are_ordered() {
+        let validator = ResourceValidator::new(CoinManager::with_balances(5, 5))
+            .with_natives(NativeRegistry::with_stdlib());
+        let estimate = validator.estimate(&statements("let x = pow(2, 2)"));
+        let order: Vec<CoinType> = estimate.statements[0].costs.iter().map(|c| c.coin_type).collect();
+
+        assert_eq!(order, CoinType::ALL.to_vec());
+    }
+
+    #[test]
+    fn test_priced_natives_are_charged() {
+        let natives = NativeRegistry::with_stdlib();
+        let validator = ResourceValidator::new(CoinManager::with_balances(0, 1)).with_natives(natives);
+        let estimate = validator.estimate(&statements("abs(pow(2, pow(2, 2)))"));
+
+        assert_eq!(estimate.total_for(CoinType::Function), 2);
+        assert_eq!(estimate.total_for(CoinType::Variable), 0);
+        assert!(!estimate.affordable);
+    }
+
+    #[test]
+    fn test_estimate_unaffordable() {
+        let validator = ResourceValidator::new(CoinManager::with_balances(1, 0));
+        let estimate = validator.estimate(&statements("let a = 1; let b = 2"));
+
+        assert_eq!(estimate.balance_after[&CoinType::Variable], -1);
+        assert!(!estimate.affordable);
+    }
+
+    #[test]
+    fn test_param_limit_is_opt_in() {
+        let mut validator = ResourceValidator::new(CoinManager::with_balances(10, 10));
+        let program = &statements("fn add4(a, b, c, d) { a + b + c + d }")[0].0;
+        assert!(validator.check_limits(program).is_ok());
+
+        validator.coin_manager_mut().set_param_limit(true);
+        assert!(validator.check_limits(program).is_err());
+        validator.coin_manager_mut().buy_upgrade(Upgrade::ExtraParam).unwrap();
+        assert!(validator.check_limits(program).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_checks_param_limit() {
+        let mut coin_manager = CoinManager::with_balances(10, 10);
+        coin_manager.set_param_limit(true);
+        let validator = ResourceValidator::new(coin_manager);
+        let estimate = validator.estimate(&statements("fn f(a, b, c, d) { a }"));
+
+        assert!(matches!(
+            estimate.limit_error,
+            Some(CoinError::ParamLimitExceeded { requested: 4, .. })
+        ));
+        assert!(!estimate.affordable);
+    }
 }
@@ -0,0 +1,85 @@
+// built-ins every session starts with
+use crate::{
+    natives::{Arity, Native, NativeRegistry},
+    CoinType,
+};
+
+pub fn register(registry: &mut NativeRegistry) {
+    registry.register("abs", Native::new(Arity::Exact(1), |args| {
+        args[0].checked_abs().ok_or_else(|| format!("the absolute value of {} is too large", args[0]))
+    }));
+    registry.register("sign", Native::new(Arity::Exact(1), |args| Ok(args[0].signum())));
+    // arity guarantees at least one argument
+    registry.register(
+        "min",
+        Native::new(Arity::AtLeast(1), |args| Ok(args[1..].iter().fold(args[0], |a, &b| a.min(b)))),
+    );
+    registry.register(
+        "max",
+        Native::new(Arity::AtLeast(1), |args| Ok(args[1..].iter().fold(args[0], |a, &b| a.max(b)))),
+    );
+    registry.register(
+        "clamp",
+        Native::new(Arity::Exact(3), |args| {
+            let (value, low, high) = (args[0], args[1], args[2]);
+            if low > high {
+                return Err(format!("lower bound {} is above upper bound {}", low, high));
+            }
+            Ok(value.clamp(low, high))
+        }),
+    );
+    registry.register("sqrt", Native::new(Arity::Exact(1), |args| match args[0] {
+        n if n < 0 => Err(format!("can't take the square root of {}", n)),
+        n => Ok(n.isqrt()),
+    }));
+    // saves writing out a loop the language can't express, so it isn't free
+    registry.register(
+        "pow",
+        Native::new(Arity::Exact(2), |args| {
+            let (base, exponent) = (args[0], args[1]);
+            let exponent = u32::try_from(exponent).map_err(|_| format!("exponent {} must be 0 or more", exponent))?;
+            base.checked_pow(exponent).ok_or_else(|| "result is too large".to_string())
+        })
+        .with_cost(CoinType::Function, 1),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[i64]) -> Result<i64, String> {
+        let registry = NativeRegistry::with_stdlib();
+        let native = registry.get(name).unwrap();
+        assert!(native.arity.accepts(args.len()));
+        native.call(args)
+    }
+
+    #[test]
+    fn test_stdlib_functions() {
+        assert_eq!(call("abs", &[-4]), Ok(4));
+        assert!(call("abs", &[i64::MIN]).is_err());
+        assert_eq!(call("sign", &[-4]), Ok(-1));
+        assert_eq!(call("min", &[3, -1, 2]), Ok(-1));
+        assert_eq!(call("max", &[3]), Ok(3));
+        assert_eq!(call("max", &[3, 7, -2]), Ok(7));
+        assert_eq!(call("clamp", &[15, 0, 10]), Ok(10));
+        assert!(call("clamp", &[1, 5, 0]).is_err());
+        assert_eq!(call("sqrt", &[17]), Ok(4));
+        assert!(call("sqrt", &[-1]).is_err());
+        assert_eq!(call("pow", &[2, 10]), Ok(1024));
+        assert!(call("pow", &[2, -1]).is_err());
+        assert!(call("pow", &[10, 40]).is_err());
+    }
+
+    #[test]
+    fn test_arity_and_costs() {
+        let registry = NativeRegistry::with_stdlib();
+        assert!(!registry.get("min").unwrap().arity.accepts(0));
+        assert_eq!(registry.get("clamp").unwrap().arity.description(), "3 arguments");
+        assert_eq!(registry.get("max").unwrap().arity.description(), "at least 1 argument");
+        assert!(registry.get("abs").unwrap().cost.is_empty());
+        assert_eq!(registry.get("pow").unwrap().cost[0].coin_type, CoinType::Function);
+        assert_eq!(registry.names()[0], "abs");
+    }
+}